@@ -1,6 +1,6 @@
 extern crate hero_wfgen;
 
-use hero_wfgen::{DEFAULT_WAVETABLE_SIZE, gen_lut, sin, saw};
+use hero_wfgen::{DEFAULT_WAVETABLE_SIZE, gen_lut, sin, saw, square, triangle, pulse};
 
 const WT_SRC: &'static str = "src/wavetable";
 
@@ -10,4 +10,13 @@ fn main() {
 
     let mut saw_gen = saw::SawGen::new(DEFAULT_WAVETABLE_SIZE);
     gen_lut(WT_SRC, "saw", &mut saw_gen).unwrap();
+
+    let mut square_gen = square::SquareGen::new(DEFAULT_WAVETABLE_SIZE);
+    gen_lut(WT_SRC, "square", &mut square_gen).unwrap();
+
+    let mut triangle_gen = triangle::TriangleGen::new(DEFAULT_WAVETABLE_SIZE);
+    gen_lut(WT_SRC, "triangle", &mut triangle_gen).unwrap();
+
+    let mut pulse_gen = pulse::PulseGen::new(DEFAULT_WAVETABLE_SIZE, 0.25);
+    gen_lut(WT_SRC, "pulse", &mut pulse_gen).unwrap();
 }
@@ -0,0 +1,155 @@
+//!
+//! Virtual analog waveforms calculated analytically.
+//! Discontinuities are smoothed with PolyBLEP, and the corners of the triangle with PolyBLAMP.
+//!
+
+/// Limits for the pulse width, so the two edges never get too close
+const MIN_PULSE_WIDTH: f64 = 0.01;
+const MAX_PULSE_WIDTH: f64 = 0.99;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Saw = 0,
+    Square,
+    Triangle,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name {
+            "saw" => Some(Waveform::Saw),
+            "square" | "pulse" => Some(Waveform::Square),
+            "triangle" | "tri" => Some(Waveform::Triangle),
+            _ => None
+        }
+    }
+}
+
+/// Polynomial residual of a band limited step of height 2 at phase 0.
+/// `t` is the phase in [0, 1) and `dt` the phase increment per sample.
+pub fn poly_blep(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let x = t / dt;
+        x + x - x * x - 1.0
+    }
+    else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + x + x + 1.0
+    }
+    else {
+        0.0
+    }
+}
+
+/// Polynomial residual of a band limited ramp with a change of slope of two per sample at phase 0.
+/// `t` is the phase in [0, 1) and `dt` the phase increment per sample.
+pub fn poly_blamp(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let x = t / dt - 1.0;
+        -x * x * x / 3.0
+    }
+    else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt + 1.0;
+        x * x * x / 3.0
+    }
+    else {
+        0.0
+    }
+}
+
+fn wrap(t: f64) -> f64 {
+    t - t.floor()
+}
+
+/// Value of a waveform at a phase in [0, 1) for a phase increment per sample.
+///
+/// The saw rises from -1 to 1, the square is high for the first `pulse_width` of the cycle,
+/// and the triangle starts at 0 rising and reaches 1 after `pulse_width / 2` of the cycle,
+/// so all of them are aligned with the stock wavetables for a pulse width of 0.5.
+pub fn value(waveform: Waveform, t: f64, dt: f64, pulse_width: f64) -> f64 {
+    let dt = dt.abs().min(0.5);
+    match waveform {
+        Waveform::Saw => {
+            2.0 * t - 1.0 - poly_blep(t, dt)
+        },
+        Waveform::Square => {
+            let pw = pulse_width.max(MIN_PULSE_WIDTH).min(MAX_PULSE_WIDTH);
+            let naive = if t < pw { 1.0 } else { -1.0 };
+            naive + poly_blep(t, dt) - poly_blep(wrap(t - pw), dt)
+        },
+        Waveform::Triangle => {
+            let pw = pulse_width.max(MIN_PULSE_WIDTH).min(MAX_PULSE_WIDTH);
+            // Phase from the bottom corner
+            let t = wrap(t + 0.5 * pw);
+            let naive = if t < pw {
+                2.0 * t / pw - 1.0
+            }
+            else {
+                1.0 - 2.0 * (t - pw) / (1.0 - pw)
+            };
+            // Half of the change of slope per sample at the corners
+            let slope_change = dt / (pw * (1.0 - pw));
+            naive + slope_change * (poly_blamp(t, dt) - poly_blamp(wrap(t - pw), dt))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use complex::Complex;
+    use fft;
+
+    const SIZE: usize = 4096;
+    const CYCLES: usize = 373;
+
+    /// Fraction of the energy that is not in the bins of the harmonics of the fundamental
+    fn alias_energy<F>(f: F) -> f64 where F: Fn(f64, f64) -> f64 {
+        let dt = CYCLES as f64 / SIZE as f64;
+        let mut spectrum: Vec<Complex> = (0..SIZE)
+            .map(|i| Complex::new(f(wrap(i as f64 * dt), dt), 0.0))
+            .collect();
+        fft::fft(&mut spectrum);
+
+        let mut alias = 0.0;
+        let mut total = 0.0;
+        for (bin, value) in spectrum.iter().enumerate().skip(1).take(SIZE / 2 - 1) {
+            let energy = value.norm() * value.norm();
+            total += energy;
+            if bin % CYCLES != 0 {
+                alias += energy;
+            }
+        }
+        alias / total
+    }
+
+    #[test]
+    fn poly_blep_reduces_aliasing() {
+        let cases = [(Waveform::Saw, 0.5), (Waveform::Square, 0.5), (Waveform::Square, 0.2)];
+        for &(waveform, pw) in cases.iter() {
+            let naive = alias_energy(|t, _| value(waveform, t, 0.0, pw));
+            let blep = alias_energy(|t, dt| value(waveform, t, dt, pw));
+            assert!(blep < naive * 0.1, "{:?} {} {}", waveform, naive, blep);
+        }
+    }
+
+    #[test]
+    fn poly_blamp_reduces_aliasing() {
+        for &pw in [0.5, 0.3].iter() {
+            let naive = alias_energy(|t, _| value(Waveform::Triangle, t, 0.0, pw));
+            let blamp = alias_energy(|t, dt| value(Waveform::Triangle, t, dt, pw));
+            assert!(blamp < naive * 0.1, "{} {} {}", pw, naive, blamp);
+        }
+    }
+
+    #[test]
+    fn waveforms_aligned_with_stock() {
+        let dt = 1e-6;
+        assert!((value(Waveform::Saw, 0.25, dt, 0.5) + 0.5).abs() < 1e-9);
+        assert!((value(Waveform::Square, 0.25, dt, 0.5) - 1.0).abs() < 1e-9);
+        assert!((value(Waveform::Square, 0.75, dt, 0.5) + 1.0).abs() < 1e-9);
+        assert!(value(Waveform::Triangle, 0.0, dt, 0.5).abs() < 1e-5);
+        assert!((value(Waveform::Triangle, 0.25, dt, 0.5) - 1.0).abs() < 1e-5);
+        assert!((value(Waveform::Triangle, 0.75, dt, 0.5) + 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,86 @@
+//!
+//! Complex numbers
+//!
+
+use std::ops::{Add, Sub, Mul, Div};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Default for Complex {
+    fn default() -> Self {
+        Complex::zero()
+    }
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Complex {
+        Complex { re: re, im: im }
+    }
+
+    pub fn zero() -> Complex {
+        Complex { re: 0.0, im: 0.0 }
+    }
+
+    pub fn from_polar(norm: f64, arg: f64) -> Complex {
+        Complex { re: norm * arg.cos(), im: norm * arg.sin() }
+    }
+
+    pub fn norm(&self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    pub fn arg(&self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn conj(&self) -> Complex {
+        Complex { re: self.re, im: -self.im }
+    }
+
+    pub fn scale(&self, value: f64) -> Complex {
+        Complex { re: self.re * value, im: self.im * value }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex { re: self.re + other.re, im: self.im + other.im }
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex { re: self.re - other.re, im: self.im - other.im }
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re
+        }
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let den = other.re * other.re + other.im * other.im;
+        Complex {
+            re: (self.re * other.re + self.im * other.im) / den,
+            im: (self.im * other.re - self.re * other.im) / den
+        }
+    }
+}
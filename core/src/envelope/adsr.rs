@@ -0,0 +1,285 @@
+//!
+//! Attack, Decay, Sustain, Release envelope
+//!
+
+use envelope::{Curve, Envelope, Segment};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    Idle = 0,
+    Attack,
+    Decay,
+    Sustain,
+    Release
+}
+
+#[derive(Clone, Debug)]
+pub struct Adsr {
+    sample_rate: f64,
+    attack: f64,        // Attack time in seconds
+    decay: f64,         // Decay time in seconds
+    sustain: f64,       // Sustain level [0, 1]
+    release: f64,       // Release time in seconds
+    curve: Curve,
+
+    stage: Stage,
+    level: f64,
+    segment: Segment,
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Adsr {
+            sample_rate: 44100.0,
+            attack: 0.0,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.0,
+            curve: Curve::Linear,
+            stage: Stage::Idle,
+            level: 0.0,
+            segment: Segment::default(),
+        }
+    }
+}
+
+impl Adsr {
+    pub fn new(sample_rate: f64, attack: f64, decay: f64, sustain: f64, release: f64, curve: Curve) -> Adsr {
+        assert!(sample_rate > 0.0);
+
+        Adsr {
+            sample_rate: sample_rate,
+            attack: attack.max(0.0),
+            decay: decay.max(0.0),
+            sustain: sustain.max(0.0).min(1.0),
+            release: release.max(0.0),
+            curve: curve,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_sample_rate(sample_rate: f64) -> Adsr {
+        Adsr::new(sample_rate, 0.0, 0.0, 1.0, 0.0, Curve::Linear)
+    }
+
+    /// Changes the sample rate, the current stage keeps its time
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        assert!(sample_rate > 0.0);
+        self.segment.rescale(sample_rate / self.sample_rate);
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn set_attack(&mut self, attack: f64) {
+        self.attack = attack.max(0.0);
+    }
+
+    pub fn get_attack(&self) -> f64 {
+        self.attack
+    }
+
+    pub fn set_decay(&mut self, decay: f64) {
+        self.decay = decay.max(0.0);
+    }
+
+    pub fn get_decay(&self) -> f64 {
+        self.decay
+    }
+
+    pub fn set_sustain(&mut self, sustain: f64) {
+        self.sustain = sustain.max(0.0).min(1.0);
+    }
+
+    pub fn get_sustain(&self) -> f64 {
+        self.sustain
+    }
+
+    pub fn set_release(&mut self, release: f64) {
+        self.release = release.max(0.0);
+    }
+
+    pub fn get_release(&self) -> f64 {
+        self.release
+    }
+
+    pub fn set_curve(&mut self, curve: Curve) {
+        self.curve = curve;
+    }
+
+    pub fn get_curve(&self) -> Curve {
+        self.curve
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    pub fn level(&self) -> f64 {
+        self.level
+    }
+
+    fn next_stage(stage: Stage) -> Stage {
+        match stage {
+            Stage::Attack => Stage::Decay,
+            Stage::Decay => Stage::Sustain,
+            Stage::Release => Stage::Idle,
+            other => other
+        }
+    }
+
+    /// Starts a stage from the current level.
+    /// Stages with a length of zero samples are skipped immediately.
+    fn enter(&mut self, stage: Stage) {
+        self.stage = stage;
+        let (time, target) = match stage {
+            Stage::Attack => (self.attack, 1.0),
+            Stage::Decay => (self.decay, self.sustain),
+            Stage::Release => (self.release, 0.0),
+            Stage::Sustain => {
+                self.level = self.sustain;
+                return;
+            },
+            Stage::Idle => {
+                self.level = 0.0;
+                return;
+            }
+        };
+
+        let length = (time * self.sample_rate).round() as usize;
+        if length == 0 {
+            self.level = target;
+            self.enter(Self::next_stage(stage));
+        }
+        else {
+            self.segment = Segment::new(self.level, target, length, self.curve.curvature());
+        }
+    }
+}
+
+impl Envelope for Adsr {
+    fn reset(&mut self) {
+        self.stage = Stage::Idle;
+        self.level = 0.0;
+    }
+
+    fn note_on(&mut self) {
+        self.enter(Stage::Attack);
+    }
+
+    fn note_off(&mut self) {
+        if self.stage != Stage::Idle {
+            self.enter(Stage::Release);
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.stage != Stage::Idle
+    }
+
+    fn process(&mut self) -> f64 {
+        match self.stage {
+            Stage::Idle => 0.0,
+            Stage::Sustain => {
+                self.level = self.sustain;
+                self.level
+            },
+            stage => {
+                let value = self.segment.process();
+                if self.segment.is_finished() {
+                    self.level = self.segment.end;
+                    self.enter(Self::next_stage(stage));
+                }
+                else {
+                    self.level = value;
+                }
+                value
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use envelope::{Curve, Envelope};
+
+    #[test]
+    fn adsr_idle() {
+        let mut env = Adsr::new(10.0, 1.0, 1.0, 0.5, 1.0, Curve::Linear);
+        assert_eq!(env.is_active(), false);
+        assert_eq!(env.process(), 0.0);
+        env.note_off();
+        assert_eq!(env.is_active(), false);
+    }
+
+    #[test]
+    fn adsr_linear_stages() {
+        let mut env = Adsr::new(4.0, 1.0, 1.0, 0.5, 1.0, Curve::Linear);
+        env.note_on();
+        let attack: Vec<f64> = (0..4).map(|_| env.process()).collect();
+        assert_eq!(attack, vec![0.0, 0.25, 0.5, 0.75]);
+        assert_eq!(env.stage(), Stage::Decay);
+        let decay: Vec<f64> = (0..4).map(|_| env.process()).collect();
+        assert_eq!(decay, vec![1.0, 0.875, 0.75, 0.625]);
+        assert_eq!(env.process(), 0.5);
+        assert_eq!(env.stage(), Stage::Sustain);
+        assert_eq!(env.process(), 0.5);
+        env.note_off();
+        let release: Vec<f64> = (0..4).map(|_| env.process()).collect();
+        assert_eq!(release, vec![0.5, 0.375, 0.25, 0.125]);
+        assert_eq!(env.is_active(), false);
+        assert_eq!(env.process(), 0.0);
+    }
+
+    #[test]
+    fn adsr_zero_times() {
+        let mut env = Adsr::new(44100.0, 0.0, 0.0, 0.7, 0.0, Curve::Linear);
+        env.note_on();
+        assert_eq!(env.stage(), Stage::Sustain);
+        assert_eq!(env.process(), 0.7);
+        env.note_off();
+        assert_eq!(env.is_active(), false);
+    }
+
+    #[test]
+    fn adsr_sample_rate_change() {
+        let mut env = Adsr::new(4.0, 1.0, 1.0, 0.5, 1.0, Curve::Linear);
+        env.note_on();
+        env.process();
+        env.process();
+
+        // The rest of the attack takes the same time at twice the sample rate
+        env.set_sample_rate(8.0);
+        let attack: Vec<f64> = (0..4).map(|_| env.process()).collect();
+        assert_eq!(attack, vec![0.5, 0.625, 0.75, 0.875]);
+        assert_eq!(env.stage(), Stage::Decay);
+    }
+
+    #[test]
+    fn adsr_release_before_sustain() {
+        let mut env = Adsr::new(4.0, 1.0, 1.0, 0.5, 0.5, Curve::Linear);
+        env.note_on();
+        env.process();
+        env.process();
+        env.note_off();
+        assert_eq!(env.stage(), Stage::Release);
+        let release: Vec<f64> = (0..2).map(|_| env.process()).collect();
+        assert_eq!(release, vec![0.25, 0.125]);
+        assert_eq!(env.is_active(), false);
+    }
+
+    #[test]
+    fn adsr_exponential_reaches_targets() {
+        let mut env = Adsr::new(100.0, 0.1, 0.1, 0.25, 0.1, Curve::Exponential);
+        env.note_on();
+        let first = env.process();
+        let second = env.process();
+        assert_eq!(first, 0.0);
+        assert!(second > 0.1);
+        for _ in 0..20 { env.process(); }
+        assert_eq!(env.stage(), Stage::Sustain);
+        assert!((env.process() - 0.25).abs() < 1e-12);
+        env.note_off();
+        for _ in 0..10 { env.process(); }
+        assert_eq!(env.is_active(), false);
+    }
+}
@@ -0,0 +1,120 @@
+//!
+//! Envelope generators
+//!
+
+pub mod adsr;
+pub mod mseg;
+
+/// Curvature used by the exponential curves (negative values give a fast start and a slow end)
+const EXPONENTIAL_CURVATURE: f64 = -5.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Curve {
+    Linear = 0,
+    Exponential
+}
+
+impl Curve {
+    pub fn from_name(name: &str) -> Option<Curve> {
+        match name {
+            "linear" => Some(Curve::Linear),
+            "exp" | "exponential" => Some(Curve::Exponential),
+            _ => None
+        }
+    }
+
+    pub fn curvature(&self) -> f64 {
+        match *self {
+            Curve::Linear => 0.0,
+            Curve::Exponential => EXPONENTIAL_CURVATURE,
+        }
+    }
+}
+
+pub trait Envelope {
+    fn reset(&mut self);
+    fn note_on(&mut self);
+    fn note_off(&mut self);
+    fn is_active(&self) -> bool;
+    fn process(&mut self) -> f64;
+}
+
+/// A transition from one level to another along a number of samples.
+///
+/// The shape is given by the curvature: 0 is a straight line, negative values
+/// move fast at the beginning and slow down at the end, and positive values the opposite.
+#[derive(Clone, Debug)]
+struct Segment {
+    start: f64,
+    end: f64,
+    length: usize,
+    pos: usize,
+    curvature: f64,
+    growth: f64,    // Factor to update exp on every sample: e^(curvature / length)
+    exp: f64,       // e^(curvature * pos / length)
+    norm: f64,      // 1 / (e^curvature - 1)
+}
+
+impl Default for Segment {
+    fn default() -> Self {
+        Segment::new(0.0, 0.0, 0, 0.0)
+    }
+}
+
+impl Segment {
+    fn new(start: f64, end: f64, length: usize, curvature: f64) -> Segment {
+        let curvature = if curvature.abs() < 1e-6 { 0.0 } else { curvature };
+        let (growth, norm) = match length {
+            0 => (1.0, 0.0),
+            _ if curvature == 0.0 => (1.0, 0.0),
+            _ => ((curvature / length as f64).exp(), 1.0 / (curvature.exp() - 1.0))
+        };
+        Segment {
+            start: start,
+            end: end,
+            length: length,
+            pos: 0,
+            curvature: curvature,
+            growth: growth,
+            exp: 1.0,
+            norm: norm,
+        }
+    }
+
+    /// Changes the number of samples by a ratio keeping the progress, when the sample rate changes
+    fn rescale(&mut self, ratio: f64) {
+        if self.length > 0 {
+            let length = ((self.length as f64 * ratio).round() as usize).max(1);
+            let pos = (self.pos as f64 * length as f64 / self.length as f64).round() as usize;
+            if self.curvature != 0.0 {
+                self.growth = (self.curvature / length as f64).exp();
+                self.exp = (self.curvature * pos as f64 / length as f64).exp();
+            }
+            self.length = length;
+            self.pos = pos;
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.pos >= self.length
+    }
+
+    /// Returns the level for the current position and moves to the next sample
+    fn process(&mut self) -> f64 {
+        if self.pos >= self.length {
+            return self.end;
+        }
+
+        let shape = if self.curvature == 0.0 {
+            self.pos as f64 / self.length as f64
+        }
+        else {
+            (self.exp - 1.0) * self.norm
+        };
+
+        self.pos += 1;
+        self.exp *= self.growth;
+
+        self.start + (self.end - self.start) * shape
+    }
+}
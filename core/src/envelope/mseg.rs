@@ -0,0 +1,313 @@
+//!
+//! Multi-stage envelope built from breakpoints
+//!
+//! Every breakpoint defines a segment that moves from the previous level to the breakpoint level.
+//! The envelope can hold at a sustain breakpoint while the note is on, and repeat a range of
+//! segments in a loop, so it can also be used as a rhythmic modulator.
+//!
+
+use envelope::{Envelope, Segment};
+
+/// Curvature applied to the segment when the breakpoint curvature is at its limits (-1 or 1)
+const MAX_CURVATURE: f64 = 10.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub time: f64,          // Time in seconds to reach the level from the previous breakpoint
+    pub level: f64,         // Target level
+    pub curvature: f64,     // [-1, 1] where 0 is linear, negative starts fast and positive starts slow
+}
+
+impl Breakpoint {
+    pub fn new(time: f64, level: f64, curvature: f64) -> Breakpoint {
+        Breakpoint {
+            time: time.max(0.0),
+            level: level,
+            curvature: curvature.max(-1.0).min(1.0)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Mseg {
+    sample_rate: f64,
+    breakpoints: Vec<Breakpoint>,
+    sustain: Option<usize>,             // Breakpoint where to hold while the note is on
+    loop_points: Option<(usize, usize)>, // Start and end breakpoints to repeat while the note is on
+
+    gate: bool,
+    active: bool,
+    holding: bool,
+    index: usize,
+    level: f64,
+    segment: Segment,
+}
+
+impl Mseg {
+    pub fn new(sample_rate: f64, breakpoints: Vec<Breakpoint>) -> Mseg {
+        assert!(sample_rate > 0.0);
+
+        Mseg {
+            sample_rate: sample_rate,
+            breakpoints: breakpoints,
+            sustain: None,
+            loop_points: None,
+            gate: false,
+            active: false,
+            holding: false,
+            index: 0,
+            level: 0.0,
+            segment: Segment::default(),
+        }
+    }
+
+    /// Changes the sample rate, the current segment keeps its time
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        assert!(sample_rate > 0.0);
+        self.segment.rescale(sample_rate / self.sample_rate);
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn set_breakpoints(&mut self, breakpoints: Vec<Breakpoint>) {
+        if self.breakpoints != breakpoints {
+            self.breakpoints = breakpoints;
+            self.sustain = self.sustain.and_then(|index| self.valid_index(index));
+            self.loop_points = self.loop_points.and_then(|(start, end)| self.valid_loop(start, end));
+            if self.index >= self.breakpoints.len() {
+                self.active = false;
+            }
+        }
+    }
+
+    pub fn get_breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn set_sustain(&mut self, sustain: Option<usize>) {
+        self.sustain = sustain.and_then(|index| self.valid_index(index));
+    }
+
+    pub fn get_sustain(&self) -> Option<usize> {
+        self.sustain
+    }
+
+    pub fn set_loop(&mut self, loop_points: Option<(usize, usize)>) {
+        self.loop_points = loop_points.and_then(|(start, end)| self.valid_loop(start, end));
+    }
+
+    pub fn get_loop(&self) -> Option<(usize, usize)> {
+        self.loop_points
+    }
+
+    pub fn level(&self) -> f64 {
+        self.level
+    }
+
+    fn valid_index(&self, index: usize) -> Option<usize> {
+        if index < self.breakpoints.len() { Some(index) } else { None }
+    }
+
+    fn valid_loop(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        if start <= end && end < self.breakpoints.len() { Some((start, end)) } else { None }
+    }
+
+    /// Decides what comes after reaching the breakpoint at index.
+    /// Returns None when the envelope has to hold or has finished.
+    fn next_index(&mut self, index: usize) -> Option<usize> {
+        if self.gate && self.sustain == Some(index) {
+            self.holding = true;
+            return None;
+        }
+
+        match self.loop_points {
+            Some((start, end)) if self.gate && end == index => Some(start),
+            _ if index + 1 < self.breakpoints.len() => Some(index + 1),
+            _ => {
+                self.active = false;
+                None
+            }
+        }
+    }
+
+    /// Starts moving from the current level towards the breakpoint at index.
+    /// Breakpoints with a time of zero samples are reached immediately.
+    fn start_segment(&mut self, index: usize) {
+        let mut next = Some(index);
+        // Bound the jumps to avoid spinning forever on loops where all the times are zero
+        let mut jumps = 2 * self.breakpoints.len();
+        while let Some(index) = next {
+            if index >= self.breakpoints.len() {
+                self.active = false;
+                return;
+            }
+
+            self.index = index;
+            let Breakpoint { time, level, curvature } = self.breakpoints[index];
+            let length = (time * self.sample_rate).round() as usize;
+            if length > 0 {
+                self.segment = Segment::new(self.level, level, length, curvature * MAX_CURVATURE);
+                return;
+            }
+
+            self.level = level;
+            if jumps == 0 {
+                // Hold the last level until the note is off, when the loop is left
+                self.holding = true;
+                return;
+            }
+            jumps -= 1;
+            next = self.next_index(index);
+        }
+    }
+}
+
+impl Envelope for Mseg {
+    fn reset(&mut self) {
+        self.gate = false;
+        self.active = false;
+        self.holding = false;
+        self.index = 0;
+        self.level = 0.0;
+    }
+
+    fn note_on(&mut self) {
+        self.gate = true;
+        self.active = true;
+        self.holding = false;
+        self.start_segment(0);
+    }
+
+    fn note_off(&mut self) {
+        if !self.gate {
+            return;
+        }
+
+        self.gate = false;
+        if self.active {
+            match self.sustain {
+                Some(sustain) if self.index <= sustain => {
+                    self.holding = false;
+                    self.start_segment(sustain + 1);
+                },
+                // Holding in a loop where all the times are zero
+                _ if self.holding => {
+                    self.holding = false;
+                    let index = self.index;
+                    if let Some(next) = self.next_index(index) {
+                        self.start_segment(next);
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn process(&mut self) -> f64 {
+        if !self.active || self.holding {
+            return self.level;
+        }
+
+        let value = self.segment.process();
+        if self.segment.is_finished() {
+            let index = self.index;
+            self.level = self.breakpoints[index].level;
+            if let Some(next) = self.next_index(index) {
+                self.start_segment(next);
+            }
+        }
+        else {
+            self.level = value;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use envelope::Envelope;
+
+    fn process(env: &mut Mseg, count: usize) -> Vec<f64> {
+        (0..count).map(|_| env.process()).collect()
+    }
+
+    #[test]
+    fn mseg_one_shot() {
+        let mut env = Mseg::new(2.0, vec![
+            Breakpoint::new(1.0, 1.0, 0.0),
+            Breakpoint::new(2.0, 0.0, 0.0)]);
+        env.note_on();
+        assert_eq!(process(&mut env, 6), vec![0.0, 0.5, 1.0, 0.75, 0.5, 0.25]);
+        assert_eq!(env.is_active(), false);
+        assert_eq!(env.process(), 0.0);
+    }
+
+    #[test]
+    fn mseg_sustain() {
+        let mut env = Mseg::new(2.0, vec![
+            Breakpoint::new(1.0, 1.0, 0.0),
+            Breakpoint::new(1.0, 0.5, 0.0),
+            Breakpoint::new(1.0, 0.0, 0.0)]);
+        env.set_sustain(Some(1));
+        env.note_on();
+        assert_eq!(process(&mut env, 6), vec![0.0, 0.5, 1.0, 0.75, 0.5, 0.5]);
+        env.note_off();
+        assert_eq!(process(&mut env, 2), vec![0.5, 0.25]);
+        assert_eq!(env.is_active(), false);
+    }
+
+    #[test]
+    fn mseg_release_before_sustain() {
+        let mut env = Mseg::new(2.0, vec![
+            Breakpoint::new(2.0, 1.0, 0.0),
+            Breakpoint::new(1.0, 0.0, 0.0)]);
+        env.set_sustain(Some(0));
+        env.note_on();
+        assert_eq!(process(&mut env, 2), vec![0.0, 0.25]);
+        env.note_off();
+        assert_eq!(process(&mut env, 2), vec![0.25, 0.125]);
+        assert_eq!(env.is_active(), false);
+    }
+
+    #[test]
+    fn mseg_loop() {
+        let mut env = Mseg::new(2.0, vec![
+            Breakpoint::new(0.0, 1.0, 0.0),
+            Breakpoint::new(1.0, 0.0, 0.0),
+            Breakpoint::new(1.0, 1.0, 0.0),
+            Breakpoint::new(1.0, 0.0, 0.0)]);
+        env.set_loop(Some((1, 2)));
+        env.note_on();
+        assert_eq!(process(&mut env, 8), vec![1.0, 0.5, 0.0, 0.5, 1.0, 0.5, 0.0, 0.5]);
+        env.note_off();
+        assert_eq!(process(&mut env, 6), vec![1.0, 0.5, 0.0, 0.5, 1.0, 0.5]);
+        assert_eq!(env.is_active(), false);
+    }
+
+    #[test]
+    fn mseg_zero_time_loop() {
+        let mut env = Mseg::new(44100.0, vec![
+            Breakpoint::new(0.0, 1.0, 0.0),
+            Breakpoint::new(0.0, 0.5, 0.0)]);
+        env.set_loop(Some((0, 1)));
+        env.note_on();
+        assert_eq!(env.is_active(), true);
+        assert_eq!(process(&mut env, 2), vec![1.0, 1.0]);
+        env.note_off();
+        assert_eq!(env.process(), 0.5);
+        assert_eq!(env.is_active(), false);
+    }
+
+    #[test]
+    fn mseg_invalid_points() {
+        let mut env = Mseg::new(44100.0, vec![Breakpoint::new(1.0, 1.0, 0.0)]);
+        env.set_sustain(Some(1));
+        env.set_loop(Some((0, 2)));
+        assert_eq!(env.get_sustain(), None);
+        assert_eq!(env.get_loop(), None);
+    }
+}
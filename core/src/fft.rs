@@ -0,0 +1,86 @@
+//!
+//! Radix-2 Fast Fourier Transform
+//!
+
+use std::f64::consts::PI;
+
+use complex::Complex;
+
+/// In-place forward transform. The length of the data has to be a power of two.
+pub fn fft(data: &mut [Complex]) {
+    transform(data, -1.0);
+}
+
+/// In-place inverse transform, normalized by the length of the data.
+pub fn ifft(data: &mut [Complex]) {
+    transform(data, 1.0);
+    let norm = 1.0 / data.len() as f64;
+    for value in data.iter_mut() {
+        *value = value.scale(norm);
+    }
+}
+
+fn transform(data: &mut [Complex], sign: f64) {
+    let len = data.len();
+    assert!(len.is_power_of_two());
+
+    // Bit reversal permutation
+    let mut j = 0;
+    for i in 1..len {
+        let mut bit = len >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let mut size = 2;
+    while size <= len {
+        let step = Complex::from_polar(1.0, sign * 2.0 * PI / size as f64);
+        for start in (0..len).step_by(size) {
+            let mut w = Complex::new(1.0, 0.0);
+            for k in 0..size / 2 {
+                let even = data[start + k];
+                let odd = data[start + k + size / 2] * w;
+                data[start + k] = even + odd;
+                data[start + k + size / 2] = even - odd;
+                w = w * step;
+            }
+        }
+        size <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use complex::Complex;
+
+    #[test]
+    fn fft_sin_harmonic() {
+        let len = 64;
+        let mut data: Vec<Complex> = (0..len)
+            .map(|i| Complex::new((2.0 * PI * 3.0 * i as f64 / len as f64).sin(), 0.0))
+            .collect();
+        fft(&mut data);
+        for (bin, value) in data.iter().enumerate() {
+            let expected = if bin == 3 || bin == len - 3 { len as f64 / 2.0 } else { 0.0 };
+            assert!((value.norm() - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn fft_ifft_identity() {
+        let input: Vec<Complex> = (0..32).map(|i| Complex::new(i as f64, -(i as f64) / 2.0)).collect();
+        let mut data = input.clone();
+        fft(&mut data);
+        ifft(&mut data);
+        for (a, b) in input.iter().zip(data.iter()) {
+            assert!((*a - *b).norm() < 1e-9);
+        }
+    }
+}
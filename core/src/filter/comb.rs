@@ -0,0 +1,256 @@
+//!
+//! Comb filters tuned to a frequency, with a fractional delay line
+//!
+//! The cutoff is the frequency of the first peak (or of the spacing of the notches),
+//! and it can follow the frequency of the key played to tune the comb to the pitch of the note.
+//! The slope has no effect.
+//!
+
+use std::f64::consts::PI;
+use complex::Complex;
+use filter::{Mode, Slope, Filter, CUTOFF_MIN, KEY_TRACKING_REFERENCE, limit_cutoff, key_track};
+
+/// Maximum gain of the feedback, to keep the filter stable
+const MAX_FEEDBACK: f64 = 0.99;
+
+#[derive(Debug)]
+pub struct Comb {
+    mode: Mode,
+    sample_rate: f64,
+    cutoff: f64,
+    res: f64,
+    enabled: bool,
+    key_freq: f64,
+    key_tracking: f64,
+    delay: f64,     // Delay in samples
+    gain: f64,
+    buffer: Vec<f64>,
+    pos: usize,
+}
+
+impl Comb {
+    pub fn new(mode: Mode, sample_rate: f64, cutoff: f64, res: f64) -> Comb {
+        assert!(sample_rate > 0.0);
+        assert!(cutoff >= 0.0);
+        assert!(res >= 0.0);
+
+        let size = (sample_rate / CUTOFF_MIN).ceil() as usize + 2;
+        let mut f = Comb {
+            mode: mode,
+            sample_rate: sample_rate,
+            cutoff: limit_cutoff(cutoff, sample_rate),
+            res: res,
+            enabled: true,
+            key_freq: KEY_TRACKING_REFERENCE,
+            key_tracking: 1.0,
+            delay: 0.0,
+            gain: 0.0,
+            buffer: vec![0.0; size],
+            pos: 0,
+        };
+        f.update_delay();
+        f.update_gain();
+        f
+    }
+
+    pub fn feedforward(sample_rate: f64, cutoff: f64, res: f64) -> Comb {
+        Comb::new(Mode::CombFeedForward, sample_rate, cutoff, res)
+    }
+
+    pub fn feedback(sample_rate: f64, cutoff: f64, res: f64) -> Comb {
+        Comb::new(Mode::CombFeedBack, sample_rate, cutoff, res)
+    }
+
+    /// Sets the frequency of the key played, used by the key tracking
+    pub fn set_key_freq(&mut self, freq: f64) {
+        if self.key_freq != freq {
+            self.key_freq = freq;
+            self.update_delay();
+        }
+    }
+
+    /// Sets how much the tuning follows the key, 1 to follow it exactly and 0 to ignore it
+    pub fn set_key_tracking(&mut self, amount: f64) {
+        if self.key_tracking != amount {
+            self.key_tracking = amount;
+            self.update_delay();
+        }
+    }
+
+    /// Frequency the comb is tuned to, after the key tracking
+    pub fn get_frequency(&self) -> f64 {
+        self.sample_rate / self.delay
+    }
+
+    fn update_delay(&mut self) {
+        let freq = key_track(self.cutoff, self.key_freq, self.key_tracking);
+        self.delay = self.sample_rate / limit_cutoff(freq, self.sample_rate);
+    }
+
+    fn update_gain(&mut self) {
+        self.gain = match self.mode {
+            Mode::CombFeedForward => self.res.min(1.0),
+            _ => self.res.min(MAX_FEEDBACK),
+        };
+    }
+
+    /// Delayed value, linearly interpolated. The last value written is one sample late.
+    fn delayed(&self) -> f64 {
+        let size = self.buffer.len();
+        let delay = self.delay.max(1.0);
+        let index = delay.floor() as usize;
+        let frac = delay - index as f64;
+        let v0 = self.buffer[(self.pos + size + 1 - index) % size];
+        let v1 = self.buffer[(self.pos + size - index) % size];
+        v0 + frac * (v1 - v0)
+    }
+}
+
+impl Filter for Comb {
+    fn reset(&mut self) {
+        for value in self.buffer.iter_mut() {
+            *value = 0.0;
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        if self.enabled != enabled {
+            self.enabled = enabled;
+            self.reset();
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        if self.mode != mode {
+            self.mode = mode;
+            self.update_gain();
+            self.reset();
+        }
+    }
+
+    fn set_slope(&mut self, _slope: Slope) {
+    }
+
+    fn set_cutoff(&mut self, cutoff: f64) {
+        let cutoff = limit_cutoff(cutoff, self.sample_rate);
+        if self.cutoff != cutoff {
+            self.cutoff = cutoff;
+            self.update_delay();
+        }
+    }
+
+    fn set_resonance(&mut self, res: f64) {
+        if self.res != res {
+            self.res = res;
+            self.update_gain();
+        }
+    }
+
+    /// The delay line only grows, so going back to a lower sample rate does not allocate
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        assert!(sample_rate > 0.0);
+        let size = (sample_rate / CUTOFF_MIN).ceil() as usize + 2;
+        if size > self.buffer.len() {
+            self.buffer.resize(size, 0.0);
+        }
+        self.sample_rate = sample_rate;
+        self.cutoff = limit_cutoff(self.cutoff, sample_rate);
+        self.update_delay();
+    }
+
+    /// Response with an ideal fractional delay
+    fn response(&self, freq: f64) -> Complex {
+        let one = Complex::new(1.0, 0.0);
+        if !self.enabled {
+            return one;
+        }
+
+        let g = self.gain;
+        let delay = Complex::from_polar(1.0, -2.0 * PI * freq / self.sample_rate * self.delay.max(1.0));
+        match self.mode {
+            Mode::CombFeedForward => (one + delay.scale(g)).scale(1.0 / (1.0 + g)),
+            Mode::CombFeedBack => Complex::new(1.0 - g, 0.0) / (one - delay.scale(g)),
+            Mode::AllPass => (delay - Complex::new(g, 0.0)) / (one - delay.scale(g)),
+            _ => one,
+        }
+    }
+
+    /// The peaks of the feedback comb and the notches of the feedforward comb are normalised to unity gain
+    fn process(&mut self, signal: f64) -> f64 {
+        if !self.enabled {
+            return signal;
+        }
+
+        let g = self.gain;
+        let delayed = self.delayed();
+        let (stored, value) = match self.mode {
+            Mode::CombFeedForward => (signal, (signal + g * delayed) / (1.0 + g)),
+            Mode::CombFeedBack => {
+                let value = (1.0 - g) * signal + g * delayed;
+                (value, value)
+            },
+            // Schroeder allpass
+            Mode::AllPass => {
+                let stored = signal + g * delayed;
+                (stored, delayed - g * stored)
+            },
+            _ => (signal, signal),
+        };
+
+        self.pos = (self.pos + 1) % self.buffer.len();
+        self.buffer[self.pos] = stored;
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filter::gain_db;
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    fn gain(filter: &mut Comb, freq: f64) -> f64 {
+        filter.reset();
+        let incr = 2.0 * PI * freq / SAMPLE_RATE;
+        let mut peak = 0.0f64;
+        for i in 0..40000 {
+            let value = filter.process((i as f64 * incr).sin());
+            if i >= 20000 {
+                peak = peak.max(value.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn comb_key_tracking() {
+        let mut filter = Comb::feedback(SAMPLE_RATE, KEY_TRACKING_REFERENCE, 0.9);
+        filter.set_key_freq(440.0);
+        assert!((filter.get_frequency() - 440.0).abs() < 1e-9);
+        filter.set_key_tracking(0.0);
+        assert!((filter.get_frequency() - KEY_TRACKING_REFERENCE).abs() < 1e-9);
+    }
+
+    #[test]
+    fn comb_peaks_and_notches() {
+        let mut filter = Comb::feedback(SAMPLE_RATE, 441.0, 0.9);
+        assert!((gain(&mut filter, 441.0) - 1.0).abs() < 0.05);
+        assert!((gain(&mut filter, 882.0) - 1.0).abs() < 0.05);
+        assert!(gain(&mut filter, 661.5) < 0.1);
+
+        filter.set_mode(Mode::CombFeedForward);
+        filter.set_resonance(1.0);
+        assert!((gain(&mut filter, 441.0) - 1.0).abs() < 0.05);
+        assert!(gain(&mut filter, 220.5) < 0.01);
+        assert!(gain(&mut filter, 661.5) < 0.01);
+
+        assert!(gain_db(filter.response(220.5)) < -100.0);
+
+        filter.set_mode(Mode::AllPass);
+        filter.set_resonance(0.7);
+        for &freq in [100.0, 220.5, 441.0, 1000.0].iter() {
+            assert!((gain(&mut filter, freq) - 1.0).abs() < 0.05);
+        }
+    }
+}
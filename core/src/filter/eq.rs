@@ -0,0 +1,190 @@
+//!
+//! Multi-band parametric equalizer built from the peaking and shelving biquads of the RBJ cookbook
+//!
+//! It processes one or two channels, so the same bands can be used as a tone shaper of a voice
+//! or as the equalizer of a stereo bus.
+//!
+
+use std::f64::consts::FRAC_1_SQRT_2;
+
+use complex::Complex;
+use filter::iir::Coeffs;
+
+/// Q of the shelves for a slope of 1 (Butterworth)
+pub const DEFAULT_SHELF_Q: f64 = FRAC_1_SQRT_2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BandKind {
+    Peak,
+    LowShelf,
+    HighShelf,
+}
+
+impl BandKind {
+    pub fn from_name(name: &str) -> Option<BandKind> {
+        match name {
+            "peak" | "bell" => Some(BandKind::Peak),
+            "low-shelf" | "lowshelf" => Some(BandKind::LowShelf),
+            "high-shelf" | "highshelf" => Some(BandKind::HighShelf),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Band {
+    kind: BandKind,
+    freq: f64,
+    q: f64,
+    gain_db: f64,
+    coeff: Coeffs,
+    delays: [[f64; 2]; 2],      // Delays for the left and right channels
+}
+
+impl Band {
+    fn new(sample_rate: f64, kind: BandKind, freq: f64, q: f64, gain_db: f64) -> Band {
+        Band {
+            kind: kind,
+            freq: freq,
+            q: q,
+            gain_db: gain_db,
+            coeff: Self::coeffs(sample_rate, kind, freq, q, gain_db),
+            delays: [[0.0; 2]; 2],
+        }
+    }
+
+    fn coeffs(sample_rate: f64, kind: BandKind, freq: f64, q: f64, gain_db: f64) -> Coeffs {
+        assert!(q > 0.0);
+        match kind {
+            BandKind::Peak => Coeffs::peaking(sample_rate, freq, q, gain_db),
+            BandKind::LowShelf => Coeffs::low_shelf(sample_rate, freq, q, gain_db),
+            BandKind::HighShelf => Coeffs::high_shelf(sample_rate, freq, q, gain_db),
+        }
+    }
+
+    pub fn get_kind(&self) -> BandKind {
+        self.kind
+    }
+
+    pub fn get_freq(&self) -> f64 {
+        self.freq
+    }
+
+    pub fn get_q(&self) -> f64 {
+        self.q
+    }
+
+    pub fn get_gain(&self) -> f64 {
+        self.gain_db
+    }
+}
+
+#[derive(Debug)]
+pub struct Equalizer {
+    sample_rate: f64,
+    bands: Vec<Band>,
+}
+
+impl Equalizer {
+    pub fn new(sample_rate: f64) -> Equalizer {
+        assert!(sample_rate > 0.0);
+        Equalizer {
+            sample_rate: sample_rate,
+            bands: Vec::new(),
+        }
+    }
+
+    pub fn bands(&self) -> &[Band] {
+        &self.bands
+    }
+
+    /// Adds a band with a gain in dB and returns its index
+    pub fn add_band(&mut self, kind: BandKind, freq: f64, q: f64, gain_db: f64) -> usize {
+        self.bands.push(Band::new(self.sample_rate, kind, freq, q, gain_db));
+        self.bands.len() - 1
+    }
+
+    /// Changes the parameters of a band, keeping its state so it can be done while playing
+    pub fn set_band(&mut self, index: usize, kind: BandKind, freq: f64, q: f64, gain_db: f64) {
+        let band = &mut self.bands[index];
+        if band.kind != kind || band.freq != freq || band.q != q || band.gain_db != gain_db {
+            band.kind = kind;
+            band.freq = freq;
+            band.q = q;
+            band.gain_db = gain_db;
+            band.coeff = Band::coeffs(self.sample_rate, kind, freq, q, gain_db);
+        }
+    }
+
+    /// Changes the sample rate while playing, keeping the state of the bands
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+        for band in self.bands.iter_mut() {
+            band.coeff = Band::coeffs(sample_rate, band.kind, band.freq, band.q, band.gain_db);
+        }
+    }
+
+    pub fn remove_band(&mut self, index: usize) {
+        self.bands.remove(index);
+    }
+
+    pub fn truncate(&mut self, num_bands: usize) {
+        self.bands.truncate(num_bands);
+    }
+
+    pub fn reset(&mut self) {
+        for band in self.bands.iter_mut() {
+            band.delays = [[0.0; 2]; 2];
+        }
+    }
+
+    pub fn process(&mut self, signal: f64) -> f64 {
+        self.bands.iter_mut().fold(signal, |signal, band| band.coeff.process(signal, &mut band.delays[0]))
+    }
+
+    pub fn process_stereo(&mut self, left: f64, right: f64) -> (f64, f64) {
+        self.bands.iter_mut().fold((left, right), |(left, right), band| {
+            let left = band.coeff.process(left, &mut band.delays[0]);
+            let right = band.coeff.process(right, &mut band.delays[1]);
+            (left, right)
+        })
+    }
+
+    /// Complex response of all the bands at a frequency
+    pub fn response(&self, freq: f64) -> Complex {
+        self.bands.iter().fold(Complex::new(1.0, 0.0), |response, band| {
+            response * band.coeff.response(freq, self.sample_rate)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filter::gain_db;
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    #[test]
+    fn equalizer_bands() {
+        let mut eq = Equalizer::new(SAMPLE_RATE);
+        eq.add_band(BandKind::LowShelf, 100.0, DEFAULT_SHELF_Q, 6.0);
+        eq.add_band(BandKind::Peak, 1000.0, 2.0, -12.0);
+        eq.add_band(BandKind::HighShelf, 8000.0, DEFAULT_SHELF_Q, 3.0);
+
+        assert!((gain_db(eq.response(10.0)) - 6.0).abs() < 0.1);
+        assert!((gain_db(eq.response(1000.0)) + 12.0).abs() < 0.1);
+        assert!((gain_db(eq.response(20000.0)) - 3.0).abs() < 0.1);
+        // Half of the gain at the corner frequency of the shelves
+        assert!((gain_db(eq.response(8000.0)) - 1.5).abs() < 0.1);
+
+        // A band without gain leaves the signal unchanged
+        eq.set_band(1, BandKind::Peak, 1000.0, 2.0, 0.0);
+        assert!(gain_db(eq.response(1000.0)).abs() < 0.1);
+
+        eq.truncate(0);
+        let (left, right) = eq.process_stereo(0.5, -0.25);
+        assert_eq!((left, right), (0.5, -0.25));
+    }
+}
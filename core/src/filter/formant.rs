@@ -0,0 +1,211 @@
+//!
+//! Formant filter morphing between vowels, with band pass state variable filters in parallel
+//!
+//! The cutoff shifts all the formants, they are at their nominal frequencies for a cutoff of 1 kHz,
+//! and the resonance narrows their bandwidth. The slope has no effect.
+//!
+
+use complex::Complex;
+use filter::{Mode, Slope, Filter, limit_cutoff};
+use filter::svf::Svf;
+
+/// Cutoff for which the formants are at their nominal frequencies
+pub const REFERENCE_CUTOFF: f64 = 1000.0;
+
+const NUM_FORMANTS: usize = 5;
+
+/// Frequency, gain in dB and bandwidth of a formant
+type FormantData = (f64, f64, f64);
+
+/// Formants of a bass voice for the vowels A, E, I, O and U
+static VOWELS: [[FormantData; NUM_FORMANTS]; 5] = [
+    [(600.0, 0.0, 60.0), (1040.0, -7.0, 70.0), (2250.0, -9.0, 110.0), (2450.0, -9.0, 120.0), (2750.0, -20.0, 130.0)],
+    [(400.0, 0.0, 40.0), (1620.0, -12.0, 80.0), (2400.0, -9.0, 100.0), (2800.0, -12.0, 120.0), (3100.0, -18.0, 120.0)],
+    [(250.0, 0.0, 60.0), (1750.0, -30.0, 90.0), (2600.0, -16.0, 100.0), (3050.0, -22.0, 120.0), (3340.0, -28.0, 120.0)],
+    [(400.0, 0.0, 40.0), (750.0, -11.0, 80.0), (2400.0, -21.0, 100.0), (2600.0, -20.0, 120.0), (2900.0, -40.0, 120.0)],
+    [(350.0, 0.0, 40.0), (600.0, -20.0, 80.0), (2400.0, -32.0, 100.0), (2675.0, -28.0, 120.0), (2950.0, -36.0, 120.0)],
+];
+
+/// Names of the vowels, in the order of their positions
+pub static VOWEL_NAMES: [&'static str; 5] = ["a", "e", "i", "o", "u"];
+
+#[derive(Debug)]
+pub struct Formant {
+    mode: Mode,
+    sample_rate: f64,
+    cutoff: f64,
+    res: f64,
+    enabled: bool,
+    vowel: f64,
+    bands: Vec<Svf>,
+    gains: [f64; NUM_FORMANTS],
+}
+
+impl Formant {
+    pub fn new(sample_rate: f64, cutoff: f64, res: f64, vowel: f64) -> Formant {
+        assert!(sample_rate > 0.0);
+        assert!(cutoff >= 0.0);
+        assert!(res >= 0.0);
+
+        let mut f = Formant {
+            mode: Mode::Formant,
+            sample_rate: sample_rate,
+            cutoff: limit_cutoff(cutoff, sample_rate),
+            res: res,
+            enabled: true,
+            vowel: 0.0,
+            bands: (0..NUM_FORMANTS).map(|_| Svf::bandpass12(sample_rate, REFERENCE_CUTOFF, 0.0)).collect(),
+            gains: [0.0; NUM_FORMANTS],
+        };
+        f.set_vowel(vowel);
+        f
+    }
+
+    pub fn get_vowel(&self) -> f64 {
+        self.vowel
+    }
+
+    /// Position of a vowel from its name
+    pub fn vowel_from_name(name: &str) -> Option<f64> {
+        VOWEL_NAMES.iter().position(|&n| n == name).map(|index| index as f64)
+    }
+
+    fn update_formants(&mut self) {
+        let index = (self.vowel.floor() as usize).min(VOWELS.len() - 2);
+        let frac = self.vowel - index as f64;
+        let shift = self.cutoff / REFERENCE_CUTOFF;
+        let narrow = 1.0 - 0.9 * self.res.min(1.0);
+
+        let bands = self.bands.iter_mut().zip(self.gains.iter_mut());
+        let vowels = VOWELS[index].iter().zip(VOWELS[index + 1].iter());
+        for ((band, band_gain), (&(f0, g0, b0), &(f1, g1, b1))) in bands.zip(vowels) {
+            let freq = (f0 + frac * (f1 - f0)) * shift;
+            let bandwidth = (b0 + frac * (b1 - b0)) * shift * narrow;
+            let gain = g0 + frac * (g1 - g0);
+
+            // Resonance of the state variable filter for Q = freq / bandwidth
+            let res = 1.0 - 0.5 * bandwidth / freq;
+
+            band.set_cutoff(freq);
+            band.set_resonance(res.max(0.0));
+            *band_gain = (10.0f64).powf(gain / 20.0);
+        }
+    }
+}
+
+impl Filter for Formant {
+    fn reset(&mut self) {
+        for band in self.bands.iter_mut() {
+            band.reset();
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        if self.enabled != enabled {
+            self.enabled = enabled;
+            self.reset();
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    fn set_slope(&mut self, _slope: Slope) {
+    }
+
+    fn set_cutoff(&mut self, cutoff: f64) {
+        let cutoff = limit_cutoff(cutoff, self.sample_rate);
+        if self.cutoff != cutoff {
+            self.cutoff = cutoff;
+            self.update_formants();
+        }
+    }
+
+    fn set_resonance(&mut self, res: f64) {
+        if self.res != res {
+            self.res = res;
+            self.update_formants();
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+        self.cutoff = limit_cutoff(self.cutoff, sample_rate);
+        for band in self.bands.iter_mut() {
+            band.set_sample_rate(sample_rate);
+        }
+        self.update_formants();
+    }
+
+    fn process(&mut self, signal: f64) -> f64 {
+        if !self.enabled || self.mode != Mode::Formant {
+            return signal;
+        }
+
+        let mut value = 0.0;
+        for (band, gain) in self.bands.iter_mut().zip(self.gains.iter()) {
+            value += gain * band.process(signal);
+        }
+        value
+    }
+
+    fn response(&self, freq: f64) -> Complex {
+        if !self.enabled || self.mode != Mode::Formant {
+            return Complex::new(1.0, 0.0);
+        }
+
+        self.bands.iter().zip(self.gains.iter())
+            .fold(Complex::zero(), |response, (band, gain)| response + band.response(freq).scale(*gain))
+    }
+
+    /// Sets the position between the vowels, from 0 for A to 4 for U
+    fn set_vowel(&mut self, vowel: f64) {
+        self.vowel = vowel.max(0.0).min((VOWELS.len() - 1) as f64);
+        self.update_formants();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    fn gain(filter: &mut Formant, freq: f64) -> f64 {
+        filter.reset();
+        let incr = 2.0 * PI * freq / SAMPLE_RATE;
+        let mut peak = 0.0f64;
+        for i in 0..40000 {
+            let value = filter.process((i as f64 * incr).sin());
+            if i >= 20000 {
+                peak = peak.max(value.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn formant_vowels() {
+        let mut filter = Formant::new(SAMPLE_RATE, REFERENCE_CUTOFF, 0.0, 0.0);
+        // A has its first formant at 600 Hz and I at 250 Hz
+        assert!(gain(&mut filter, 600.0) > 0.9);
+        assert!(gain(&mut filter, 250.0) < 0.2);
+
+        filter.set_vowel(Formant::vowel_from_name("i").unwrap());
+        assert!(gain(&mut filter, 250.0) > 0.9);
+        assert!(gain(&mut filter, 600.0) < 0.2);
+
+        // Half way between E and I
+        filter.set_vowel(1.5);
+        assert!(gain(&mut filter, 325.0) > 0.9);
+        assert!((gain(&mut filter, 325.0) - filter.response(325.0).norm()).abs() < 1e-2);
+
+        // The cutoff shifts the formants
+        filter.set_vowel(0.0);
+        filter.set_cutoff(2.0 * REFERENCE_CUTOFF);
+        assert!(gain(&mut filter, 1200.0) > 0.9);
+    }
+}
@@ -7,16 +7,14 @@
 use std::f64::consts::PI;
 use std::fmt::Display;
 use std::fmt;
-use filter::{Mode, Slope, Filter};
+use complex::Complex;
+use filter::{Mode, Slope, Filter, limit_cutoff};
 
-const CUTOFF_DELTA: f64 = 0.01;
-const CUTOFF_MIN: f64 = 10.0;
+/// Number of samples over which the coefficients are interpolated after a change of the parameters.
+/// The coefficients are computed once per period at most, so the cutoff can be modulated every sample.
+pub const CONTROL_PERIOD: usize = 16;
 
-fn limit_cutoff(cutoff: f64, sample_rate: f64) -> f64 {
-    cutoff.max(CUTOFF_MIN).min((sample_rate - 1.0) / 2.0)
-}
-
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Coeffs {
     a0: f64,
     a1: f64,
@@ -55,6 +53,27 @@ impl Coeffs {
         }
     }
 
+    /// Increment to add every sample to reach the target coefficients in a number of steps.
+    /// The intermediate filters are stable when both ends are, as the stability domain of b1 and b2 is convex.
+    fn step_to(&self, target: &Coeffs, steps: usize) -> Coeffs {
+        let n = steps as f64;
+        Coeffs {
+            a0: (target.a0 - self.a0) / n,
+            a1: (target.a1 - self.a1) / n,
+            a2: (target.a2 - self.a2) / n,
+            b1: (target.b1 - self.b1) / n,
+            b2: (target.b2 - self.b2) / n,
+        }
+    }
+
+    fn add(&mut self, incr: &Coeffs) {
+        self.a0 += incr.a0;
+        self.a1 += incr.a1;
+        self.a2 += incr.a2;
+        self.b1 += incr.b1;
+        self.b2 += incr.b2;
+    }
+
     pub fn zero() -> Coeffs {
         Coeffs {
             a0: 0.0,
@@ -65,6 +84,25 @@ impl Coeffs {
         }
     }
 
+    /// Processes a sample with the delays of a transposed direct form II
+    pub fn process(&self, signal: f64, delays: &mut [f64; 2]) -> f64 {
+        let out =            self.a0 * signal + delays[0];
+        delays[0] = delays[1] + self.a1 * signal - self.b1 * out;
+        delays[1] =             self.a2 * signal - self.b2 * out;
+        out
+    }
+
+    /// Complex response of the biquad at a frequency
+    pub fn response(&self, freq: f64, sample_rate: f64) -> Complex {
+        let w = 2.0 * PI * freq / sample_rate;
+        let z1 = Complex::from_polar(1.0, -w);
+        let z2 = z1 * z1;
+        let one = Complex::new(1.0, 0.0);
+        let num = one.scale(self.a0) + z1.scale(self.a1) + z2.scale(self.a2);
+        let den = one + z1.scale(self.b1) + z2.scale(self.b2);
+        num / den
+    }
+
     fn common(sample_rate: f64, cutoff: f64, res: f64) -> (f64, f64, f64) {
         let w: f64 = cutoff / sample_rate; // cutoff freq [ 0 <= w <= 0.5 ]
         let r: f64 = (0.001f64).max(2.0 * (1.0 - res)); // r is 1/Q (sqrt(2) for a butterworth response)
@@ -120,6 +158,93 @@ impl Coeffs {
         }
     }
 
+    /// Low pass minus high pass, which boosts the cutoff frequency
+    fn peak(sample_rate: f64, cutoff: f64, res: f64) -> Coeffs {
+
+        let (k2, rk, bh) = Self::common(sample_rate, cutoff, res);
+
+        let a0: f64 = (k2 - 1.0) / bh;
+
+        Coeffs {
+            a0:  a0,
+            a1: (2.0 * (k2 + 1.0)) / bh,
+            a2:  a0,
+            b1: (2.0 * (k2 - 1.0)) / bh,
+            b2: (1.0 - rk + k2) / bh,
+        }
+    }
+
+    fn allpass(sample_rate: f64, cutoff: f64, res: f64) -> Coeffs {
+
+        let (k2, rk, bh) = Self::common(sample_rate, cutoff, res);
+
+        let a1: f64 = (2.0 * (k2 - 1.0)) / bh;
+        let b2: f64 = (1.0 - rk + k2) / bh;
+
+        Coeffs {
+            a0:  b2,
+            a1:  a1,
+            a2:  1.0,
+            b1:  a1,
+            b2:  b2,
+        }
+    }
+
+    /// Normalises the coefficients of the RBJ cookbook, where b are the feedforward and a the feedback coefficients
+    fn from_rbj(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Coeffs {
+        Coeffs {
+            a0: b0 / a0,
+            a1: b1 / a0,
+            a2: b2 / a0,
+            b1: a1 / a0,
+            b2: a2 / a0,
+        }
+    }
+
+    /// Peaking EQ with a gain in dB at the center frequency
+    pub fn peaking(sample_rate: f64, freq: f64, q: f64, gain_db: f64) -> Coeffs {
+        let a = (10.0f64).powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * limit_cutoff(freq, sample_rate) / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+
+        Self::from_rbj(
+            1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a,
+            1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a)
+    }
+
+    /// Low shelf with a gain in dB below the corner frequency
+    pub fn low_shelf(sample_rate: f64, freq: f64, q: f64, gain_db: f64) -> Coeffs {
+        let a = (10.0f64).powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * limit_cutoff(freq, sample_rate) / sample_rate;
+        let beta = 2.0 * a.sqrt() * w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+
+        Self::from_rbj(
+            a * ((a + 1.0) - (a - 1.0) * cos + beta),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            (a + 1.0) + (a - 1.0) * cos + beta,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - beta)
+    }
+
+    /// High shelf with a gain in dB above the corner frequency
+    pub fn high_shelf(sample_rate: f64, freq: f64, q: f64, gain_db: f64) -> Coeffs {
+        let a = (10.0f64).powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * limit_cutoff(freq, sample_rate) / sample_rate;
+        let beta = 2.0 * a.sqrt() * w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+
+        Self::from_rbj(
+            a * ((a + 1.0) + (a - 1.0) * cos + beta),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - beta),
+            (a + 1.0) - (a - 1.0) * cos + beta,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - beta)
+    }
+
     fn bandstop(sample_rate: f64, cutoff: f64, res: f64) -> Coeffs {
 
         let (k2, rk, bh) = Self::common(sample_rate, cutoff, res);
@@ -146,6 +271,9 @@ pub struct IIR {
     res: f64,
     enabled: bool,
     pub coeff: Coeffs,
+    target: Coeffs,
+    incr: Coeffs,
+    steps: usize,
     d1: f64, d2: f64, d3: f64, d4: f64,
     invalid_coeffs: bool,
     invalid_delays: bool,
@@ -165,6 +293,9 @@ impl IIR {
             res: res,
             enabled: true,
             coeff: Coeffs::default(),
+            target: Coeffs::default(),
+            incr: Coeffs::default(),
+            steps: 0,
             d1: 0.0, d2: 0.0, d3: 0.0, d4: 0.0,
             invalid_coeffs: true,
             invalid_delays: true,
@@ -210,14 +341,30 @@ impl IIR {
     }
 
     fn update_coeffs(&mut self) {
-        self.coeff = match self.mode {
-            Mode::ByPass => Coeffs::zero(),
+        self.coeff = self.coeffs();
+        self.target = self.coeff;
+        self.steps = 0;
+        self.invalid_coeffs = false;
+    }
+
+    /// Starts the interpolation to the coefficients of the current parameters
+    fn interpolate_coeffs(&mut self) {
+        self.target = self.coeffs();
+        self.incr = self.coeff.step_to(&self.target, CONTROL_PERIOD);
+        self.steps = CONTROL_PERIOD;
+        self.invalid_coeffs = false;
+    }
+
+    fn coeffs(&self) -> Coeffs {
+        match self.mode {
+            Mode::ByPass | Mode::CombFeedForward | Mode::CombFeedBack | Mode::Formant => Coeffs::new(1.0, 0.0, 0.0, 0.0, 0.0),
             Mode::LowPass => Coeffs::lowpass(self.sample_rate, self.cutoff, self.res),
             Mode::HighPass => Coeffs::highpass(self.sample_rate, self.cutoff, self.res),
             Mode::BandPass => Coeffs::bandpass(self.sample_rate, self.cutoff, self.res),
             Mode::BandStop => Coeffs::bandstop(self.sample_rate, self.cutoff, self.res),
-        };
-        self.invalid_coeffs = false;
+            Mode::Peak => Coeffs::peak(self.sample_rate, self.cutoff, self.res),
+            Mode::AllPass => Coeffs::allpass(self.sample_rate, self.cutoff, self.res),
+        }
     }
 
     fn reset_delays(&mut self) {
@@ -251,8 +398,9 @@ impl Filter for IIR {
     }
 
     fn set_cutoff(&mut self, cutoff: f64) {
-        if (self.cutoff - cutoff).abs() >= CUTOFF_DELTA {
-            self.cutoff = limit_cutoff(cutoff, self.sample_rate);
+        let cutoff = limit_cutoff(cutoff, self.sample_rate);
+        if self.cutoff != cutoff {
+            self.cutoff = cutoff;
             self.invalid_coeffs = true;
         }
     }
@@ -264,20 +412,39 @@ impl Filter for IIR {
         }
     }
 
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+        self.cutoff = limit_cutoff(self.cutoff, sample_rate);
+        self.update_coeffs();
+    }
+
     fn process(&mut self, signal: f64) -> f64 {
         if self.enabled {
-            if self.invalid_coeffs {
-                if self.invalid_delays {
-                    self.reset_delays();
-                }
-
+            // A new mode or slope is applied at once, while changes of the cutoff and resonance
+            // are interpolated, and picked up at the end of the current interpolation
+            if self.invalid_coeffs && self.invalid_delays {
+                self.reset_delays();
                 self.update_coeffs();
             }
+            else if self.invalid_coeffs && self.steps == 0 {
+                self.interpolate_coeffs();
+            }
+
+            if self.steps > 0 {
+                self.steps -= 1;
+                if self.steps == 0 {
+                    self.coeff = self.target;
+                }
+                else {
+                    self.coeff.add(&self.incr);
+                }
+            }
 
             let Coeffs { a0, a1, a2, b1, b2 } = self.coeff;
 
             let value = match self.slope {
-                Slope::Slope12 => {
+                Slope::Slope6 | Slope::Slope12 => {
                     let out =           a0 * signal + self.d1;
                     self.d1 = self.d2 + a1 * signal - b1 * out;
                     self.d2 =           a2 * signal - b2 * out;
@@ -286,7 +453,7 @@ impl Filter for IIR {
                     out
                 },
 
-                Slope::Slope24 => {
+                Slope::Slope18 | Slope::Slope24 => {
                     let out =           a0 * signal + self.d1;
                     self.d1 = self.d2 + a1 * signal - b1 * out;
                     self.d2 =           a2 * signal - b2 * out;
@@ -303,4 +470,86 @@ impl Filter for IIR {
             signal
         }
     }
+
+    fn response(&self, freq: f64) -> Complex {
+        if !self.enabled {
+            return Complex::new(1.0, 0.0);
+        }
+        let response = self.coeffs().response(freq, self.sample_rate);
+        match self.slope {
+            Slope::Slope6 | Slope::Slope12 => response,
+            Slope::Slope18 | Slope::Slope24 => response * response,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filter::gain_db;
+    use std::f64::consts::SQRT_2;
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    /// Resonance for a Butterworth response, with Q = 1 / sqrt(2)
+    const BUTTERWORTH: f64 = 1.0 - SQRT_2 / 2.0;
+
+    #[test]
+    fn iir_cutoff_points() {
+        let filter = IIR::lowpass12(SAMPLE_RATE, 1000.0, BUTTERWORTH);
+        assert!(gain_db(filter.response(20.0)).abs() < 0.01);
+        assert!((gain_db(filter.response(1000.0)) + 3.01).abs() < 0.01);
+        assert!((filter.response(1000.0).arg() + PI / 2.0).abs() < 1e-6);
+
+        let filter = IIR::highpass24(SAMPLE_RATE, 1000.0, BUTTERWORTH);
+        assert!((gain_db(filter.response(1000.0)) + 6.02).abs() < 0.01);
+        assert!(gain_db(filter.response(10000.0)).abs() < 0.01);
+
+        let filter = IIR::bandstop12(SAMPLE_RATE, 1000.0, BUTTERWORTH);
+        assert!(gain_db(filter.response(1000.0)) < -100.0);
+    }
+
+    #[test]
+    fn iir_response_matches_process() {
+        let mut filter = IIR::lowpass24(SAMPLE_RATE, 2000.0, 0.5);
+        let freq = 3000.0;
+        let incr = 2.0 * PI * freq / SAMPLE_RATE;
+        let mut peak = 0.0f64;
+        for i in 0..20000 {
+            let value = filter.process((i as f64 * incr).sin());
+            if i >= 10000 {
+                peak = peak.max(value.abs());
+            }
+        }
+        assert!((peak - filter.response(freq).norm()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn iir_cutoff_modulation() {
+        let mut filter = IIR::lowpass12(SAMPLE_RATE, 200.0, 0.5);
+        // Cutoff sweep every sample, the coefficients follow it
+        for i in 0..4410 {
+            let cutoff = 200.0 * (2.0f64).powf(i as f64 / 1000.0);
+            filter.set_cutoff(cutoff);
+            let value = filter.process((i as f64 * 0.1).sin());
+            assert!(value.is_finite() && value.abs() < 10.0);
+        }
+
+        // Settles on the coefficients of the last cutoff
+        for _ in 0..2 * CONTROL_PERIOD {
+            filter.process(0.0);
+        }
+        let expected = IIR::lowpass12(SAMPLE_RATE, 200.0 * (2.0f64).powf(4.409), 0.5);
+        assert_eq!(filter.coeff, expected.coeff);
+
+        // A jump of the cutoff is spread over a control period
+        filter.set_cutoff(8000.0);
+        let start = filter.coeff;
+        filter.process(0.0);
+        assert!(filter.coeff != start && filter.coeff != filter.coeffs());
+        for _ in 1..CONTROL_PERIOD {
+            filter.process(0.0);
+        }
+        assert_eq!(filter.coeff, filter.coeffs());
+    }
 }
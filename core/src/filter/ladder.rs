@@ -0,0 +1,292 @@
+//!
+//! Moog style transistor ladder filter
+//!
+//! Four one pole stages with topology preserving transform, saturated with tanh,
+//! and a global feedback solved without delay for the linear part.
+//! The outputs of the stages are mixed to get the different modes and slopes, like the Oberheim Xpander.
+//!
+//! See "The Art of VA Filter Design" by Vadim Zavalishin
+//!
+
+use std::f64::consts::PI;
+use complex::Complex;
+use filter::{Mode, Slope, Filter, limit_cutoff, bilinear_s};
+
+/// Feedback for the maximum resonance, the filter self-oscillates above 4
+const MAX_FEEDBACK: f64 = 4.5;
+
+/// Gains of the input and the outputs of the 4 stages for each mode and slope
+type Mix = [f64; 5];
+
+fn mix(mode: &Mode, slope: &Slope) -> Mix {
+    match (mode, slope) {
+        (&Mode::ByPass, _) |
+        (&Mode::CombFeedForward, _) |
+        (&Mode::CombFeedBack, _) |
+        (&Mode::Formant, _) => [1.0, 0.0, 0.0, 0.0, 0.0],
+
+        (&Mode::LowPass, &Slope::Slope6) => [0.0, 1.0, 0.0, 0.0, 0.0],
+        (&Mode::LowPass, &Slope::Slope12) => [0.0, 0.0, 1.0, 0.0, 0.0],
+        (&Mode::LowPass, &Slope::Slope18) => [0.0, 0.0, 0.0, 1.0, 0.0],
+        (&Mode::LowPass, &Slope::Slope24) => [0.0, 0.0, 0.0, 0.0, 1.0],
+
+        // (1 - H)^n
+        (&Mode::HighPass, &Slope::Slope6) => [1.0, -1.0, 0.0, 0.0, 0.0],
+        (&Mode::HighPass, &Slope::Slope12) => [1.0, -2.0, 1.0, 0.0, 0.0],
+        (&Mode::HighPass, &Slope::Slope18) => [1.0, -3.0, 3.0, -1.0, 0.0],
+        (&Mode::HighPass, &Slope::Slope24) => [1.0, -4.0, 6.0, -4.0, 1.0],
+
+        // 2 H (1 - H) and 4 H^2 (1 - H)^2, with unity gain at the cutoff frequency
+        (&Mode::BandPass, &Slope::Slope6) |
+        (&Mode::BandPass, &Slope::Slope12) => [0.0, 2.0, -2.0, 0.0, 0.0],
+        (&Mode::BandPass, &Slope::Slope18) |
+        (&Mode::BandPass, &Slope::Slope24) => [0.0, 0.0, 4.0, -8.0, 4.0],
+
+        // 1 - band pass
+        (&Mode::BandStop, &Slope::Slope6) |
+        (&Mode::BandStop, &Slope::Slope12) => [1.0, -2.0, 2.0, 0.0, 0.0],
+        (&Mode::BandStop, &Slope::Slope18) |
+        (&Mode::BandStop, &Slope::Slope24) => [1.0, 0.0, -4.0, 8.0, -4.0],
+
+        // Low pass - high pass
+        (&Mode::Peak, &Slope::Slope6) => [-1.0, 2.0, 0.0, 0.0, 0.0],
+        (&Mode::Peak, &Slope::Slope12) => [-1.0, 2.0, 0.0, 0.0, 0.0],
+        (&Mode::Peak, &Slope::Slope18) => [-1.0, 3.0, -3.0, 2.0, 0.0],
+        (&Mode::Peak, &Slope::Slope24) => [-1.0, 4.0, -6.0, 4.0, 0.0],
+
+        // (1 - 2 H)^n
+        (&Mode::AllPass, &Slope::Slope6) => [1.0, -2.0, 0.0, 0.0, 0.0],
+        (&Mode::AllPass, &Slope::Slope12) => [1.0, -4.0, 4.0, 0.0, 0.0],
+        (&Mode::AllPass, &Slope::Slope18) => [1.0, -6.0, 12.0, -8.0, 0.0],
+        (&Mode::AllPass, &Slope::Slope24) => [1.0, -8.0, 24.0, -32.0, 16.0],
+    }
+}
+
+#[derive(Debug)]
+pub struct Ladder {
+    mode: Mode,
+    slope: Slope,
+    sample_rate: f64,
+    cutoff: f64,
+    res: f64,
+    drive: f64,
+    enabled: bool,
+    mix: Mix,
+    g: f64,     // Gain of a stage, g / (1 + g) for the prewarped g
+    k: f64,     // Feedback
+    s: [f64; 4],
+}
+
+impl Ladder {
+    pub fn new(mode: Mode, slope: Slope, sample_rate: f64, cutoff: f64, res: f64) -> Ladder {
+        assert!(sample_rate > 0.0);
+        assert!(cutoff >= 0.0);
+        assert!(res >= 0.0);
+
+        let mut f = Ladder {
+            mix: mix(&mode, &slope),
+            mode: mode,
+            slope: slope,
+            sample_rate: sample_rate,
+            cutoff: limit_cutoff(cutoff, sample_rate),
+            res: res,
+            drive: 1.0,
+            enabled: true,
+            g: 0.0,
+            k: 0.0,
+            s: [0.0; 4],
+        };
+        f.update_coeffs();
+        f
+    }
+
+    pub fn lowpass24(sample_rate: f64, cutoff: f64, res: f64) -> Ladder {
+        Ladder::new(Mode::LowPass, Slope::Slope24, sample_rate, cutoff, res)
+    }
+
+    pub fn get_drive(&self) -> f64 {
+        self.drive
+    }
+
+    fn update_coeffs(&mut self) {
+        let g = (PI * self.cutoff / self.sample_rate).tan();
+        self.g = g / (1.0 + g);
+        self.k = self.res.min(1.0) * MAX_FEEDBACK;
+    }
+}
+
+impl Filter for Ladder {
+    fn reset(&mut self) {
+        self.s = [0.0; 4];
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        if self.enabled != enabled {
+            self.enabled = enabled;
+            self.reset();
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mix = mix(&mode, &self.slope);
+        self.mode = mode;
+    }
+
+    fn set_slope(&mut self, slope: Slope) {
+        self.mix = mix(&self.mode, &slope);
+        self.slope = slope;
+    }
+
+    fn set_cutoff(&mut self, cutoff: f64) {
+        let cutoff = limit_cutoff(cutoff, self.sample_rate);
+        if self.cutoff != cutoff {
+            self.cutoff = cutoff;
+            self.update_coeffs();
+        }
+    }
+
+    fn set_resonance(&mut self, res: f64) {
+        if self.res != res {
+            self.res = res;
+            self.update_coeffs();
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+        self.cutoff = limit_cutoff(self.cutoff, sample_rate);
+        self.update_coeffs();
+    }
+
+    fn process(&mut self, signal: f64) -> f64 {
+        if !self.enabled {
+            return signal;
+        }
+
+        let g = self.g;
+        let k = self.k;
+
+        // Output of the last stage is g^4 * u + sigma, solve the feedback for u with the linear stages
+        let b = 1.0 - g;
+        let sigma = b * (g * g * g * self.s[0] + g * g * self.s[1] + g * self.s[2] + self.s[3]);
+        let u = (self.drive * signal - k * sigma) / (1.0 + k * g * g * g * g);
+
+        let mut y = [u.tanh(), 0.0, 0.0, 0.0, 0.0];
+        for i in 0..4 {
+            let x = if i == 0 { y[0] } else { y[i].tanh() };
+            let v = (x - self.s[i]) * g;
+            y[i + 1] = v + self.s[i];
+            self.s[i] = y[i + 1] + v;
+        }
+
+        let m = &self.mix;
+        m[0] * y[0] + m[1] * y[1] + m[2] * y[2] + m[3] * y[3] + m[4] * y[4]
+    }
+
+    /// Response for small signals, when the saturation is linear
+    fn response(&self, freq: f64) -> Complex {
+        let one = Complex::new(1.0, 0.0);
+        if !self.enabled {
+            return one;
+        }
+
+        // One pole stage, and input of the stages with the feedback solved
+        let h = one / (one + bilinear_s(freq, self.cutoff, self.sample_rate));
+        let h2 = h * h;
+        let u = Complex::new(self.drive, 0.0) / (one + (h2 * h2).scale(self.k));
+
+        let mut response = Complex::zero();
+        let mut stage = u;
+        for m in self.mix.iter() {
+            response = response + stage.scale(*m);
+            stage = stage * h;
+        }
+        response
+    }
+
+    /// Gain of the input before the saturation, 1 for unity gain
+    fn set_drive(&mut self, drive: f64) {
+        assert!(drive > 0.0);
+        self.drive = drive;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    /// Peak amplitude of the output for a small sine after the filter settles, relative to the input
+    fn gain(filter: &mut Ladder, freq: f64) -> f64 {
+        const AMPLITUDE: f64 = 0.001;
+        filter.reset();
+        let incr = 2.0 * PI * freq / SAMPLE_RATE;
+        let mut peak = 0.0f64;
+        for i in 0..20000 {
+            let value = filter.process(AMPLITUDE * (i as f64 * incr).sin());
+            if i >= 10000 {
+                peak = peak.max(value.abs());
+            }
+        }
+        peak / AMPLITUDE
+    }
+
+    #[test]
+    fn ladder_slopes() {
+        let mut filter = Ladder::lowpass24(SAMPLE_RATE, 250.0, 0.0);
+        let slopes = [(Slope::Slope6, -6.0), (Slope::Slope12, -12.0), (Slope::Slope18, -18.0), (Slope::Slope24, -24.0)];
+        for &(slope, db) in slopes.iter() {
+            filter.set_slope(slope);
+            assert!((gain(&mut filter, 20.0) - 1.0).abs() < 2e-2);
+            // Attenuation per octave well above the cutoff frequency
+            let octave = 20.0 * (gain(&mut filter, 4000.0) / gain(&mut filter, 2000.0)).log10();
+            assert!((octave - db).abs() < 1.5, "{:?} {}", slope, octave);
+        }
+
+        filter.set_mode(Mode::HighPass);
+        assert!(gain(&mut filter, 20.0) < 1e-3);
+        assert!((gain(&mut filter, 15000.0) - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn ladder_response_matches_process() {
+        let mut filter = Ladder::new(Mode::BandPass, Slope::Slope12, SAMPLE_RATE, 1000.0, 0.5);
+        for &freq in [250.0, 1000.0, 4000.0].iter() {
+            let expected = filter.response(freq).norm();
+            assert!((gain(&mut filter, freq) - expected).abs() < 1e-2, "{}", freq);
+        }
+
+        // The resonance attenuates the low frequencies
+        filter.set_mode(Mode::LowPass);
+        assert!((filter.response(0.0).norm() - 1.0 / (1.0 + 0.5 * MAX_FEEDBACK)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ladder_self_oscillation() {
+        let mut filter = Ladder::lowpass24(SAMPLE_RATE, 1000.0, 1.0);
+        filter.set_drive(4.0);
+        let mut peak = 0.0f64;
+        for i in 0..SAMPLE_RATE as usize {
+            let value = filter.process(if i == 0 { 1.0 } else { 0.0 });
+            assert!(value.is_finite());
+            if i as f64 >= SAMPLE_RATE * 0.9 {
+                peak = peak.max(value.abs());
+            }
+        }
+        // Still oscillating without input, and bounded by the saturation
+        assert!(peak > 0.1 && peak < 1.5, "{}", peak);
+
+        filter.reset();
+        filter.set_resonance(0.5);
+        let mut peak = 0.0f64;
+        for i in 0..SAMPLE_RATE as usize {
+            let value = filter.process(if i == 0 { 1.0 } else { 0.0 });
+            if i as f64 >= SAMPLE_RATE * 0.9 {
+                peak = peak.max(value.abs());
+            }
+        }
+        assert!(peak < 1e-6);
+    }
+}
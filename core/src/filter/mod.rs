@@ -1,27 +1,149 @@
 
-#[derive(Debug)]
+use std::fmt::Debug;
+use std::f64::consts::PI;
+use complex::Complex;
+
+pub const CUTOFF_MIN: f64 = 10.0;
+
+/// Limits the cutoff to the range of frequencies that can be represented
+pub fn limit_cutoff(cutoff: f64, sample_rate: f64) -> f64 {
+    cutoff.max(CUTOFF_MIN).min((sample_rate - 1.0) / 2.0)
+}
+
+/// Analog frequency `s` normalised to the cutoff, for the bilinear transform prewarped at the cutoff.
+/// The response of the analog prototype at `s` is the response of the digital filter at `freq`.
+pub fn bilinear_s(freq: f64, cutoff: f64, sample_rate: f64) -> Complex {
+    let nyquist = 0.5 * sample_rate;
+    let freq = freq.max(0.0).min(nyquist * 0.99999);
+    Complex::new(0.0, (PI * freq / sample_rate).tan() / (PI * cutoff / sample_rate).tan())
+}
+
+/// Gain in dB of a response
+pub fn gain_db(response: Complex) -> f64 {
+    20.0 * response.norm().log10()
+}
+
+/// Response of a filter at `num_points` log spaced frequencies between `min_freq` and `max_freq`,
+/// returned as pairs of frequency and response, to draw its magnitude and phase curves
+pub fn response_curve(filter: &dyn Filter, min_freq: f64, max_freq: f64, num_points: usize) -> Vec<(f64, Complex)> {
+    assert!(min_freq > 0.0 && max_freq >= min_freq);
+    let ratio = max_freq / min_freq;
+    let last = num_points.max(2) - 1;
+    (0..num_points).map(|i| {
+        let freq = min_freq * ratio.powf(i as f64 / last as f64);
+        (freq, filter.response(freq))
+    }).collect()
+}
+
+/// Frequency of the key at which key tracking leaves the cutoff unchanged (middle C)
+pub const KEY_TRACKING_REFERENCE: f64 = 261.626;
+
+/// Cutoff following the frequency of the key played, with an amount of 1 to follow it exactly
+pub fn key_track(cutoff: f64, key_freq: f64, amount: f64) -> f64 {
+    cutoff * (key_freq / KEY_TRACKING_REFERENCE).powf(amount)
+}
+
+/// Filter modes.
+/// The comb and formant modes are only implemented by their own filters,
+/// the other filters let the signal pass through unchanged for them.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Mode {
     ByPass = 0,
     LowPass,
     HighPass,
     BandPass,
-    BandStop
+    BandStop,
+    Peak,
+    AllPass,
+    CombFeedForward,
+    CombFeedBack,
+    Formant
 }
 
-#[derive(Debug)]
+impl Mode {
+    pub fn from_name(name: &str) -> Option<Mode> {
+        match name {
+            "bypass" => Some(Mode::ByPass),
+            "lowpass" | "lp" => Some(Mode::LowPass),
+            "highpass" | "hp" => Some(Mode::HighPass),
+            "bandpass" | "bp" => Some(Mode::BandPass),
+            "bandstop" | "notch" => Some(Mode::BandStop),
+            "peak" => Some(Mode::Peak),
+            "allpass" | "ap" => Some(Mode::AllPass),
+            "comb-ff" => Some(Mode::CombFeedForward),
+            "comb-fb" | "comb" => Some(Mode::CombFeedBack),
+            "formant" => Some(Mode::Formant),
+            _ => None
+        }
+    }
+}
+
+/// Attenuation per octave above the cutoff frequency.
+/// The IIR and state variable filters only have 12 and 24 dB slopes, 6 and 18 dB are rounded up to them.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Slope {
-    Slope12 = 0,
+    Slope6 = 0,
+    Slope12,
+    Slope18,
     Slope24
 }
 
-pub trait Filter {
+impl Slope {
+    pub fn from_name(name: &str) -> Option<Slope> {
+        match name {
+            "6" | "6db" => Some(Slope::Slope6),
+            "12" | "12db" => Some(Slope::Slope12),
+            "18" | "18db" => Some(Slope::Slope18),
+            "24" | "24db" => Some(Slope::Slope24),
+            _ => None
+        }
+    }
+}
+
+pub trait Filter: Debug {
     fn reset(&mut self);
     fn set_enabled(&mut self, enabled: bool);
     fn set_mode(&mut self, mode: Mode);
     fn set_slope(&mut self, slope: Slope);
     fn set_cutoff(&mut self, cutoff: f64);
     fn set_resonance(&mut self, res: f64);
+
+    /// Changes the sample rate while playing, keeping the state of the filter
+    fn set_sample_rate(&mut self, sample_rate: f64);
+
     fn process(&mut self, signal: f64) -> f64;
+
+    /// Complex response at a frequency for the current parameters, ignoring any saturation
+    fn response(&self, freq: f64) -> Complex;
+
+    /// Gain of the input before the saturation, only used by the filters that saturate
+    fn set_drive(&mut self, _drive: f64) {}
+
+    /// Position between the vowels, only used by the formant filter
+    fn set_vowel(&mut self, _vowel: f64) {}
 }
 
 pub mod iir;
+pub mod svf;
+pub mod ladder;
+pub mod comb;
+pub mod formant;
+pub mod eq;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filter::iir::IIR;
+
+    #[test]
+    fn response_curve_log_spaced() {
+        let filter = IIR::lowpass12(44100.0, 1000.0, 0.0);
+        let curve = response_curve(&filter, 20.0, 20000.0, 4);
+        let freqs: Vec<f64> = curve.iter().map(|&(freq, _)| freq).collect();
+        for (freq, expected) in freqs.iter().zip([20.0, 200.0, 2000.0, 20000.0].iter()) {
+            assert!((freq - expected).abs() < 1e-6);
+        }
+        assert!(gain_db(curve[0].1).abs() < 0.01);
+        assert!(gain_db(curve[3].1) < -40.0);
+    }
+}
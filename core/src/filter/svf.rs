@@ -0,0 +1,315 @@
+//!
+//! State Variable Filter (SVF) with topology preserving transform
+//!
+//! Based on https://cytomic.com/files/dsp/SvfLinearTrapOptimised2.pdf
+//!
+//! The state is kept in the integrators, so the cutoff and resonance can be modulated at audio rate
+//! without resetting the filter.
+//!
+
+use std::f64::consts::PI;
+use complex::Complex;
+use filter::{Mode, Slope, Filter, limit_cutoff, bilinear_s};
+
+/// Minimum damping, to keep the filter stable when the resonance is at its maximum
+const MIN_DAMPING: f64 = 0.001;
+
+/// All the outputs of the filter for a sample
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Outputs {
+    pub low: f64,
+    pub high: f64,
+    pub band: f64,      // Normalised to unity gain at the cutoff frequency
+    pub notch: f64,
+    pub peak: f64,
+    pub all: f64,
+}
+
+impl Outputs {
+    pub fn get(&self, mode: &Mode, signal: f64) -> f64 {
+        match *mode {
+            Mode::ByPass | Mode::CombFeedForward | Mode::CombFeedBack | Mode::Formant => signal,
+            Mode::LowPass => self.low,
+            Mode::HighPass => self.high,
+            Mode::BandPass => self.band,
+            Mode::BandStop => self.notch,
+            Mode::Peak => self.peak,
+            Mode::AllPass => self.all,
+        }
+    }
+}
+
+/// Integrator states of one stage
+#[derive(Clone, Copy, Debug, Default)]
+struct State {
+    ic1eq: f64,
+    ic2eq: f64,
+}
+
+#[derive(Debug)]
+pub struct Svf {
+    mode: Mode,
+    slope: Slope,
+    sample_rate: f64,
+    cutoff: f64,
+    res: f64,
+    enabled: bool,
+    k: f64,
+    a1: f64,
+    a2: f64,
+    a3: f64,
+    stages: [State; 2],
+}
+
+impl Svf {
+    pub fn new(mode: Mode, slope: Slope, sample_rate: f64, cutoff: f64, res: f64) -> Svf {
+        assert!(sample_rate > 0.0);
+        assert!(cutoff >= 0.0);
+        assert!(res >= 0.0);
+
+        let mut f = Svf {
+            mode: mode,
+            slope: slope,
+            sample_rate: sample_rate,
+            cutoff: limit_cutoff(cutoff, sample_rate),
+            res: res,
+            enabled: true,
+            k: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+            stages: [State::default(); 2],
+        };
+        f.update_coeffs();
+        f
+    }
+
+    pub fn bypass(sample_rate: f64) -> Svf {
+        Svf::new(Mode::ByPass, Slope::Slope12, sample_rate, 0.0, 0.0)
+    }
+
+    pub fn lowpass12(sample_rate: f64, cutoff: f64, res: f64) -> Svf {
+        Svf::new(Mode::LowPass, Slope::Slope12, sample_rate, cutoff, res)
+    }
+
+    pub fn highpass12(sample_rate: f64, cutoff: f64, res: f64) -> Svf {
+        Svf::new(Mode::HighPass, Slope::Slope12, sample_rate, cutoff, res)
+    }
+
+    pub fn bandpass12(sample_rate: f64, cutoff: f64, res: f64) -> Svf {
+        Svf::new(Mode::BandPass, Slope::Slope12, sample_rate, cutoff, res)
+    }
+
+    pub fn lowpass24(sample_rate: f64, cutoff: f64, res: f64) -> Svf {
+        Svf::new(Mode::LowPass, Slope::Slope24, sample_rate, cutoff, res)
+    }
+
+    fn update_coeffs(&mut self) {
+        let g = (PI * self.cutoff / self.sample_rate).tan();
+        // Same mapping of the resonance to 1/Q than the IIR filter
+        self.k = MIN_DAMPING.max(2.0 * (1.0 - self.res));
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+
+    fn process_stage(&mut self, stage: usize, v0: f64) -> Outputs {
+        let State { ic1eq, ic2eq } = self.stages[stage];
+        let v3 = v0 - ic2eq;
+        let v1 = self.a1 * ic1eq + self.a2 * v3;
+        let v2 = ic2eq + self.a2 * ic1eq + self.a3 * v3;
+        self.stages[stage] = State {
+            ic1eq: 2.0 * v1 - ic1eq,
+            ic2eq: 2.0 * v2 - ic2eq,
+        };
+
+        let k = self.k;
+        Outputs {
+            low: v2,
+            high: v0 - k * v1 - v2,
+            band: k * v1,
+            notch: v0 - k * v1,
+            peak: 2.0 * v2 - v0 + k * v1,
+            all: v0 - 2.0 * k * v1,
+        }
+    }
+
+    /// Processes a sample and returns all the outputs at the same time.
+    /// With a slope of 24 dB, the second stage is fed with the output of the first stage for the current mode.
+    pub fn process_all(&mut self, signal: f64) -> Outputs {
+        let outputs = self.process_stage(0, signal);
+        match self.slope {
+            Slope::Slope6 | Slope::Slope12 => outputs,
+            Slope::Slope18 | Slope::Slope24 => {
+                let signal = outputs.get(&self.mode, signal);
+                self.process_stage(1, signal)
+            }
+        }
+    }
+}
+
+impl Filter for Svf {
+    fn reset(&mut self) {
+        self.stages = [State::default(); 2];
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        if self.enabled != enabled {
+            self.enabled = enabled;
+            self.reset();
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    fn set_slope(&mut self, slope: Slope) {
+        self.slope = slope;
+        self.stages[1] = State::default();
+    }
+
+    fn set_cutoff(&mut self, cutoff: f64) {
+        let cutoff = limit_cutoff(cutoff, self.sample_rate);
+        if self.cutoff != cutoff {
+            self.cutoff = cutoff;
+            self.update_coeffs();
+        }
+    }
+
+    fn set_resonance(&mut self, res: f64) {
+        if self.res != res {
+            self.res = res;
+            self.update_coeffs();
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        assert!(sample_rate > 0.0);
+        self.sample_rate = sample_rate;
+        self.cutoff = limit_cutoff(self.cutoff, sample_rate);
+        self.update_coeffs();
+    }
+
+    fn process(&mut self, signal: f64) -> f64 {
+        if self.enabled {
+            match self.mode {
+                Mode::ByPass => signal,
+                _ => self.process_all(signal).get(&self.mode, signal)
+            }
+        }
+        else {
+            signal
+        }
+    }
+
+    /// Response of the analog prototype, which the topology preserving transform keeps exactly
+    fn response(&self, freq: f64) -> Complex {
+        let one = Complex::new(1.0, 0.0);
+        if !self.enabled {
+            return one;
+        }
+
+        let s = bilinear_s(freq, self.cutoff, self.sample_rate);
+        let s2 = s * s;
+        let ks = s.scale(self.k);
+        let num = match self.mode {
+            Mode::ByPass | Mode::CombFeedForward | Mode::CombFeedBack | Mode::Formant => return one,
+            Mode::LowPass => one,
+            Mode::HighPass => s2,
+            Mode::BandPass => ks,
+            Mode::BandStop => s2 + one,
+            Mode::Peak => one - s2,
+            Mode::AllPass => s2 - ks + one,
+        };
+        let response = num / (s2 + ks + one);
+        match self.slope {
+            Slope::Slope6 | Slope::Slope12 => response,
+            Slope::Slope18 | Slope::Slope24 => response * response,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use noise::{Noise, Color};
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    /// Peak amplitude of the output for a sine after the filter settles
+    fn gain(filter: &mut Svf, freq: f64) -> f64 {
+        filter.reset();
+        let incr = 2.0 * PI * freq / SAMPLE_RATE;
+        let mut peak = 0.0f64;
+        for i in 0..20000 {
+            let value = filter.process((i as f64 * incr).sin());
+            if i >= 10000 {
+                peak = peak.max(value.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn svf_responses() {
+        let mut filter = Svf::lowpass12(SAMPLE_RATE, 1000.0, 0.0);
+        assert!((gain(&mut filter, 20.0) - 1.0).abs() < 1e-2);
+        assert!((gain(&mut filter, 1000.0) - 0.5).abs() < 1e-2);
+        assert!(gain(&mut filter, 10000.0) < 0.02);
+
+        filter.set_mode(Mode::HighPass);
+        assert!(gain(&mut filter, 20.0) < 1e-3);
+        assert!((gain(&mut filter, 10000.0) - 1.0).abs() < 1e-2);
+
+        filter.set_mode(Mode::BandPass);
+        assert!((gain(&mut filter, 1000.0) - 1.0).abs() < 1e-2);
+
+        filter.set_resonance(0.5);
+        filter.set_mode(Mode::BandStop);
+        assert!(gain(&mut filter, 1000.0) < 1e-2);
+        assert!((gain(&mut filter, 20.0) - 1.0).abs() < 1e-2);
+
+        filter.set_mode(Mode::Peak);
+        assert!((gain(&mut filter, 1000.0) - 2.0).abs() < 2e-2);
+        assert!((gain(&mut filter, 20.0) - 1.0).abs() < 1e-2);
+
+        filter.set_mode(Mode::AllPass);
+        assert!((gain(&mut filter, 20.0) - 1.0).abs() < 1e-2);
+        assert!((gain(&mut filter, 1000.0) - 1.0).abs() < 1e-2);
+
+        filter.set_mode(Mode::LowPass);
+        filter.set_slope(Slope::Slope24);
+        assert!((gain(&mut filter, 1000.0) - 1.0).abs() < 1e-2);
+        assert!(gain(&mut filter, 10000.0) < 1e-3);
+    }
+
+    #[test]
+    fn svf_response_matches_process() {
+        let modes = [Mode::LowPass, Mode::HighPass, Mode::BandPass, Mode::Peak];
+        for &mode in modes.iter() {
+            let mut filter = Svf::new(mode, Slope::Slope24, SAMPLE_RATE, 1000.0, 0.6);
+            for &freq in [300.0, 1000.0, 4000.0].iter() {
+                let expected = filter.response(freq).norm();
+                assert!((gain(&mut filter, freq) - expected).abs() < 1e-2 * expected.max(0.1), "{:?} {}", mode, freq);
+            }
+        }
+    }
+
+    #[test]
+    fn svf_stable_under_audio_rate_modulation() {
+        let mut filter = Svf::lowpass24(SAMPLE_RATE, 1000.0, 0.95);
+        let mut noise = Noise::new(Color::White, 1);
+        let incr = 2.0 * PI * 1000.0 / SAMPLE_RATE;
+        let mut peak = 0.0f64;
+        for i in 0..SAMPLE_RATE as usize {
+            let cutoff = 100.0 * (200.0f64).powf(0.5 + 0.5 * (i as f64 * incr).sin());
+            filter.set_cutoff(cutoff);
+            let value = filter.process(noise.process());
+            assert!(value.is_finite());
+            peak = peak.max(value.abs());
+        }
+        // The resonance of the two stages gives a gain of up to 100 at the cutoff frequency
+        assert!(peak < 100.0);
+    }
+}
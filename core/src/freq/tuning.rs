@@ -0,0 +1,378 @@
+//!
+//! Microtuning with Scala scale (.scl) and keyboard mapping (.kbm) files
+//!
+//! See http://www.huygens-fokker.org/scala/scl_format.html and
+//! http://www.huygens-fokker.org/scala/help.htm#mappings
+//!
+
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+pub const NUM_KEYS: usize = 128;
+
+/// Frequency of the A above the middle C for the standard tuning
+pub const DEFAULT_REFERENCE_FREQ: f64 = 440.0;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "I/O error: {}", err),
+            Error::Format(ref msg) => write!(f, "Wrong format: {}", msg),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, Error> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Lines without the comments, which start with an exclamation mark
+fn lines(text: &str) -> Vec<&str> {
+    text.lines().filter(|line| !line.starts_with('!')).collect()
+}
+
+/// First word of a line, the rest of it is ignored
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+/// Pitch in cents of a scale degree, with a dot for cents or as a ratio or an integer
+fn parse_pitch(text: &str) -> Result<f64, Error> {
+    let error = || Error::Format(format!("Invalid pitch {}", text));
+    if text.contains('.') {
+        return text.parse::<f64>().map_err(|_| error());
+    }
+
+    let mut parts = text.splitn(2, '/');
+    let num = parts.next().unwrap_or("").parse::<f64>().map_err(|_| error())?;
+    let den = match parts.next() {
+        Some(den) => den.parse::<f64>().map_err(|_| error())?,
+        None => 1.0,
+    };
+    if num > 0.0 && den > 0.0 {
+        Ok(1200.0 * (num / den).log2())
+    }
+    else {
+        Err(error())
+    }
+}
+
+/// Scale with the pitches of its degrees, the last one being the period, usually the octave
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    cents: Vec<f64>,
+}
+
+impl Scale {
+    pub fn new(description: &str, cents: Vec<f64>) -> Scale {
+        assert!(!cents.is_empty());
+        Scale {
+            description: description.to_string(),
+            cents: cents,
+        }
+    }
+
+    /// Equal temperament with a number of notes per octave
+    pub fn equal_temperament(notes: usize) -> Scale {
+        assert!(notes > 0);
+        let step = 1200.0 / notes as f64;
+        Scale::new(&format!("{}-TET", notes), (1..notes + 1).map(|i| i as f64 * step).collect())
+    }
+
+    pub fn parse(text: &str) -> Result<Scale, Error> {
+        let lines = lines(text);
+        if lines.len() < 2 {
+            return Err(Error::Format("Missing description or number of notes".to_string()));
+        }
+
+        let description = lines[0].trim();
+        let count = first_word(lines[1]).parse::<usize>()
+            .map_err(|_| Error::Format(format!("Invalid number of notes {}", lines[1])))?;
+        if count == 0 || lines.len() < 2 + count {
+            return Err(Error::Format(format!("Expected {} notes", count)));
+        }
+
+        let cents = lines[2..2 + count].iter()
+            .map(|line| parse_pitch(first_word(line)))
+            .collect::<Result<Vec<f64>, Error>>()?;
+        Ok(Scale::new(description, cents))
+    }
+
+    pub fn load(path: &Path) -> Result<Scale, Error> {
+        Scale::parse(&read_file(path)?)
+    }
+
+    /// Number of degrees per period
+    pub fn num_degrees(&self) -> usize {
+        self.cents.len()
+    }
+
+    /// Pitch in cents of a degree, which can be in other periods than the first one
+    pub fn degree_cents(&self, degree: i32) -> f64 {
+        let len = self.cents.len() as i32;
+        let period = degree.div_euclid(len);
+        let index = degree.rem_euclid(len);
+        let cents = if index == 0 { 0.0 } else { self.cents[(index - 1) as usize] };
+        period as f64 * self.cents[self.cents.len() - 1] + cents
+    }
+}
+
+/// Mapping of the keys to the degrees of a scale
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMapping {
+    pub first_key: usize,
+    pub last_key: usize,
+    pub middle_key: usize,             // Key of the first degree of the scale
+    pub reference_key: usize,          // Key tuned to the reference frequency
+    pub reference_freq: f64,
+    pub octave_degree: usize,          // Degree of the formal octave, the scale period when 0
+    pub degrees: Vec<Option<usize>>,   // Degrees of the keys in a repeating pattern, None for the keys not mapped.
+                                       // The degrees follow the keys when it is empty.
+}
+
+impl Default for KeyboardMapping {
+    fn default() -> Self {
+        KeyboardMapping {
+            first_key: 0,
+            last_key: NUM_KEYS - 1,
+            middle_key: 60,
+            reference_key: 69,
+            reference_freq: DEFAULT_REFERENCE_FREQ,
+            octave_degree: 0,
+            degrees: Vec::new(),
+        }
+    }
+}
+
+impl KeyboardMapping {
+    pub fn parse(text: &str) -> Result<KeyboardMapping, Error> {
+        let lines = lines(text);
+        if lines.len() < 7 {
+            return Err(Error::Format("Missing keyboard mapping parameters".to_string()));
+        }
+
+        let value = |index: usize, name: &str| -> Result<usize, Error> {
+            first_word(lines[index]).parse::<usize>()
+                .map_err(|_| Error::Format(format!("Invalid {} {}", name, lines[index])))
+        };
+        let size = value(0, "map size")?;
+        let reference_freq = first_word(lines[5]).parse::<f64>()
+            .map_err(|_| Error::Format(format!("Invalid reference frequency {}", lines[5])))?;
+        if reference_freq <= 0.0 {
+            return Err(Error::Format(format!("Invalid reference frequency {}", lines[5])));
+        }
+
+        // The keys missing at the end of the map are not mapped
+        let mut degrees = Vec::with_capacity(size);
+        for i in 0..size {
+            let degree = match lines.get(7 + i).map(|line| first_word(line)) {
+                None | Some("x") | Some("X") | Some("") => None,
+                Some(word) => Some(word.parse::<usize>()
+                    .map_err(|_| Error::Format(format!("Invalid key mapping {}", word)))?),
+            };
+            degrees.push(degree);
+        }
+
+        Ok(KeyboardMapping {
+            first_key: value(1, "first key")?,
+            last_key: value(2, "last key")?,
+            middle_key: value(3, "middle key")?,
+            reference_key: value(4, "reference key")?,
+            reference_freq: reference_freq,
+            octave_degree: value(6, "octave degree")?,
+            degrees: degrees,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<KeyboardMapping, Error> {
+        KeyboardMapping::parse(&read_file(path)?)
+    }
+
+    /// Degree of the scale of a key, or None when it is not mapped
+    fn key_degree(&self, key: usize, scale: &Scale) -> Option<i32> {
+        if key < self.first_key || key > self.last_key {
+            return None;
+        }
+
+        let offset = key as i32 - self.middle_key as i32;
+        if self.degrees.is_empty() {
+            return Some(offset);
+        }
+
+        // Every repetition of the map is shifted by the degree of the formal octave
+        let size = self.degrees.len() as i32;
+        let octave = if self.octave_degree > 0 { self.octave_degree as i32 } else { scale.num_degrees() as i32 };
+        self.degrees[offset.rem_euclid(size) as usize]
+            .map(|degree| offset.div_euclid(size) * octave + degree as i32)
+    }
+}
+
+/// Frequencies of the keys for a scale and a keyboard mapping
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    scale: Scale,
+    mapping: KeyboardMapping,
+    freqs: [f64; NUM_KEYS],       // 0 for the keys not mapped
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning::new(Scale::equal_temperament(12), KeyboardMapping::default()).unwrap()
+    }
+}
+
+impl Tuning {
+    /// Fails when the reference key is not mapped
+    pub fn new(scale: Scale, mapping: KeyboardMapping) -> Result<Tuning, Error> {
+        let mut tuning = Tuning {
+            scale: scale,
+            mapping: mapping,
+            freqs: [0.0; NUM_KEYS],
+        };
+        tuning.update_freqs()?;
+        Ok(tuning)
+    }
+
+    /// Loads a scale, with a linear mapping from the middle C and the A at 440 Hz without keyboard mapping
+    pub fn load(scale_path: &Path, mapping_path: Option<&Path>) -> Result<Tuning, Error> {
+        let scale = Scale::load(scale_path)?;
+        let mapping = match mapping_path {
+            Some(path) => KeyboardMapping::load(path)?,
+            None => KeyboardMapping::default(),
+        };
+        Tuning::new(scale, mapping)
+    }
+
+    pub fn get_scale(&self) -> &Scale {
+        &self.scale
+    }
+
+    pub fn get_mapping(&self) -> &KeyboardMapping {
+        &self.mapping
+    }
+
+    pub fn get_reference_freq(&self) -> f64 {
+        self.mapping.reference_freq
+    }
+
+    /// Sets the frequency of the reference key, like 432, 440 or 443 Hz for the A.
+    /// The frequencies are computed again from the scale, discarding the keys retuned one by one.
+    pub fn set_reference_freq(&mut self, freq: f64) {
+        assert!(freq > 0.0);
+        self.mapping.reference_freq = freq;
+        self.update_freqs().unwrap();
+    }
+
+    /// Frequency of a key, 0 when it is not mapped or it is not a MIDI key
+    pub fn freq(&self, key: usize) -> f64 {
+        self.freqs.get(key).cloned().unwrap_or(0.0)
+    }
+
+    /// Retunes a single key, 0 to leave it unmapped. The keys that are not MIDI keys are ignored.
+    pub fn set_key_freq(&mut self, key: usize, freq: f64) {
+        assert!(freq >= 0.0);
+        if let Some(key_freq) = self.freqs.get_mut(key) {
+            *key_freq = freq;
+        }
+    }
+
+    pub fn is_mapped(&self, key: usize) -> bool {
+        self.freq(key) > 0.0
+    }
+
+    pub fn freqs(&self) -> &[f64; NUM_KEYS] {
+        &self.freqs
+    }
+
+    fn update_freqs(&mut self) -> Result<(), Error> {
+        let reference = match self.mapping.key_degree(self.mapping.reference_key, &self.scale) {
+            Some(degree) => self.scale.degree_cents(degree),
+            None => return Err(Error::Format(format!("Reference key {} is not mapped", self.mapping.reference_key))),
+        };
+
+        for key in 0..NUM_KEYS {
+            self.freqs[key] = match self.mapping.key_degree(key, &self.scale) {
+                Some(degree) => {
+                    let cents = self.scale.degree_cents(degree) - reference;
+                    self.mapping.reference_freq * (2_f64).powf(cents / 1200.0)
+                },
+                None => 0.0,
+            };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use freq::KEY_FREQ;
+
+    #[test]
+    fn tuning_equal_temperament() {
+        let mut tuning = Tuning::default();
+        for key in 0..NUM_KEYS {
+            assert!((tuning.freq(key) - KEY_FREQ[key]).abs() < 1e-3);
+        }
+
+        tuning.set_reference_freq(432.0);
+        assert_eq!(tuning.freq(69), 432.0);
+        assert!((tuning.freq(81) - 864.0).abs() < 1e-9);
+
+        tuning.set_key_freq(60, 256.0);
+        assert_eq!(tuning.freq(60), 256.0);
+        tuning.set_reference_freq(440.0);
+        assert!((tuning.freq(60) - KEY_FREQ[60]).abs() < 1e-3);
+
+        // The keys out of range do not wrap around
+        tuning.set_key_freq(NUM_KEYS + 60, 100.0);
+        assert!((tuning.freq(60) - KEY_FREQ[60]).abs() < 1e-3);
+        assert_eq!(tuning.freq(NUM_KEYS + 60), 0.0);
+    }
+
+    #[test]
+    fn tuning_scala_files() {
+        let scale = Scale::parse("! just.scl\n!\nJust intonation pentatonic\n 5\n!\n 9/8\n 5/4\n 3/2\n 5/3\n 2\n").unwrap();
+        assert_eq!(scale.description, "Just intonation pentatonic");
+        assert_eq!(scale.num_degrees(), 5);
+        assert!((scale.degree_cents(2) - 386.314).abs() < 1e-3);
+        assert!((scale.degree_cents(-1) + 1200.0 - 884.359).abs() < 1e-3);
+        assert!((Scale::parse("test\n2\n100.0 cents\n1200.\n").unwrap().degree_cents(1) - 100.0).abs() < 1e-12);
+        assert!(Scale::parse("test\n2\n100.0\n").is_err());
+        assert!(Scale::parse("test\n1\n-2/3\n").is_err());
+
+        // Five degrees on the white keys from C, the black keys are not mapped
+        let mapping = KeyboardMapping::parse("! white.kbm\n12\n0\n127\n60\n60\n261.6256\n5\n\
+            0\nx\n1\nx\n2\n3\nx\n4\n")
+            .unwrap();
+        assert_eq!(mapping.degrees.len(), 12);
+        assert_eq!(mapping.degrees[11], None);
+
+        let tuning = Tuning::new(scale, mapping).unwrap();
+        assert!((tuning.freq(60) - 261.6256).abs() < 1e-9);
+        assert!((tuning.freq(64) - 261.6256 * 5.0 / 4.0).abs() < 1e-9);
+        assert!((tuning.freq(72) - 523.2512).abs() < 1e-9);
+        assert!((tuning.freq(55) - 261.6256 / 2.0 * 5.0 / 3.0).abs() < 1e-9);
+        assert!(!tuning.is_mapped(61) && !tuning.is_mapped(59));
+    }
+}
@@ -0,0 +1,335 @@
+//!
+//! Low Frequency Oscillators used as modulation sources
+//!
+//! The rate is either free in Hz or synced to the tempo with a note value,
+//! and the output in [-1, 1] can be delayed and faded in after a reset.
+//!
+
+use std::f64::consts::PI;
+
+use types::{SampleRate, Tempo, DEFAULT_TEMPO};
+use noise::WhiteNoise;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Sine = 0,
+    Triangle,
+    SawUp,
+    SawDown,
+    Square,
+    SampleAndHold,
+    SmoothRandom,
+}
+
+impl Shape {
+    pub fn from_name(name: &str) -> Option<Shape> {
+        match name {
+            "sine" | "sin" => Some(Shape::Sine),
+            "triangle" | "tri" => Some(Shape::Triangle),
+            "saw-up" | "saw" => Some(Shape::SawUp),
+            "saw-down" => Some(Shape::SawDown),
+            "square" => Some(Shape::Square),
+            "sample-hold" | "s&h" => Some(Shape::SampleAndHold),
+            "random" => Some(Shape::SmoothRandom),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timing {
+    Straight,
+    Dotted,         // 3/2 of the note
+    Triplet,        // 2/3 of the note
+}
+
+/// Note value of the period of a synced LFO, from a whole note to a 64th
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteValue {
+    pub denominator: u32,
+    pub timing: Timing,
+}
+
+impl NoteValue {
+    pub fn new(denominator: u32, timing: Timing) -> NoteValue {
+        assert!(denominator.is_power_of_two() && denominator <= 64);
+        NoteValue {
+            denominator: denominator,
+            timing: timing,
+        }
+    }
+
+    /// Parses a note value like 1/4, 1/8d for dotted or 1/16t for triplet
+    pub fn from_name(name: &str) -> Option<NoteValue> {
+        let (value, timing) = if name.ends_with('d') {
+            (&name[..name.len() - 1], Timing::Dotted)
+        }
+        else if name.ends_with('t') {
+            (&name[..name.len() - 1], Timing::Triplet)
+        }
+        else {
+            (name, Timing::Straight)
+        };
+
+        if !value.starts_with("1/") {
+            return None;
+        }
+        match value[2..].parse::<u32>() {
+            Ok(denominator) if denominator.is_power_of_two() && denominator <= 64 => {
+                Some(NoteValue::new(denominator, timing))
+            },
+            _ => None
+        }
+    }
+
+    /// Length in beats, a beat being a quarter note
+    pub fn beats(&self) -> f64 {
+        let beats = 4.0 / self.denominator as f64;
+        match self.timing {
+            Timing::Straight => beats,
+            Timing::Dotted => beats * 1.5,
+            Timing::Triplet => beats * 2.0 / 3.0,
+        }
+    }
+
+    /// Frequency in Hz of a period of this length
+    pub fn frequency(&self, tempo: Tempo) -> f64 {
+        tempo / 60.0 / self.beats()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rate {
+    Free(f64),              // Frequency in Hz
+    Sync(NoteValue),        // Period synced to the tempo
+}
+
+#[derive(Clone, Debug)]
+pub struct Lfo {
+    sample_rate: SampleRate,
+    shape: Shape,
+    rate: Rate,
+    tempo: Tempo,
+    phase_offset: f64,
+    delay: f64,             // Time in seconds before the output starts
+    fade_in: f64,           // Time in seconds of the fade in after the delay
+    incr: f64,
+    phase: f64,             // Position in the period [0, 1)
+    time: f64,              // Samples since the reset
+    previous: f64,          // Random value of the previous period
+    held: f64,              // Random value of the current period
+    noise: WhiteNoise,
+}
+
+impl Lfo {
+    pub fn new(sample_rate: SampleRate, shape: Shape, rate: Rate) -> Lfo {
+        assert!(sample_rate > 0.0);
+        let mut lfo = Lfo {
+            sample_rate: sample_rate,
+            shape: shape,
+            rate: rate,
+            tempo: DEFAULT_TEMPO,
+            phase_offset: 0.0,
+            delay: 0.0,
+            fade_in: 0.0,
+            incr: 0.0,
+            phase: 0.0,
+            time: 0.0,
+            previous: 0.0,
+            held: 0.0,
+            noise: WhiteNoise::default(),
+        };
+        lfo.update_incr();
+        lfo.reset();
+        lfo
+    }
+
+    /// Changes the sample rate keeping the frequency, the phase and the time since the reset
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        assert!(sample_rate > 0.0);
+        self.time *= sample_rate / self.sample_rate;
+        self.sample_rate = sample_rate;
+        self.update_incr();
+    }
+
+    pub fn get_shape(&self) -> Shape {
+        self.shape
+    }
+
+    pub fn set_shape(&mut self, shape: Shape) {
+        self.shape = shape;
+    }
+
+    pub fn get_rate(&self) -> Rate {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: Rate) {
+        if self.rate != rate {
+            self.rate = rate;
+            self.update_incr();
+        }
+    }
+
+    pub fn set_tempo(&mut self, tempo: Tempo) {
+        assert!(tempo > 0.0);
+        if self.tempo != tempo {
+            self.tempo = tempo;
+            self.update_incr();
+        }
+    }
+
+    /// Frequency in Hz, after the tempo sync
+    pub fn get_frequency(&self) -> f64 {
+        match self.rate {
+            Rate::Free(freq) => freq,
+            Rate::Sync(note_value) => note_value.frequency(self.tempo),
+        }
+    }
+
+    /// Sets the phase at the reset, as a fraction of the period. A running LFO is shifted by the difference.
+    pub fn set_phase(&mut self, offset: f64) {
+        let offset = offset - offset.floor();
+        let phase = self.phase + offset - self.phase_offset;
+        self.phase = phase - phase.floor();
+        self.phase_offset = offset;
+    }
+
+    pub fn set_delay(&mut self, delay: f64) {
+        assert!(delay >= 0.0);
+        self.delay = delay;
+    }
+
+    pub fn set_fade_in(&mut self, fade_in: f64) {
+        assert!(fade_in >= 0.0);
+        self.fade_in = fade_in;
+    }
+
+    /// Restarts the period at the phase offset, and the delay and fade in
+    pub fn reset(&mut self) {
+        self.phase = self.phase_offset;
+        self.time = 0.0;
+        self.previous = self.noise.process();
+        self.held = self.noise.process();
+    }
+
+    fn update_incr(&mut self) {
+        self.incr = self.get_frequency() / self.sample_rate;
+    }
+
+    fn value(&self) -> f64 {
+        let p = self.phase;
+        match self.shape {
+            Shape::Sine => (2.0 * PI * p).sin(),
+            Shape::Triangle => {
+                if p < 0.25 { 4.0 * p }
+                else if p < 0.75 { 2.0 - 4.0 * p }
+                else { 4.0 * p - 4.0 }
+            },
+            Shape::SawUp => 2.0 * p - 1.0,
+            Shape::SawDown => 1.0 - 2.0 * p,
+            Shape::Square => if p < 0.5 { 1.0 } else { -1.0 },
+            Shape::SampleAndHold => self.held,
+            Shape::SmoothRandom => {
+                self.previous + (self.held - self.previous) * (1.0 - (PI * p).cos()) / 2.0
+            },
+        }
+    }
+
+    /// Gain of the delay and fade in
+    fn fade_gain(&self) -> f64 {
+        let time = self.time / self.sample_rate - self.delay;
+        if time < 0.0 {
+            0.0
+        }
+        else if time < self.fade_in {
+            time / self.fade_in
+        }
+        else {
+            1.0
+        }
+    }
+
+    pub fn process(&mut self) -> f64 {
+        let value = self.value() * self.fade_gain();
+
+        self.phase += self.incr;
+        if self.phase >= 1.0 {
+            self.phase -= self.phase.floor();
+            self.previous = self.held;
+            self.held = self.noise.process();
+        }
+        self.time += 1.0;
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    #[test]
+    fn lfo_tempo_sync() {
+        let quarter = NoteValue::from_name("1/4").unwrap();
+        assert_eq!(quarter.frequency(120.0), 2.0);
+        assert_eq!(NoteValue::from_name("1/8d").unwrap().beats(), 0.75);
+        assert!((NoteValue::from_name("1/16t").unwrap().beats() - 1.0 / 6.0).abs() < 1e-12);
+        assert_eq!(NoteValue::from_name("1/1").unwrap().beats(), 4.0);
+        assert!(NoteValue::from_name("1/3").is_none());
+        assert!(NoteValue::from_name("1/128").is_none());
+        assert!(NoteValue::from_name("4").is_none());
+
+        let mut lfo = Lfo::new(SAMPLE_RATE, Shape::SawUp, Rate::Sync(quarter));
+        assert_eq!(lfo.get_frequency(), 2.0);
+        lfo.set_tempo(90.0);
+        assert_eq!(lfo.get_frequency(), 1.5);
+    }
+
+    #[test]
+    fn lfo_shapes() {
+        // A period of 4 samples
+        let rate = Rate::Free(SAMPLE_RATE / 4.0);
+        let expected = [
+            (Shape::Sine, [0.0, 1.0, 0.0, -1.0]),
+            (Shape::Triangle, [0.0, 1.0, 0.0, -1.0]),
+            (Shape::SawUp, [-1.0, -0.5, 0.0, 0.5]),
+            (Shape::SawDown, [1.0, 0.5, 0.0, -0.5]),
+            (Shape::Square, [1.0, 1.0, -1.0, -1.0]),
+        ];
+        for &(shape, values) in expected.iter() {
+            let mut lfo = Lfo::new(SAMPLE_RATE, shape, rate);
+            for value in values.iter() {
+                assert!((lfo.process() - value).abs() < 1e-9, "{:?}", shape);
+            }
+        }
+
+        // Starts half way with a phase offset
+        let mut lfo = Lfo::new(SAMPLE_RATE, Shape::SawUp, rate);
+        lfo.set_phase(0.5);
+        assert_eq!(lfo.process(), 0.0);
+
+        // Random values held during a period
+        let mut lfo = Lfo::new(SAMPLE_RATE, Shape::SampleAndHold, rate);
+        let first = lfo.process();
+        for _ in 0..3 {
+            assert_eq!(lfo.process(), first);
+        }
+        assert!(lfo.process() != first);
+    }
+
+    #[test]
+    fn lfo_delay_and_fade_in() {
+        let mut lfo = Lfo::new(SAMPLE_RATE, Shape::Square, Rate::Free(1.0));
+        lfo.set_delay(0.1);
+        lfo.set_fade_in(0.1);
+        let values: Vec<f64> = (0..SAMPLE_RATE as usize / 4).map(|_| lfo.process()).collect();
+        assert_eq!(values[4409], 0.0);
+        assert!((values[6615] - 0.5).abs() < 1e-3);
+        assert_eq!(values[8820], 1.0);
+
+        lfo.reset();
+        assert_eq!(lfo.process(), 0.0);
+    }
+}
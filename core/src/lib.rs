@@ -6,10 +6,26 @@ pub mod types;
 
 pub mod freq;
 
+pub mod complex;
+
+pub mod fft;
+
 pub mod wavetable;
 
+pub mod noise;
+
+pub mod analog;
+
 pub mod oscillator;
 
+pub mod envelope;
+
+pub mod lfo;
+
+pub mod smoothing;
+
 pub mod filter;
 
 pub mod panning;
+
+pub mod oversampling;
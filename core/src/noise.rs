@@ -0,0 +1,212 @@
+//!
+//! Noise generators
+//!
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Used to give a different seed to every generator created by default
+static NEXT_SEED: AtomicUsize = AtomicUsize::new(0x2545F491);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    White = 0,
+    Pink,
+    Brown,
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color::White
+    }
+}
+
+impl Color {
+    pub fn from_name(name: &str) -> Option<Color> {
+        match name {
+            "white" => Some(Color::White),
+            "pink" => Some(Color::Pink),
+            "brown" => Some(Color::Brown),
+            _ => None
+        }
+    }
+}
+
+/// Uniform white noise in [-1, 1) from a xorshift generator
+#[derive(Clone, Debug)]
+pub struct WhiteNoise {
+    state: u32,
+}
+
+impl Default for WhiteNoise {
+    fn default() -> Self {
+        WhiteNoise::new(NEXT_SEED.fetch_add(0x9E3779B9, Ordering::Relaxed) as u32)
+    }
+}
+
+impl WhiteNoise {
+    pub fn new(seed: u32) -> WhiteNoise {
+        WhiteNoise {
+            state: if seed != 0 { seed } else { 1 }
+        }
+    }
+
+    pub fn process(&mut self) -> f64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        (x as f64 / 2147483648.0) - 1.0
+    }
+}
+
+/// Pink noise (-3 dB/octave) using the Paul Kellet's refined filter
+#[derive(Clone, Debug, Default)]
+pub struct PinkNoise {
+    white: WhiteNoise,
+    b: [f64; 7],
+}
+
+impl PinkNoise {
+    pub fn new(seed: u32) -> PinkNoise {
+        PinkNoise {
+            white: WhiteNoise::new(seed),
+            b: [0.0; 7]
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.b = [0.0; 7];
+    }
+
+    pub fn process(&mut self) -> f64 {
+        let white = self.white.process();
+        let b = &mut self.b;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink * 0.11
+    }
+}
+
+/// Brown noise (-6 dB/octave) from leaky integrated white noise
+#[derive(Clone, Debug, Default)]
+pub struct BrownNoise {
+    white: WhiteNoise,
+    last: f64,
+}
+
+impl BrownNoise {
+    pub fn new(seed: u32) -> BrownNoise {
+        BrownNoise {
+            white: WhiteNoise::new(seed),
+            last: 0.0
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.last = 0.0;
+    }
+
+    pub fn process(&mut self) -> f64 {
+        let white = self.white.process();
+        self.last = (self.last + 0.02 * white) / 1.02;
+        self.last * 3.5
+    }
+}
+
+/// Noise generator that can switch between the different colors
+#[derive(Clone, Debug, Default)]
+pub struct Noise {
+    color: Color,
+    white: WhiteNoise,
+    pink: PinkNoise,
+    brown: BrownNoise,
+}
+
+impl Noise {
+    pub fn new(color: Color, seed: u32) -> Noise {
+        Noise {
+            color: color,
+            white: WhiteNoise::new(seed),
+            pink: PinkNoise::new(seed),
+            brown: BrownNoise::new(seed)
+        }
+    }
+
+    pub fn set_color(&mut self, color: Color) {
+        if self.color != color {
+            self.color = color;
+            self.reset();
+        }
+    }
+
+    pub fn get_color(&self) -> Color {
+        self.color
+    }
+
+    pub fn reset(&mut self) {
+        self.pink.reset();
+        self.brown.reset();
+    }
+
+    pub fn process(&mut self) -> f64 {
+        match self.color {
+            Color::White => self.white.process(),
+            Color::Pink => self.pink.process(),
+            Color::Brown => self.brown.process(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NUM_SAMPLES: usize = 100000;
+
+    /// Returns the mean, the peak and the correlation between consecutive samples
+    fn stats(noise: &mut Noise) -> (f64, f64, f64) {
+        let samples: Vec<f64> = (0..NUM_SAMPLES).map(|_| noise.process()).collect();
+        let mean = samples.iter().sum::<f64>() / NUM_SAMPLES as f64;
+        let peak = samples.iter().fold(0.0f64, |peak, value| peak.max(value.abs()));
+        let power = samples.iter().map(|value| (value - mean) * (value - mean)).sum::<f64>();
+        let lag = samples.windows(2).map(|w| (w[0] - mean) * (w[1] - mean)).sum::<f64>();
+        (mean, peak, lag / power)
+    }
+
+    #[test]
+    fn noise_colors() {
+        let (mean, peak, white_corr) = stats(&mut Noise::new(Color::White, 1));
+        assert!(mean.abs() < 0.01);
+        assert!(peak <= 1.0);
+        assert!(white_corr.abs() < 0.02);
+
+        let (mean, peak, pink_corr) = stats(&mut Noise::new(Color::Pink, 1));
+        assert!(mean.abs() < 0.1);
+        assert!(peak <= 1.0);
+
+        let (mean, peak, brown_corr) = stats(&mut Noise::new(Color::Brown, 1));
+        assert!(mean.abs() < 0.3);
+        assert!(peak <= 1.0);
+
+        assert!(white_corr < pink_corr && pink_corr < brown_corr);
+    }
+
+    #[test]
+    fn noise_seeds() {
+        let mut a = Noise::new(Color::White, 7);
+        let mut b = Noise::new(Color::White, 7);
+        let mut c = Noise::default();
+        let mut d = Noise::default();
+        for _ in 0..16 {
+            assert_eq!(a.process(), b.process());
+            assert!(c.process() != d.process());
+        }
+    }
+}
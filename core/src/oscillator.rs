@@ -1,13 +1,50 @@
 use std::f64::consts::PI;
 
 use freq;
-use wavetable::Wavetable;
+use wavetable::{Wavetable, Interpolation};
+use noise::{self, Noise};
+use analog;
+
+/// Source of the oscillator signal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Wavetable,
+    Noise(noise::Color),
+    Analog(analog::Waveform),
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Source::Wavetable
+    }
+}
+
+/// How an oscillator reacts to the phase wrap of the oscillator it is synced to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncMode {
+    Off = 0,
+    Hard,       // Resets the phase
+    Soft,       // Reverses the direction of the phase
+}
+
+impl SyncMode {
+    pub fn from_name(name: &str) -> Option<SyncMode> {
+        match name {
+            "off" => Some(SyncMode::Off),
+            "hard" => Some(SyncMode::Hard),
+            "soft" | "reverse" => Some(SyncMode::Soft),
+            _ => None
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Oscillator {
     is_enabled: bool,
 
+    source: Source,
     wavetable: Wavetable,
+    noise: Noise,
 
     is_free_phase: bool,    // When true, the phase is not reset to the initial_phase, but continues from where it was
     initial_phase: f64,     // The initial phase for the wave in radians
@@ -15,6 +52,8 @@ pub struct Oscillator {
     freq_to_table_incr: f64,
     table_incr: f64,
     table_offset: f64,
+    direction: f64,         // Direction of the phase, it is reversed by the soft sync
+    has_wrapped: bool,      // Whether the phase completed a cycle during the last sample
 
     amplitude: f64,         // Oscillator signal amplitude
     amp_mod: f64,           // Amplitude modulation
@@ -26,18 +65,33 @@ pub struct Oscillator {
 
     frequency: f64,         // Calculated from base_frequency, octaves, semitones and detune
     phase_mod: f64,         // Phase modulation calculated from frequency and freq_mod
+    pitch_mod: f64,         // Pitch modulation as a frequency ratio
+    phase_offset: f64,      // True phase modulation as an offset in the table
+
+    feedback: f64,          // Phase modulation in radians from its own output
+    feedback_history: [f64; 2], // Last two output values, averaged for the feedback
+
+    position: f64,          // Position across the frames of the wavetable [0, 1]
+    position_mod: f64,      // Position modulation added to the position
+
+    pulse_width: f64,       // Pulse width for the analog waveforms (0, 1)
+    pulse_width_mod: f64,   // Pulse width modulation added to the pulse width
 }
 
 impl Default for Oscillator {
     fn default() -> Self {
         Oscillator {
             is_enabled: true,
+            source: Source::default(),
             wavetable: Wavetable::default(),
+            noise: Noise::default(),
             is_free_phase: false,
             initial_phase: 0.0,
             freq_to_table_incr: 0.0,
             table_incr: 0.0,
             table_offset: 0.0,
+            direction: 1.0,
+            has_wrapped: false,
             amplitude: 1.0,
             amp_mod: 1.0,
             base_frequency: 440.0,
@@ -45,7 +99,15 @@ impl Default for Oscillator {
             semitones: 0.0,
             detune: 0.0,
             frequency: 0.0,
-            phase_mod: 0.0
+            phase_mod: 0.0,
+            pitch_mod: 1.0,
+            phase_offset: 0.0,
+            feedback: 0.0,
+            feedback_history: [0.0; 2],
+            position: 0.0,
+            position_mod: 0.0,
+            pulse_width: 0.5,
+            pulse_width_mod: 0.0
         }
     }
 }
@@ -82,6 +144,17 @@ impl Oscillator {
         o
     }
 
+    /// Changes the sample rate keeping the frequency and the phase
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        assert!(sample_rate > 0.0);
+        let freq_to_table_incr = self.wavetable.size() as f64 / sample_rate;
+        if self.freq_to_table_incr > 0.0 {
+            self.phase_mod *= freq_to_table_incr / self.freq_to_table_incr;
+        }
+        self.freq_to_table_incr = freq_to_table_incr;
+        self.update_frequency();
+    }
+
     fn init(&mut self) {
         self.reset_phase();
         self.update_frequency();
@@ -91,6 +164,9 @@ impl Oscillator {
         if !self.is_free_phase {
             self.reset_phase();
         }
+        self.direction = 1.0;
+        self.has_wrapped = false;
+        self.feedback_history = [0.0; 2];
     }
 
     fn reset_phase(&mut self) {
@@ -131,6 +207,42 @@ impl Oscillator {
         self.initial_phase
     }
 
+    pub fn set_source(&mut self, source: Source) {
+        if let Source::Noise(color) = source {
+            self.noise.set_color(color);
+        }
+        self.source = source;
+    }
+
+    pub fn get_source(&self) -> Source {
+        self.source
+    }
+
+    /// Replaces the wavetable keeping the current phase and frequency
+    pub fn set_wavetable(&mut self, wavetable: Wavetable) {
+        let size_ratio = wavetable.size() as f64 / self.wavetable.size() as f64;
+        let interpolation = self.wavetable.get_interpolation();
+        self.wavetable = wavetable;
+        self.wavetable.set_interpolation(interpolation);
+        self.freq_to_table_incr *= size_ratio;
+        self.table_offset *= size_ratio;
+        self.phase_mod *= size_ratio;
+        self.phase_offset *= size_ratio;
+        self.update_frequency();
+    }
+
+    pub fn get_wavetable(&self) -> &Wavetable {
+        &self.wavetable
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.wavetable.set_interpolation(interpolation);
+    }
+
+    pub fn get_interpolation(&self) -> Interpolation {
+        self.wavetable.get_interpolation()
+    }
+
     pub fn set_octaves(&mut self, octaves: f64) {
         self.octaves = octaves;
         self.update_frequency();
@@ -179,6 +291,66 @@ impl Oscillator {
         self.phase_mod = value * self.freq_to_table_incr;
     }
 
+    /// Modulates the phase directly by an amount of radians, as in DX7 style FM
+    pub fn set_phase_modulation(&mut self, radians: f64) {
+        self.phase_offset = radians / (2.0 * PI) * self.wavetable.size() as f64;
+    }
+
+    /// Amount of phase modulation in radians from its own output
+    pub fn set_feedback(&mut self, feedback: f64) {
+        self.feedback = feedback;
+    }
+
+    pub fn get_feedback(&self) -> f64 {
+        self.feedback
+    }
+
+    /// Modulates the pitch by a number of semitones (they can be fractional)
+    pub fn set_pitch_modulation(&mut self, semitones: f64) {
+        self.pitch_mod = if semitones != 0.0 { (2_f64).powf(semitones / 12.0) } else { 1.0 };
+    }
+
+    pub fn set_position(&mut self, position: f64) {
+        self.position = position;
+    }
+
+    pub fn get_position(&self) -> f64 {
+        self.position
+    }
+
+    /// Modulates the position across the frames. The result is clamped to [0, 1]
+    pub fn set_position_modulation(&mut self, value: f64) {
+        self.position_mod = value;
+    }
+
+    pub fn set_pulse_width(&mut self, pulse_width: f64) {
+        self.pulse_width = pulse_width;
+    }
+
+    pub fn get_pulse_width(&self) -> f64 {
+        self.pulse_width
+    }
+
+    /// Modulates the pulse width of the analog waveforms. The result is limited to (0, 1)
+    pub fn set_pulse_width_modulation(&mut self, value: f64) {
+        self.pulse_width_mod = value;
+    }
+
+    /// Whether the phase completed a cycle during the last call to process.
+    /// It is the event other oscillators can sync to.
+    pub fn has_wrapped(&self) -> bool {
+        self.has_wrapped
+    }
+
+    /// Syncs the phase to the phase wrap of another oscillator
+    pub fn sync(&mut self, mode: SyncMode) {
+        match mode {
+            SyncMode::Off => {},
+            SyncMode::Hard => self.reset_phase(),
+            SyncMode::Soft => self.direction = -self.direction,
+        }
+    }
+
     pub fn process(&mut self) -> f64 {
         let wt_size = self.wavetable.size() as f64;
         if self.table_offset < 0.0 {
@@ -189,13 +361,126 @@ impl Oscillator {
             // self.table_offset -= wt_size;
         }
 
+        let incr = (self.table_incr * self.pitch_mod + self.phase_mod) * self.direction;
+
+        // The feedback averages the last two samples to avoid the hunting oscillation
+        let feedback = self.feedback * 0.5 * (self.feedback_history[0] + self.feedback_history[1]);
+        let offset = self.table_offset + self.phase_offset + feedback / (2.0 * PI) * wt_size;
+
         let mut value = 0.0f64;
         if self.is_enabled && self.amplitude > 0.0 {
-            value = self.amplitude * self.amp_mod * self.wavetable.value(self.table_offset);
+            let source_value = match self.source {
+                Source::Wavetable => self.band_limited_value(offset, incr),
+                Source::Noise(_) => self.noise.process(),
+                Source::Analog(waveform) => {
+                    let phase = (offset / wt_size).rem_euclid(1.0);
+                    let pulse_width = self.pulse_width + self.pulse_width_mod;
+                    analog::value(waveform, phase, incr / wt_size, pulse_width)
+                },
+            };
+            value = self.amplitude * self.amp_mod * source_value;
         }
+        self.feedback_history = [value, self.feedback_history[0]];
 
-        self.table_offset += self.table_incr + self.phase_mod;
+        self.table_offset += incr;
+        self.has_wrapped = self.table_offset >= wt_size || self.table_offset < 0.0;
 
         value
     }
+
+    /// Crossfades between the two band limited levels of the wavetable that suit the table increment
+    fn band_limited_value(&self, offset: f64, incr: f64) -> f64 {
+        let position = self.position + self.position_mod;
+        if self.wavetable.num_levels() == 1 {
+            return self.wavetable.position_value(position, 0, offset);
+        }
+
+        let level = self.wavetable.level_for_incr(incr);
+        let index = level.floor();
+        let fraction = level - index;
+        let index = index as usize;
+
+        let value = self.wavetable.position_value(position, index, offset);
+        if fraction > 0.0 {
+            let next_value = self.wavetable.position_value(position, index + 1, offset);
+            value + (next_value - value) * fraction
+        }
+        else {
+            value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analog::Waveform;
+
+    const SAMPLE_RATE: f64 = 1000.0;
+
+    fn saw(freq: f64) -> Oscillator {
+        let mut osc = Oscillator::from_sample_rate(SAMPLE_RATE);
+        osc.set_source(Source::Analog(Waveform::Saw));
+        osc.set_base_frequency(freq);
+        osc
+    }
+
+    #[test]
+    fn phase_wraps_once_per_cycle() {
+        let mut osc = saw(10.0);
+        let wraps = (0..1000).filter(|_| { osc.process(); osc.has_wrapped() }).count();
+        assert_eq!(wraps, 10);
+    }
+
+    #[test]
+    fn hard_and_soft_sync() {
+        let mut master = saw(10.0);
+        let mut hard = saw(13.0);
+        let mut soft = saw(13.0);
+        let mut hard_values = Vec::new();
+        let mut soft_values = Vec::new();
+        for _ in 0..300 {
+            master.process();
+            if master.has_wrapped() {
+                hard.sync(SyncMode::Hard);
+                soft.sync(SyncMode::Soft);
+            }
+            hard_values.push(hard.process());
+            soft_values.push(soft.process());
+        }
+        // The slave restarts its cycle with every cycle of the master, which wraps at the sample 99
+        assert_eq!(&hard_values[99..199], &hard_values[199..299]);
+        assert!((hard_values[99] - hard_values[0]).abs() < 1e-9);
+        // The soft synced phase goes backwards after the first cycle of the master
+        assert!(soft_values[110] < soft_values[109]);
+        assert!(soft_values[210] > soft_values[209]);
+    }
+
+    #[test]
+    fn phase_modulation_and_feedback() {
+        // A sine carrier modulated by the phase of a sine modulator, compared with the formula
+        let (carrier_freq, mod_freq, index) = (10.0, 30.0, 2.0);
+        let mut osc = Oscillator::from_sample_rate(SAMPLE_RATE);
+        osc.set_base_frequency(carrier_freq);
+        for i in 0..200 {
+            let t = i as f64 / SAMPLE_RATE;
+            let modulator = index * (2.0 * PI * mod_freq * t).sin();
+            osc.set_phase_modulation(modulator);
+            let expected = (2.0 * PI * carrier_freq * t + modulator).sin();
+            assert!((osc.process() - expected).abs() < 1e-3);
+        }
+
+        // The feedback modulates the phase with the average of the last two outputs
+        let feedback = 1.5;
+        let mut osc = Oscillator::from_sample_rate(SAMPLE_RATE);
+        osc.set_base_frequency(carrier_freq);
+        osc.set_feedback(feedback);
+        let mut history = [0.0; 2];
+        for i in 0..200 {
+            let t = i as f64 / SAMPLE_RATE;
+            let expected = (2.0 * PI * carrier_freq * t + feedback * 0.5 * (history[0] + history[1])).sin();
+            history = [expected, history[0]];
+            assert!((osc.process() - expected).abs() < 1e-3);
+        }
+    }
 }
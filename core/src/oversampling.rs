@@ -0,0 +1,347 @@
+//!
+//! Oversampling by 2, 4 or 8 with cascaded polyphase halfband filters
+//!
+//! Every stage doubles or halves the sample rate with a linear phase FIR halfband filter.
+//! Half of its taps are zero, and the centre one is 1/2, so each phase of the polyphase filter
+//! is either a short dot product or a plain delay.
+//!
+
+use std::f64::consts::PI;
+
+/// Number of taps of the halfband filters, of the form 4 k + 3 so the centre tap is odd
+const NUM_TAPS: usize = 47;
+
+/// Centre of the halfband filters, it is also their delay in samples at the higher rate
+const CENTER: usize = (NUM_TAPS - 1) / 2;
+
+/// Beta of the Kaiser window, for about 80 dB of attenuation in the stop band
+const KAISER_BETA: f64 = 8.0;
+
+/// Supported oversampling factors
+pub static FACTORS: [usize; 4] = [1, 2, 4, 8];
+
+pub fn is_valid_factor(factor: usize) -> bool {
+    FACTORS.contains(&factor)
+}
+
+/// Modified Bessel function of the first kind and order 0
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+/// Taps at even positions of a Kaiser windowed halfband filter, normalised to sum 1/2.
+/// The odd taps are zero except the centre one, which is 1/2.
+fn halfband_coeffs() -> Vec<f64> {
+    let last = (NUM_TAPS - 1) as f64;
+    let mut coeffs: Vec<f64> = (0..NUM_TAPS).filter(|n| n % 2 == 0).map(|n| {
+        let x = (n as f64 - CENTER as f64) / 2.0;
+        let sinc = (PI * x).sin() / (PI * x);
+        let r = 2.0 * n as f64 / last - 1.0;
+        let window = bessel_i0(KAISER_BETA * (1.0 - r * r).sqrt()) / bessel_i0(KAISER_BETA);
+        0.5 * sinc * window
+    }).collect();
+
+    let sum: f64 = coeffs.iter().sum();
+    for coeff in coeffs.iter_mut() {
+        *coeff *= 0.5 / sum;
+    }
+    coeffs
+}
+
+/// Last input values, stored twice so the most recent ones are always contiguous
+#[derive(Clone, Debug)]
+struct History {
+    buffer: Vec<f64>,
+    pos: usize,
+}
+
+impl History {
+    fn new(len: usize) -> History {
+        History {
+            buffer: vec![0.0; 2 * len],
+            pos: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.buffer.len() / 2
+    }
+
+    fn push(&mut self, value: f64) {
+        let len = self.len();
+        self.pos = (self.pos + 1) % len;
+        self.buffer[self.pos] = value;
+        self.buffer[self.pos + len] = value;
+    }
+
+    /// Value pushed a number of samples ago, 0 for the last one
+    fn get(&self, delay: usize) -> f64 {
+        self.buffer[self.pos + self.len() - delay]
+    }
+
+    /// Dot product of the coefficients with the values, from the last one to the older ones
+    fn dot(&self, coeffs: &[f64]) -> f64 {
+        let newest = self.pos + self.len();
+        coeffs.iter().enumerate().fold(0.0, |sum, (i, coeff)| sum + coeff * self.buffer[newest - i])
+    }
+
+    fn reset(&mut self) {
+        for value in self.buffer.iter_mut() {
+            *value = 0.0;
+        }
+    }
+}
+
+/// Doubles the sample rate
+#[derive(Clone, Debug)]
+struct UpStage {
+    coeffs: Vec<f64>,
+    input: History,
+}
+
+impl UpStage {
+    fn new(coeffs: &[f64]) -> UpStage {
+        UpStage {
+            coeffs: coeffs.to_vec(),
+            input: History::new(coeffs.len()),
+        }
+    }
+
+    /// Two output values for an input value, with a gain of 2 to make up for the inserted zeros
+    fn process(&mut self, signal: f64) -> (f64, f64) {
+        self.input.push(signal);
+        (2.0 * self.input.dot(&self.coeffs), self.input.get((CENTER - 1) / 2))
+    }
+}
+
+/// Halves the sample rate
+#[derive(Clone, Debug)]
+struct DownStage {
+    coeffs: Vec<f64>,
+    even: History,
+    odd: History,
+}
+
+impl DownStage {
+    fn new(coeffs: &[f64]) -> DownStage {
+        DownStage {
+            coeffs: coeffs.to_vec(),
+            even: History::new(coeffs.len()),
+            odd: History::new((CENTER + 1) / 2 + 1),
+        }
+    }
+
+    fn process(&mut self, first: f64, second: f64) -> f64 {
+        self.even.push(first);
+        self.odd.push(second);
+        self.even.dot(&self.coeffs) + 0.5 * self.odd.get((CENTER + 1) / 2)
+    }
+}
+
+/// Number of halving or doubling stages for an oversampling factor
+fn num_stages(factor: usize) -> usize {
+    assert!(is_valid_factor(factor), "Invalid oversampling factor {}", factor);
+    factor.trailing_zeros() as usize
+}
+
+/// Delay of the cascaded stages of an up or down sampler, in samples at the base rate
+fn stages_latency(factor: usize) -> f64 {
+    CENTER as f64 * (1.0 - 1.0 / factor as f64)
+}
+
+/// Converts a signal to a sample rate multiplied by a factor
+#[derive(Clone, Debug)]
+pub struct Upsampler {
+    factor: usize,
+    stages: Vec<UpStage>,
+    scratch: Vec<f64>,
+}
+
+impl Upsampler {
+    pub fn new(factor: usize) -> Upsampler {
+        let coeffs = halfband_coeffs();
+        Upsampler {
+            factor: factor,
+            stages: (0..num_stages(factor)).map(|_| UpStage::new(&coeffs)).collect(),
+            scratch: vec![0.0; factor],
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Delay in samples at the base rate
+    pub fn latency(&self) -> f64 {
+        stages_latency(self.factor)
+    }
+
+    pub fn reset(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.input.reset();
+        }
+    }
+
+    /// Writes as many values as the factor at the higher rate for an input value
+    pub fn process(&mut self, signal: f64, output: &mut [f64]) {
+        assert_eq!(output.len(), self.factor);
+        output[0] = signal;
+        let mut len = 1;
+        for stage in self.stages.iter_mut() {
+            self.scratch[..len].copy_from_slice(&output[..len]);
+            for i in 0..len {
+                let (first, second) = stage.process(self.scratch[i]);
+                output[2 * i] = first;
+                output[2 * i + 1] = second;
+            }
+            len *= 2;
+        }
+    }
+}
+
+/// Converts a signal back to a sample rate divided by a factor, removing the frequencies above its Nyquist frequency
+#[derive(Clone, Debug)]
+pub struct Downsampler {
+    factor: usize,
+    stages: Vec<DownStage>,
+    scratch: Vec<f64>,
+}
+
+impl Downsampler {
+    pub fn new(factor: usize) -> Downsampler {
+        let coeffs = halfband_coeffs();
+        Downsampler {
+            factor: factor,
+            stages: (0..num_stages(factor)).map(|_| DownStage::new(&coeffs)).collect(),
+            scratch: vec![0.0; factor],
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Delay in samples at the base rate
+    pub fn latency(&self) -> f64 {
+        stages_latency(self.factor)
+    }
+
+    pub fn reset(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.even.reset();
+            stage.odd.reset();
+        }
+    }
+
+    /// One value at the base rate for as many values as the factor at the higher rate
+    pub fn process(&mut self, input: &[f64]) -> f64 {
+        assert_eq!(input.len(), self.factor);
+        self.scratch.copy_from_slice(input);
+        let mut len = self.factor;
+        for stage in self.stages.iter_mut() {
+            len /= 2;
+            for i in 0..len {
+                self.scratch[i] = stage.process(self.scratch[2 * i], self.scratch[2 * i + 1]);
+            }
+        }
+        self.scratch[0]
+    }
+}
+
+/// Runs a processing at a multiple of the sample rate
+#[derive(Clone, Debug)]
+pub struct Oversampler {
+    up: Upsampler,
+    down: Downsampler,
+    buffer: Vec<f64>,
+}
+
+impl Oversampler {
+    pub fn new(factor: usize) -> Oversampler {
+        Oversampler {
+            up: Upsampler::new(factor),
+            down: Downsampler::new(factor),
+            buffer: vec![0.0; factor],
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        self.up.factor()
+    }
+
+    /// Delay of the up and down sampling, in samples at the base rate
+    pub fn latency(&self) -> f64 {
+        self.up.latency() + self.down.latency()
+    }
+
+    pub fn reset(&mut self) {
+        self.up.reset();
+        self.down.reset();
+    }
+
+    /// Processes a value with a function called once per value at the higher rate
+    pub fn process<F: FnMut(f64) -> f64>(&mut self, signal: f64, mut f: F) -> f64 {
+        self.up.process(signal, &mut self.buffer);
+        for value in self.buffer.iter_mut() {
+            *value = f(*value);
+        }
+        self.down.process(&self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 44100.0;
+
+    fn sine(freq: f64, sample_rate: f64, index: usize) -> f64 {
+        (2.0 * PI * freq / sample_rate * index as f64).sin()
+    }
+
+    #[test]
+    fn oversampler_round_trip() {
+        for &factor in FACTORS.iter() {
+            let mut oversampler = Oversampler::new(factor);
+            let latency = oversampler.latency();
+            assert_eq!(latency, 2.0 * CENTER as f64 * (1.0 - 1.0 / factor as f64));
+
+            // A tone well below the Nyquist frequency comes out delayed by the latency
+            let freq = 1000.0;
+            for i in 0..2000 {
+                let value = oversampler.process(sine(freq, SAMPLE_RATE, i), |x| x);
+                if i >= 200 {
+                    let expected = (2.0 * PI * freq / SAMPLE_RATE * (i as f64 - latency)).sin();
+                    assert!((value - expected).abs() < 1e-3, "{} {} {}", factor, value, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn downsampler_rejects_aliases() {
+        let mut downsampler = Downsampler::new(4);
+        let high_rate = 4.0 * SAMPLE_RATE;
+        let mut input = [0.0; 4];
+        let mut peak = 0.0f64;
+        for i in 0..4000 {
+            // Would alias to 14.1 kHz without filtering
+            for j in 0..4 {
+                input[j] = sine(30000.0, high_rate, 4 * i + j);
+            }
+            let value = downsampler.process(&input);
+            if i >= 100 {
+                peak = peak.max(value.abs());
+            }
+        }
+        assert!(peak < 1e-3, "{}", peak);
+    }
+}
@@ -1,45 +1,222 @@
 //!
-//! Non linear panning using a wavetable
+//! Panning of mono signals with selectable laws, and balance and width of stereo signals
 //!
+//! The gains are computed when the value changes, so there is no table to share between the instances.
+//!
+
+use std::f64::consts::FRAC_PI_4;
 
-use wavetable::{self, Wavetable};
+/// Gain of the sides for a position of a mono signal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Law {
+    Linear,             // 0 dB at the centre, the opposite side fades out linearly
+    ConstantPower,      // -3 dB at the centre
+    Compromise,         // -4.5 dB at the centre, between constant power and -6 dB
+    Minus6dB,           // -6 dB at the centre, the gains sum to 1
+}
 
-#[derive(Debug)]
+impl Default for Law {
+    fn default() -> Self {
+        Law::ConstantPower
+    }
+}
+
+impl Law {
+    pub fn from_name(name: &str) -> Option<Law> {
+        match name {
+            "linear" | "0db" => Some(Law::Linear),
+            "constant-power" | "-3db" => Some(Law::ConstantPower),
+            "compromise" | "-4.5db" => Some(Law::Compromise),
+            "-6db" => Some(Law::Minus6dB),
+            _ => None
+        }
+    }
+
+    /// Gains of the left and right sides for a value in [-1, 1], from left to right
+    pub fn gains(&self, value: f64) -> (f64, f64) {
+        let value = value.max(-1.0).min(1.0);
+        match *self {
+            Law::Linear => ((1.0 - value).min(1.0), (1.0 + value).min(1.0)),
+            Law::ConstantPower => {
+                let angle = (1.0 + value) * FRAC_PI_4;
+                (angle.cos(), angle.sin())
+            },
+            Law::Compromise => {
+                let angle = (1.0 + value) * FRAC_PI_4;
+                (((1.0 - value) / 2.0 * angle.cos()).sqrt(), ((1.0 + value) / 2.0 * angle.sin()).sqrt())
+            },
+            Law::Minus6dB => ((1.0 - value) / 2.0, (1.0 + value) / 2.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Panning {
+    law: Law,
     left: f64,
     right: f64,
     value: f64,
-    wavetable: Wavetable
 }
 
 impl Default for Panning {
     fn default() -> Self {
-        Panning {
-            left: 0.5,
-            right: 0.5,
-            value: 0.0,
-            wavetable: Wavetable::from_stock(wavetable::Stock::Sin)
-        }
+        Panning::with_law(Law::default(), 0.0)
     }
 }
 
 impl Panning {
     pub fn new(value: f64) -> Panning {
-        let mut p = Panning::default();
-        p.set_value(value);
-        p
+        Panning::with_law(Law::default(), value)
     }
 
+    pub fn with_law(law: Law, value: f64) -> Panning {
+        let (left, right) = law.gains(value);
+        Panning {
+            law: law,
+            left: left,
+            right: right,
+            value: value,
+        }
+    }
+
+    pub fn get_law(&self) -> Law {
+        self.law
+    }
+
+    pub fn set_law(&mut self, law: Law) {
+        if self.law != law {
+            self.law = law;
+            self.update_gains();
+        }
+    }
+
+    pub fn get_value(&self) -> f64 {
+        self.value
+    }
+
+    /// Sets the position, from -1 for left to 1 for right
     pub fn set_value(&mut self, value: f64) {
         if self.value != value {
-            let wt_size = self.wavetable.size() as f64;
-            self.left = self.wavetable.value(((1.0 - value) / 8.0) * wt_size);
-            self.right = self.wavetable.value(((1.0 + value) / 8.0) * wt_size);
             self.value = value;
+            self.update_gains();
         }
     }
 
+    fn update_gains(&mut self) {
+        let (left, right) = self.law.gains(self.value);
+        self.left = left;
+        self.right = right;
+    }
+
     pub fn process(&self, signal: f64) -> (f64, f64) {
         (signal * self.left, signal * self.right)
     }
 }
+
+/// Width and balance of a stereo signal
+#[derive(Clone, Debug)]
+pub struct StereoImage {
+    width: f64,
+    balance: f64,
+    left: f64,
+    right: f64,
+}
+
+impl Default for StereoImage {
+    fn default() -> Self {
+        StereoImage::new(1.0, 0.0)
+    }
+}
+
+impl StereoImage {
+    pub fn new(width: f64, balance: f64) -> StereoImage {
+        let mut image = StereoImage {
+            width: width,
+            balance: 0.0,
+            left: 1.0,
+            right: 1.0,
+        };
+        image.set_balance(balance);
+        image
+    }
+
+    pub fn get_width(&self) -> f64 {
+        self.width
+    }
+
+    /// Sets the width, 0 for mono, 1 to keep the signal unchanged and above to widen it
+    pub fn set_width(&mut self, width: f64) {
+        assert!(width >= 0.0);
+        self.width = width;
+    }
+
+    pub fn get_balance(&self) -> f64 {
+        self.balance
+    }
+
+    /// Sets the balance, from -1 to keep only the left side to 1 to keep only the right side.
+    /// The centre keeps both sides unchanged.
+    pub fn set_balance(&mut self, balance: f64) {
+        let (left, right) = Law::Linear.gains(balance);
+        self.balance = balance;
+        self.left = left;
+        self.right = right;
+    }
+
+    pub fn process(&self, left: f64, right: f64) -> (f64, f64) {
+        let mid = (left + right) / 2.0;
+        let side = (left - right) / 2.0 * self.width;
+        ((mid + side) * self.left, (mid - side) * self.right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db(gain: f64) -> f64 {
+        20.0 * gain.log10()
+    }
+
+    #[test]
+    fn panning_laws() {
+        let laws = [(Law::Linear, 0.0), (Law::ConstantPower, -3.01), (Law::Compromise, -4.52), (Law::Minus6dB, -6.02)];
+        for &(law, centre) in laws.iter() {
+            let panning = Panning::with_law(law, 0.0);
+            let (left, right) = panning.process(1.0);
+            assert!((left - right).abs() < 1e-12);
+            assert!((db(left) - centre).abs() < 0.01, "{:?} {}", law, db(left));
+
+            // Hard left and right
+            let (left, right) = law.gains(-1.0);
+            assert!((left - 1.0).abs() < 1e-12 && right.abs() < 1e-12, "{:?}", law);
+            let (left, right) = law.gains(1.0);
+            assert!(left.abs() < 1e-12 && (right - 1.0).abs() < 1e-12, "{:?}", law);
+        }
+
+        // The power is constant with the constant power law
+        let mut panning = Panning::new(0.0);
+        for &value in [-0.7, -0.2, 0.4, 0.9].iter() {
+            panning.set_value(value);
+            let (left, right) = panning.process(1.0);
+            assert!((left * left + right * right - 1.0).abs() < 1e-12);
+        }
+
+        panning.set_law(Law::Minus6dB);
+        let (left, right) = panning.process(1.0);
+        assert!((left - 0.05).abs() < 1e-12 && (right - 0.95).abs() < 1e-12);
+    }
+
+    #[test]
+    fn stereo_image() {
+        let mut image = StereoImage::default();
+        assert_eq!(image.process(0.5, -0.25), (0.5, -0.25));
+
+        image.set_width(0.0);
+        assert_eq!(image.process(0.5, -0.25), (0.125, 0.125));
+
+        image.set_width(1.0);
+        image.set_balance(0.5);
+        assert_eq!(image.process(1.0, 1.0), (0.5, 1.0));
+    }
+}
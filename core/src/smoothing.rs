@@ -0,0 +1,195 @@
+//!
+//! Smoothing of the parameters changed while playing, to avoid zipper noise
+//!
+//! A linear ramp reaches the new value in exactly the ramp time, a one pole filter
+//! reaches 99% of it in the ramp time and follows continuous changes more softly.
+//!
+
+use types::SampleRate;
+
+/// Distance to the target under which a one pole ramp is finished
+const SETTLED: f64 = 1e-9;
+
+/// Fraction of a step left by a one pole ramp at the end of the ramp time
+const ONE_POLE_REMAINING: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ramp {
+    Linear,
+    OnePole,
+}
+
+#[derive(Clone, Debug)]
+pub struct Smoother {
+    sample_rate: SampleRate,
+    ramp: Ramp,
+    time: f64,          // Ramp time in seconds
+    value: f64,
+    target: f64,
+    incr: f64,          // Increment per sample of the linear ramp
+    steps: usize,       // Samples left in the linear ramp
+    coeff: f64,         // Coefficient of the one pole filter
+}
+
+impl Smoother {
+    pub fn new(sample_rate: SampleRate, ramp: Ramp, time: f64, value: f64) -> Smoother {
+        assert!(sample_rate > 0.0);
+        let mut smoother = Smoother {
+            sample_rate: sample_rate,
+            ramp: ramp,
+            time: -1.0,
+            value: value,
+            target: value,
+            incr: 0.0,
+            steps: 0,
+            coeff: 0.0,
+        };
+        smoother.set_time(time);
+        smoother
+    }
+
+    pub fn linear(sample_rate: SampleRate, time: f64, value: f64) -> Smoother {
+        Smoother::new(sample_rate, Ramp::Linear, time, value)
+    }
+
+    pub fn one_pole(sample_rate: SampleRate, time: f64, value: f64) -> Smoother {
+        Smoother::new(sample_rate, Ramp::OnePole, time, value)
+    }
+
+    /// Sets the ramp time in seconds, used from the next target for the linear ramps
+    pub fn set_time(&mut self, time: f64) {
+        assert!(time >= 0.0);
+        if self.time != time {
+            self.time = time;
+            self.update_coeff();
+        }
+    }
+
+    /// Changes the sample rate, a linear ramp in progress keeps its remaining time
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        assert!(sample_rate > 0.0);
+        if self.steps > 0 {
+            self.steps = ((self.steps as f64 * sample_rate / self.sample_rate).round() as usize).max(1);
+            self.incr = (self.target - self.value) / self.steps as f64;
+        }
+        self.sample_rate = sample_rate;
+        self.update_coeff();
+    }
+
+    fn update_coeff(&mut self) {
+        let samples = self.time * self.sample_rate;
+        self.coeff = if samples >= 1.0 { ONE_POLE_REMAINING.powf(1.0 / samples) } else { 0.0 };
+    }
+
+    pub fn get_time(&self) -> f64 {
+        self.time
+    }
+
+    /// Starts a ramp from the current value to a new value
+    pub fn set_target(&mut self, target: f64) {
+        if self.target != target {
+            self.target = target;
+            if self.ramp == Ramp::Linear {
+                self.steps = (self.time * self.sample_rate).round() as usize;
+                if self.steps == 0 {
+                    self.value = target;
+                }
+                else {
+                    self.incr = (target - self.value) / self.steps as f64;
+                }
+            }
+        }
+    }
+
+    pub fn get_target(&self) -> f64 {
+        self.target
+    }
+
+    /// Jumps to a value without any ramp
+    pub fn set_value(&mut self, value: f64) {
+        self.value = value;
+        self.target = value;
+        self.steps = 0;
+    }
+
+    pub fn get_value(&self) -> f64 {
+        self.value
+    }
+
+    /// Finishes the current ramp at once
+    pub fn skip(&mut self) {
+        let target = self.target;
+        self.set_value(target);
+    }
+
+    pub fn is_smoothing(&self) -> bool {
+        self.value != self.target
+    }
+
+    /// Value for the next sample
+    pub fn process(&mut self) -> f64 {
+        if self.value != self.target {
+            match self.ramp {
+                Ramp::Linear => {
+                    self.steps = self.steps.saturating_sub(1);
+                    self.value = if self.steps == 0 { self.target } else { self.value + self.incr };
+                },
+                Ramp::OnePole => {
+                    self.value = self.target + (self.value - self.target) * self.coeff;
+                    if (self.value - self.target).abs() < SETTLED {
+                        self.value = self.target;
+                    }
+                },
+            }
+        }
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 1000.0;
+
+    #[test]
+    fn smoother_linear() {
+        let mut smoother = Smoother::linear(SAMPLE_RATE, 0.01, 1.0);
+        smoother.set_target(2.0);
+        for i in 1..10 {
+            assert!((smoother.process() - (1.0 + i as f64 / 10.0)).abs() < 1e-12);
+        }
+        assert_eq!(smoother.process(), 2.0);
+        assert!(!smoother.is_smoothing());
+
+        // A new target starts from the current value
+        smoother.set_target(0.0);
+        smoother.process();
+        smoother.set_target(1.0);
+        assert!(smoother.process() < 2.0);
+
+        smoother.set_value(5.0);
+        assert_eq!(smoother.process(), 5.0);
+    }
+
+    #[test]
+    fn smoother_one_pole() {
+        let mut smoother = Smoother::one_pole(SAMPLE_RATE, 0.1, 0.0);
+        smoother.set_target(1.0);
+        let mut value = 0.0;
+        for _ in 0..100 {
+            let next = smoother.process();
+            assert!(next > value && next < 1.0);
+            value = next;
+        }
+        assert!((value - 0.99).abs() < 1e-9);
+
+        smoother.skip();
+        assert_eq!(smoother.get_value(), 1.0);
+
+        // Without ramp time the value follows the target at once
+        let mut smoother = Smoother::one_pole(SAMPLE_RATE, 0.0, 0.0);
+        smoother.set_target(1.0);
+        assert_eq!(smoother.process(), 1.0);
+    }
+}
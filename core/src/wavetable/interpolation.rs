@@ -0,0 +1,133 @@
+//!
+//! Interpolation of values between the samples of a periodic table
+//!
+//! The polynomial interpolators are based on
+//! "Polynomial Interpolators for High-Quality Resampling of Oversampled Audio" by Olli Niemitalo
+//!
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    None = 0,   // Truncates to the previous sample
+    Linear,
+    Hermite,    // 4-point, 3rd-order Hermite
+    Lagrange,   // 4-point, 3rd-order Lagrange
+    Optimal,    // 4-point, 3rd-order optimal polynomial for 2x oversampled data
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        Interpolation::Linear
+    }
+}
+
+impl Interpolation {
+    pub fn from_name(name: &str) -> Option<Interpolation> {
+        match name {
+            "none" => Some(Interpolation::None),
+            "linear" => Some(Interpolation::Linear),
+            "hermite" | "cubic" => Some(Interpolation::Hermite),
+            "lagrange" => Some(Interpolation::Lagrange),
+            "optimal" => Some(Interpolation::Optimal),
+            _ => None
+        }
+    }
+}
+
+/// Interpolates the value at a fractional offset of a periodic table.
+/// The offset is wrapped around the table, so it can be out of its range.
+pub fn interpolate(data: &[f64], offset: f64, mode: Interpolation) -> f64 {
+    let len = data.len() as isize;
+    let pos = offset.floor();
+    let t = offset - pos;
+    let pos = (pos as isize).rem_euclid(len);
+    let sample = |delta: isize| data[(pos + delta).rem_euclid(len) as usize];
+
+    match mode {
+        Interpolation::None => sample(0),
+
+        Interpolation::Linear => {
+            let x0 = sample(0);
+            x0 + (sample(1) - x0) * t
+        },
+
+        Interpolation::Hermite => {
+            let (xm1, x0, x1, x2) = (sample(-1), sample(0), sample(1), sample(2));
+            let c0 = x0;
+            let c1 = 0.5 * (x1 - xm1);
+            let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+            let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+            ((c3 * t + c2) * t + c1) * t + c0
+        },
+
+        Interpolation::Lagrange => {
+            let (xm1, x0, x1, x2) = (sample(-1), sample(0), sample(1), sample(2));
+            let c0 = x0;
+            let c1 = x1 - xm1 / 3.0 - 0.5 * x0 - x2 / 6.0;
+            let c2 = 0.5 * (xm1 + x1) - x0;
+            let c3 = (x2 - xm1) / 6.0 + 0.5 * (x0 - x1);
+            ((c3 * t + c2) * t + c1) * t + c0
+        },
+
+        Interpolation::Optimal => {
+            let (xm1, x0, x1, x2) = (sample(-1), sample(0), sample(1), sample(2));
+            let z = t - 0.5;
+            let (even1, odd1) = (x1 + x0, x1 - x0);
+            let (even2, odd2) = (x2 + xm1, x2 - xm1);
+            let c0 = even1 * 0.45868970870461956 + even2 * 0.04131401926395584;
+            let c1 = odd1 * 0.48068024766578432 + odd2 * 0.17577925564495955;
+            let c2 = even1 * -0.246185007019907091 + even2 * 0.24614027139700284;
+            let c3 = odd1 * -0.36030925263849456 + odd2 * 0.10174985775982505;
+            ((c3 * z + c2) * z + c1) * z + c0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const MODES: [Interpolation; 5] = [
+        Interpolation::None, Interpolation::Linear, Interpolation::Hermite,
+        Interpolation::Lagrange, Interpolation::Optimal];
+
+    #[test]
+    fn interpolate_wraps_offsets() {
+        let data = [0.0, 1.0, 2.0, 3.0];
+        for mode in MODES.iter() {
+            assert_eq!(interpolate(&data, 4.0, *mode), interpolate(&data, 0.0, *mode));
+            assert_eq!(interpolate(&data, -1.0, *mode), interpolate(&data, 3.0, *mode));
+        }
+        assert_eq!(interpolate(&data, 3.5, Interpolation::Linear), 1.5);
+        assert_eq!(interpolate(&data, 1.5, Interpolation::None), 1.0);
+    }
+
+    #[test]
+    fn interpolate_exact_at_samples() {
+        let data = [0.5, -1.0, 0.25, 0.75, -0.5];
+        for mode in [Interpolation::Linear, Interpolation::Hermite, Interpolation::Lagrange].iter() {
+            for (pos, value) in data.iter().enumerate() {
+                assert_eq!(interpolate(&data, pos as f64, *mode), *value);
+            }
+        }
+    }
+
+    #[test]
+    fn interpolate_small_sin_table() {
+        let size = 256;
+        let data: Vec<f64> = (0..size).map(|i| (2.0 * PI * i as f64 / size as f64).sin()).collect();
+        let max_error = |mode| {
+            (0..size * 7).map(|i| {
+                let offset = i as f64 / 7.0;
+                let expected = (2.0 * PI * offset / size as f64).sin();
+                (interpolate(&data, offset, mode) - expected).abs()
+            }).fold(0.0, f64::max)
+        };
+        let linear = max_error(Interpolation::Linear);
+        assert!(linear < 1e-4);
+        assert!(max_error(Interpolation::Hermite) < linear / 10.0);
+        assert!(max_error(Interpolation::Lagrange) < linear / 10.0);
+        // Not exact at the samples but it minimizes the error for 2x oversampled data
+        assert!(max_error(Interpolation::Optimal) < 1e-3);
+    }
+}
@@ -0,0 +1,99 @@
+//!
+//! Band limited versions of a wavetable, one per octave
+//!
+//! Every level keeps half of the harmonics of the previous one. They are generated
+//! by truncating the spectrum of the original table, so there is no aliasing as long
+//! as the right level is chosen for the frequency being played.
+//!
+
+use complex::Complex;
+use fft;
+
+/// Minimum number of samples per harmonic in the levels, so they can still be interpolated accurately
+const SAMPLES_PER_HARMONIC: usize = 16;
+
+pub struct Mipmaps {
+    size: usize,            // Size of the original table
+    levels: Vec<Vec<f64>>,  // Band limited levels, without the original table
+}
+
+impl Mipmaps {
+    /// Builds the levels for a table which size has to be a power of two
+    pub fn new(data: &[f64]) -> Mipmaps {
+        let size = data.len();
+        assert!(size.is_power_of_two());
+
+        let mut spectrum: Vec<Complex> = data.iter().map(|value| Complex::new(*value, 0.0)).collect();
+        fft::fft(&mut spectrum);
+
+        let mut levels = Vec::new();
+        let mut harmonics = size / 4;
+        while harmonics >= 1 {
+            let level_size = (harmonics * SAMPLES_PER_HARMONIC).min(size);
+            let scale = level_size as f64 / size as f64;
+            let mut level = vec![Complex::zero(); level_size];
+            level[0] = spectrum[0].scale(scale);
+            for harmonic in 1..harmonics + 1 {
+                level[harmonic] = spectrum[harmonic].scale(scale);
+                level[level_size - harmonic] = spectrum[size - harmonic].scale(scale);
+            }
+            fft::ifft(&mut level);
+            levels.push(level.iter().map(|value| value.re).collect());
+            harmonics /= 2;
+        }
+
+        Mipmaps {
+            size: size,
+            levels: levels
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Returns the data for a level and the factor to convert offsets of the original table into it
+    pub fn level(&self, index: usize) -> (&[f64], f64) {
+        let level = &self.levels[index];
+        (level, level.len() as f64 / self.size as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use complex::Complex;
+    use fft;
+
+    fn harmonics(data: &[f64]) -> Vec<f64> {
+        let mut spectrum: Vec<Complex> = data.iter().map(|value| Complex::new(*value, 0.0)).collect();
+        fft::fft(&mut spectrum);
+        let norm = 2.0 / data.len() as f64;
+        spectrum[1 .. data.len() / 2].iter().map(|value| value.norm() * norm).collect()
+    }
+
+    #[test]
+    fn mipmaps_truncate_harmonics() {
+        let size = 256;
+        let saw: Vec<f64> = (0..size).map(|i| -1.0 + 2.0 * i as f64 / size as f64).collect();
+        let saw_harmonics = harmonics(&saw);
+        let mipmaps = Mipmaps::new(&saw);
+        assert_eq!(mipmaps.len(), 7);
+
+        let mut max_harmonic = size / 4;
+        for index in 0..mipmaps.len() {
+            let (level, scale) = mipmaps.level(index);
+            assert_eq!(level.len() as f64, size as f64 * scale);
+            for (harmonic, amplitude) in harmonics(level).iter().enumerate() {
+                let harmonic = harmonic + 1;
+                let expected = if harmonic <= max_harmonic { saw_harmonics[harmonic - 1] } else { 0.0 };
+                assert!((amplitude - expected).abs() < 1e-9);
+            }
+            max_harmonic /= 2;
+        }
+    }
+}
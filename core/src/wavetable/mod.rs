@@ -1,11 +1,54 @@
 mod sin;
 mod saw;
+mod square;
+mod triangle;
+mod pulse;
+pub mod mipmap;
+pub mod interpolation;
+pub mod wav;
+pub mod registry;
 
+use std::error;
 use std::fmt;
+use std::io;
+use std::sync::{Arc, OnceLock};
 
+use self::mipmap::Mipmaps;
+pub use self::interpolation::Interpolation;
+pub use self::registry::Registry;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Format(String),
+    UnknownName(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "I/O error: {}", err),
+            Error::Format(ref msg) => write!(f, "Wrong format: {}", msg),
+            Error::UnknownName(ref name) => write!(f, "Unknown wavetable: {}", name),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Stock {
     Sin = 0,
     Saw,
+    Square,
+    Triangle,
+    Pulse,      // Pulse with a width of 25%
 }
 
 impl Stock {
@@ -13,60 +56,239 @@ impl Stock {
         match name {
             "sin" => Some(Stock::Sin),
             "saw" => Some(Stock::Saw),
+            "square" => Some(Stock::Square),
+            "triangle" | "tri" => Some(Stock::Triangle),
+            "pulse" => Some(Stock::Pulse),
             _ => None
         }
     }
+
+    fn lut(&self) -> &'static [f64] {
+        match *self {
+            Stock::Sin => sin::LUT,
+            Stock::Saw => saw::LUT,
+            Stock::Square => square::LUT,
+            Stock::Triangle => triangle::LUT,
+            Stock::Pulse => pulse::LUT,
+        }
+    }
+
+    /// The band limited levels are only calculated once and shared by all the wavetables
+    fn mipmaps(&self) -> Option<Arc<Vec<Mipmaps>>> {
+        static SAW_MIPMAPS: OnceLock<Arc<Vec<Mipmaps>>> = OnceLock::new();
+        static SQUARE_MIPMAPS: OnceLock<Arc<Vec<Mipmaps>>> = OnceLock::new();
+        static TRIANGLE_MIPMAPS: OnceLock<Arc<Vec<Mipmaps>>> = OnceLock::new();
+        static PULSE_MIPMAPS: OnceLock<Arc<Vec<Mipmaps>>> = OnceLock::new();
+
+        let mipmaps = match *self {
+            Stock::Sin => return None, // It only has one harmonic
+            Stock::Saw => &SAW_MIPMAPS,
+            Stock::Square => &SQUARE_MIPMAPS,
+            Stock::Triangle => &TRIANGLE_MIPMAPS,
+            Stock::Pulse => &PULSE_MIPMAPS,
+        };
+        Some(mipmaps.get_or_init(|| Arc::new(vec![Mipmaps::new(self.lut())])).clone())
+    }
+}
+
+/// Immutable samples shared by all the clones of a wavetable
+#[derive(Clone)]
+enum Samples {
+    Static(&'static [f64]),
+    Shared(Arc<[f64]>),
+}
+
+impl Samples {
+    fn as_slice(&self) -> &[f64] {
+        match *self {
+            Samples::Static(data) => data,
+            Samples::Shared(ref data) => data,
+        }
+    }
 }
 
+/// A wavetable with one or more frames of the same size,
+/// and optionally the band limited levels for every frame.
+///
+/// The samples and the levels are never copied, so cloning a wavetable
+/// for a voice or swapping it while playing does not allocate.
+#[derive(Clone)]
 pub struct Wavetable {
-    data: Vec<f64>,
+    data: Samples,
+    size: usize,
+    mipmaps: Option<Arc<Vec<Mipmaps>>>,
+    interpolation: Interpolation,
 }
 
 impl fmt::Debug for Wavetable {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Wavetable({})", self.data.len())
+        write!(f, "Wavetable({}, {} frames, {} levels)", self.size, self.num_frames(), self.num_levels())
     }
 }
 
-impl Clone for Wavetable {
-    fn clone(&self) -> Self { Wavetable { data: self.data.clone() } }
+/// The band limited levels are computed from the samples, so only their number is compared
+impl PartialEq for Wavetable {
+    fn eq(&self, other: &Wavetable) -> bool {
+        self.size == other.size
+            && self.num_levels() == other.num_levels()
+            && self.interpolation == other.interpolation
+            && self.data.as_slice() == other.data.as_slice()
+    }
 }
 
 impl Default for Wavetable {
     fn default() -> Self {
-        Wavetable { data: sin::LUT.to_vec() }
+        Wavetable::from_stock(Stock::Sin)
     }
 }
 
 impl Wavetable {
     pub fn new(data: Vec<f64>) -> Wavetable {
         Wavetable {
-            data: data
+            size: data.len(),
+            data: Samples::Shared(data.into()),
+            mipmaps: None,
+            interpolation: Interpolation::default()
+        }
+    }
+
+    /// Creates a wavetable with band limited versions for every octave.
+    /// The size of the data has to be a power of two.
+    pub fn band_limited(data: Vec<f64>) -> Wavetable {
+        let size = data.len();
+        Wavetable::from_frames(data, size)
+    }
+
+    /// Creates a band limited wavetable from consecutive frames.
+    /// The frame size has to be a power of two.
+    pub fn from_frames(data: Vec<f64>, frame_size: usize) -> Wavetable {
+        assert!(frame_size > 0 && data.len() % frame_size == 0);
+
+        let mipmaps = data.chunks(frame_size).map(|frame| Mipmaps::new(frame)).collect();
+        Wavetable {
+            data: Samples::Shared(data.into()),
+            size: frame_size,
+            mipmaps: Some(Arc::new(mipmaps)),
+            interpolation: Interpolation::default()
         }
     }
 
     pub fn from_stock(stock: Stock) -> Wavetable {
-        match stock {
-            Stock::Sin => Wavetable { data: sin::LUT.to_vec() },
-            Stock::Saw => Wavetable { data: saw::LUT.to_vec() },
+        let data = stock.lut();
+        Wavetable {
+            size: data.len(),
+            data: Samples::Static(data),
+            mipmaps: stock.mipmaps(),
+            interpolation: Interpolation::default()
         }
     }
 
+    /// Size of a frame
     pub fn size(&self) -> usize {
-        return self.data.len();
+        return self.size;
     }
 
+    pub fn num_frames(&self) -> usize {
+        self.data.as_slice().len() / self.size
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    pub fn get_interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// Number of band limited levels, including the original table
+    pub fn num_levels(&self) -> usize {
+        match self.mipmaps {
+            Some(ref mipmaps) => mipmaps[0].len() + 1,
+            None => 1
+        }
+    }
+
+    /// Fractional level to use for a table increment, such that there are no harmonics above the Nyquist frequency.
+    /// The level before the point and the next one have to be crossfaded by the fractional part.
+    pub fn level_for_incr(&self, incr: f64) -> f64 {
+        let max_level = (self.num_levels() - 1) as f64;
+        (incr.abs().log2() + 1.0).max(0.0).min(max_level)
+    }
+
+    fn frame(&self, index: usize) -> &[f64] {
+        &self.data.as_slice()[index * self.size .. (index + 1) * self.size]
+    }
+
+    /// Value at an offset of the first frame. Offsets out of range are wrapped around the frame.
     pub fn value(&self, offset: f64) -> f64 {
-        let data_len = self.data.len();
-        let pos: usize = offset.floor() as usize;
-        assert!(pos < data_len);
-        let value = self.data[pos];
+        interpolation::interpolate(self.frame(0), offset, self.interpolation)
+    }
+
+    pub fn level_value(&self, level: usize, offset: f64) -> f64 {
+        self.frame_value(0, level, offset)
+    }
+
+    /// Value at an offset of a band limited level of a frame
+    pub fn frame_value(&self, frame: usize, level: usize, offset: f64) -> f64 {
+        match self.mipmaps {
+            Some(ref mipmaps) if level > 0 => {
+                let (data, scale) = mipmaps[frame].level(level - 1);
+                interpolation::interpolate(data, offset * scale, self.interpolation)
+            },
+            _ => interpolation::interpolate(self.frame(frame), offset, self.interpolation)
+        }
+    }
+
+    /// Value at a position [0, 1] across the frames, crossfading the two frames around it
+    pub fn position_value(&self, position: f64, level: usize, offset: f64) -> f64 {
+        let max_frame = self.num_frames() - 1;
+        if max_frame == 0 {
+            return self.frame_value(0, level, offset);
+        }
+
+        let position = position.max(0.0).min(1.0) * max_frame as f64;
+        let frame = (position.floor() as usize).min(max_frame - 1);
+        let fraction = position - frame as f64;
+
+        let value = self.frame_value(frame, level, offset);
+        if fraction > 0.0 {
+            let next_value = self.frame_value(frame + 1, level, offset);
+            value + (next_value - value) * fraction
+        }
+        else {
+            value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_crossfades_frames() {
+        let mut data = vec![0.0; 8];
+        data.extend(vec![1.0; 8]);
+        data.extend(vec![-1.0; 8]);
+        let wavetable = Wavetable::from_frames(data, 8);
+        assert_eq!(wavetable.num_frames(), 3);
+
+        let expected = [(0.0, 0.0), (0.25, 0.5), (0.5, 1.0), (0.75, 0.0), (1.0, -1.0), (2.0, -1.0)];
+        for &(position, value) in expected.iter() {
+            assert!((wavetable.position_value(position, 0, 3.0) - value).abs() < 1e-9);
+            assert!((wavetable.position_value(position, 1, 3.0) - value).abs() < 1e-9);
+        }
+    }
 
-        let next_pos: usize = (pos + 1) % data_len;
-        let next_value = self.data[next_pos];
+    #[test]
+    fn clones_share_samples() {
+        let stock = Wavetable::from_stock(Stock::Saw);
+        let copy = stock.clone();
+        assert_eq!(stock.frame(0).as_ptr(), copy.frame(0).as_ptr());
+        assert_eq!(stock.frame(0).as_ptr(), Stock::Saw.lut().as_ptr());
 
-        let diff = next_value - value;
-        let fraction = offset - (pos as f64);
-        return value + diff * fraction;
+        let wavetable = Wavetable::from_frames(vec![0.5; 16], 8);
+        let copy = wavetable.clone();
+        assert_eq!(wavetable.frame(1).as_ptr(), copy.frame(1).as_ptr());
     }
 }
@@ -0,0 +1,213 @@
+//!
+//! Registry of the wavetables that can be referenced by name
+//!
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use complex::Complex;
+use fft;
+use wavetable::{wav, Error, Interpolation, Stock, Wavetable};
+use wavetable::interpolation::interpolate;
+
+/// Frame size for the wavetables loaded from files
+pub const DEFAULT_FRAME_SIZE: usize = 2048;
+
+/// Maximum number of frames of a multi-frame wavetable
+pub const MAX_FRAMES: usize = 256;
+
+const STOCK: [(&'static str, Stock); 5] = [
+    ("sin", Stock::Sin),
+    ("saw", Stock::Saw),
+    ("square", Stock::Square),
+    ("triangle", Stock::Triangle),
+    ("pulse", Stock::Pulse),
+];
+
+pub struct Registry {
+    frame_size: usize,
+    tables: HashMap<String, Wavetable>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new(DEFAULT_FRAME_SIZE)
+    }
+}
+
+impl Registry {
+    /// Creates a registry with the stock wavetables. The frame size has to be a power of two.
+    pub fn new(frame_size: usize) -> Registry {
+        assert!(frame_size.is_power_of_two());
+
+        let mut registry = Registry {
+            frame_size: frame_size,
+            tables: HashMap::new(),
+        };
+        for &(name, stock) in STOCK.iter() {
+            registry.register(name, Wavetable::from_stock(stock));
+        }
+        registry
+    }
+
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    pub fn register(&mut self, name: &str, wavetable: Wavetable) {
+        self.tables.insert(name.to_string(), wavetable);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.tables.contains_key(name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tables.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn get(&self, name: &str) -> Result<Wavetable, Error> {
+        match self.tables.get(name) {
+            Some(wavetable) => Ok(wavetable.clone()),
+            None => Err(Error::UnknownName(name.to_string()))
+        }
+    }
+
+    /// Loads a WAV file as a multi-frame wavetable with the frame size of its `clm ` chunk.
+    /// Without it, the file has frames of the default size when its length is a multiple of it,
+    /// or it is a single cycle otherwise.
+    pub fn load_wav(&mut self, name: &str, path: &Path) -> Result<(), Error> {
+        let wavetable = read_wav(path, None, self.frame_size)?;
+        self.register(name, wavetable);
+        Ok(())
+    }
+
+    /// Loads a WAV file as a multi-frame wavetable with the given frame size.
+    pub fn load_wav_frames(&mut self, name: &str, path: &Path, frame_size: usize) -> Result<(), Error> {
+        let wavetable = read_wav(path, Some(frame_size), self.frame_size)?;
+        self.register(name, wavetable);
+        Ok(())
+    }
+
+    /// Registers a wavetable from the frames contained in the samples,
+    /// resampling every frame to the frame size of the registry.
+    pub fn register_frames(&mut self, name: &str, samples: &[f64], frame_size: usize) -> Result<(), Error> {
+        let wavetable = from_frames(samples, frame_size, self.frame_size)?;
+        self.register(name, wavetable);
+        Ok(())
+    }
+}
+
+/// Reads a WAV file as a wavetable with frames of the given size, without registering it,
+/// so that the file can be read and resampled out of the audio thread.
+/// When the frame size of the file is not given, it is taken from the `clm ` chunk or guessed from its length.
+pub fn read_wav(path: &Path, frame_size: Option<usize>, size: usize) -> Result<Wavetable, Error> {
+    let wav_data = wav::read(path)?;
+    let len = wav_data.samples.len();
+    let frame_size = frame_size.or(wav_data.frame_size).unwrap_or_else(|| guess_frame_size(len));
+    from_frames(&wav_data.samples, frame_size, size)
+}
+
+/// Most wavetable files without a `clm ` chunk have frames of the default size,
+/// the rest of the lengths are taken as a single cycle.
+fn guess_frame_size(len: usize) -> usize {
+    if len > DEFAULT_FRAME_SIZE && len % DEFAULT_FRAME_SIZE == 0 { DEFAULT_FRAME_SIZE } else { len }
+}
+
+/// Creates a wavetable from the frames contained in the samples, resampling every frame to the given size.
+pub fn from_frames(samples: &[f64], frame_size: usize, size: usize) -> Result<Wavetable, Error> {
+    if frame_size == 0 || samples.len() < frame_size {
+        return Err(Error::Format(format!("not enough samples for a frame of {}", frame_size)));
+    }
+
+    let num_frames = (samples.len() / frame_size).min(MAX_FRAMES);
+    let mut data = Vec::with_capacity(num_frames * size);
+    for frame in samples.chunks(frame_size).take(num_frames) {
+        data.extend(resample(frame, size));
+    }
+    Ok(Wavetable::from_frames(data, size))
+}
+
+/// Resamples a single cycle to a different size.
+/// Cycles with a size that is a power of two are resampled exactly in the frequency domain,
+/// otherwise they are interpolated.
+fn resample(frame: &[f64], size: usize) -> Vec<f64> {
+    let len = frame.len();
+    if len == size {
+        frame.to_vec()
+    }
+    else if len.is_power_of_two() {
+        let mut spectrum: Vec<Complex> = frame.iter().map(|value| Complex::new(*value, 0.0)).collect();
+        fft::fft(&mut spectrum);
+        let scale = size as f64 / len as f64;
+        let mut resampled = vec![Complex::zero(); size];
+        resampled[0] = spectrum[0].scale(scale);
+        for bin in 1 .. len.min(size) / 2 {
+            resampled[bin] = spectrum[bin].scale(scale);
+            resampled[size - bin] = spectrum[len - bin].scale(scale);
+        }
+        fft::ifft(&mut resampled);
+        resampled.iter().map(|value| value.re).collect()
+    }
+    else {
+        let step = len as f64 / size as f64;
+        (0..size).map(|i| interpolate(frame, i as f64 * step, Interpolation::Hermite)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use wavetable::Error;
+
+    fn cycle(len: usize, harmonic: f64) -> Vec<f64> {
+        (0..len).map(|i| (2.0 * PI * harmonic * i as f64 / len as f64).sin()).collect()
+    }
+
+    #[test]
+    fn registry_stock_and_unknown() {
+        let registry = Registry::default();
+        assert_eq!(registry.names(), vec!["pulse", "saw", "sin", "square", "triangle"]);
+        assert!(registry.get("sin").is_ok());
+        match registry.get("nope") {
+            Err(Error::UnknownName(name)) => assert_eq!(name, "nope"),
+            _ => panic!("expected an unknown name error")
+        }
+    }
+
+    #[test]
+    fn registry_resamples_frames() {
+        let mut registry = Registry::new(64);
+        let mut samples = cycle(256, 1.0);
+        samples.extend(cycle(256, 2.0));
+        registry.register_frames("two", &samples, 256).unwrap();
+        registry.register_frames("odd", &cycle(100, 1.0), 100).unwrap();
+
+        let wavetable = registry.get("two").unwrap();
+        assert_eq!(wavetable.size(), 64);
+        assert_eq!(wavetable.num_frames(), 2);
+        let expected = cycle(64, 1.0);
+        for i in 0..64 {
+            assert!((wavetable.value(i as f64) - expected[i]).abs() < 1e-9);
+        }
+
+        let wavetable = registry.get("odd").unwrap();
+        assert_eq!(wavetable.num_frames(), 1);
+        for i in 0..64 {
+            assert!((wavetable.value(i as f64) - expected[i]).abs() < 1e-3);
+        }
+
+        assert!(registry.register_frames("short", &samples, 1024).is_err());
+    }
+
+    #[test]
+    fn registry_guess_frame_size() {
+        assert_eq!(guess_frame_size(4 * DEFAULT_FRAME_SIZE), DEFAULT_FRAME_SIZE);
+        assert_eq!(guess_frame_size(DEFAULT_FRAME_SIZE), DEFAULT_FRAME_SIZE);
+        assert_eq!(guess_frame_size(600), 600);
+        assert_eq!(guess_frame_size(DEFAULT_FRAME_SIZE + 600), DEFAULT_FRAME_SIZE + 600);
+    }
+}
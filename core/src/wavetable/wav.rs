@@ -0,0 +1,208 @@
+//!
+//! Reading of single cycle and multi-frame wavetables from WAV files
+//!
+//! Multi-frame wavetables follow the convention of Serum, where the frame size
+//! is declared in a `clm ` chunk with a text like `<!>2048 ...`.
+//!
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use wavetable::Error;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+#[derive(Debug)]
+struct Format {
+    tag: u16,
+    channels: usize,
+    bits: usize,
+}
+
+#[derive(Debug)]
+pub struct WavData {
+    pub samples: Vec<f64>,          // Samples of the first channel
+    pub frame_size: Option<usize>,  // Frame size declared in the `clm ` chunk
+}
+
+pub fn read(path: &Path) -> Result<WavData, Error> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    parse(&data)
+}
+
+pub fn parse(data: &[u8]) -> Result<WavData, Error> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err(Error::Format("not a RIFF/WAVE file".to_string()));
+    }
+
+    let mut format = None;
+    let mut samples = None;
+    let mut frame_size = None;
+
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let id = &data[pos .. pos + 4];
+        let size = read_u32(data, pos + 4) as usize;
+        let start = pos + 8;
+        let chunk = &data[start .. (start + size).min(data.len())];
+        match id {
+            b"fmt " => format = Some(parse_format(chunk)?),
+            b"data" => samples = Some(chunk),
+            b"clm " => frame_size = parse_clm(chunk),
+            _ => {}
+        }
+        // Chunks are aligned to 16 bits
+        pos = start + size + (size & 1);
+    }
+
+    match (format, samples) {
+        (Some(format), Some(samples)) => Ok(WavData {
+            samples: decode(&format, samples)?,
+            frame_size: frame_size
+        }),
+        (None, _) => Err(Error::Format("missing fmt chunk".to_string())),
+        (_, None) => Err(Error::Format("missing data chunk".to_string())),
+    }
+}
+
+fn read_u16(data: &[u8], pos: usize) -> u16 {
+    data[pos] as u16 | (data[pos + 1] as u16) << 8
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    read_u16(data, pos) as u32 | (read_u16(data, pos + 2) as u32) << 16
+}
+
+fn parse_format(chunk: &[u8]) -> Result<Format, Error> {
+    if chunk.len() < 16 {
+        return Err(Error::Format("fmt chunk too short".to_string()));
+    }
+
+    let mut tag = read_u16(chunk, 0);
+    if tag == FORMAT_EXTENSIBLE && chunk.len() >= 26 {
+        // The first two bytes of the sub-format GUID contain the actual format
+        tag = read_u16(chunk, 24);
+    }
+
+    let format = Format {
+        tag: tag,
+        channels: read_u16(chunk, 2) as usize,
+        bits: read_u16(chunk, 14) as usize,
+    };
+
+    match (format.tag, format.bits) {
+        _ if format.channels == 0 => Err(Error::Format("no channels".to_string())),
+        (FORMAT_PCM, 8) | (FORMAT_PCM, 16) | (FORMAT_PCM, 24) | (FORMAT_PCM, 32) => Ok(format),
+        (FORMAT_IEEE_FLOAT, 32) | (FORMAT_IEEE_FLOAT, 64) => Ok(format),
+        (tag, bits) => Err(Error::Format(format!("unsupported format {} with {} bits", tag, bits)))
+    }
+}
+
+fn parse_clm(chunk: &[u8]) -> Option<usize> {
+    let text = String::from_utf8_lossy(chunk);
+    text.find("<!>").and_then(|pos| {
+        let digits: String = text[pos + 3 ..].chars().take_while(|c| c.is_digit(10)).collect();
+        digits.parse().ok()
+    })
+}
+
+fn decode(format: &Format, data: &[u8]) -> Result<Vec<f64>, Error> {
+    let bytes = format.bits / 8;
+    let block_size = bytes * format.channels;
+    let samples = data.chunks(block_size)
+        .filter(|block| block.len() == block_size)
+        .map(|block| match (format.tag, format.bits) {
+            (FORMAT_PCM, 8) => (block[0] as f64 - 128.0) / 128.0,
+            (FORMAT_PCM, 16) => read_u16(block, 0) as i16 as f64 / 32768.0,
+            (FORMAT_PCM, 24) => {
+                let value = (block[0] as i32) << 8 | (block[1] as i32) << 16 | (block[2] as i32) << 24;
+                (value >> 8) as f64 / 8388608.0
+            },
+            (FORMAT_PCM, _) => read_u32(block, 0) as i32 as f64 / 2147483648.0,
+            (_, 32) => f32::from_bits(read_u32(block, 0)) as f64,
+            _ => f64::from_bits(read_u32(block, 0) as u64 | (read_u32(block, 4) as u64) << 32),
+        })
+        .collect::<Vec<f64>>();
+
+    if samples.is_empty() {
+        Err(Error::Format("no samples".to_string()))
+    }
+    else {
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        let size = data.len() as u32;
+        chunk.extend(&[size as u8, (size >> 8) as u8, (size >> 16) as u8, (size >> 24) as u8]);
+        chunk.extend(data);
+        if data.len() & 1 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn wav(tag: u16, channels: u16, bits: u16, samples: &[u8], clm: Option<&str>) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let fmt = [
+            tag as u8, (tag >> 8) as u8, channels as u8, 0,
+            0x44, 0xac, 0, 0,  // 44100
+            0, 0, 0, 0,
+            block_align as u8, 0, bits as u8, 0];
+        let mut body = b"WAVE".to_vec();
+        body.extend(chunk(b"fmt ", &fmt));
+        if let Some(text) = clm {
+            body.extend(chunk(b"clm ", text.as_bytes()));
+        }
+        body.extend(chunk(b"data", samples));
+        let mut data = b"RIFF".to_vec();
+        let size = body.len() as u32;
+        data.extend(&[size as u8, (size >> 8) as u8, (size >> 16) as u8, (size >> 24) as u8]);
+        data.extend(body);
+        data
+    }
+
+    #[test]
+    fn parse_pcm16_stereo() {
+        let samples = [0x00, 0x40, 0xff, 0x7f, 0x00, 0xc0, 0x00, 0x00];
+        let data = parse(&wav(FORMAT_PCM, 2, 16, &samples, None)).unwrap();
+        assert_eq!(data.samples, vec![0.5, -0.5]);
+        assert_eq!(data.frame_size, None);
+    }
+
+    #[test]
+    fn parse_pcm24_and_float() {
+        let samples = [0x00, 0x00, 0x40, 0x00, 0x00, 0xc0];
+        let data = parse(&wav(FORMAT_PCM, 1, 24, &samples, None)).unwrap();
+        assert_eq!(data.samples, vec![0.5, -0.5]);
+
+        let bits = (0.25f32).to_bits();
+        let samples = [bits as u8, (bits >> 8) as u8, (bits >> 16) as u8, (bits >> 24) as u8];
+        let data = parse(&wav(FORMAT_IEEE_FLOAT, 1, 32, &samples, None)).unwrap();
+        assert_eq!(data.samples, vec![0.25]);
+    }
+
+    #[test]
+    fn parse_clm_frame_size() {
+        let samples = [0x80, 0xc0, 0x40];
+        let data = parse(&wav(FORMAT_PCM, 1, 8, &samples, Some("<!>2048 10000000 wavetable (www.xferrecords.com)"))).unwrap();
+        assert_eq!(data.samples, vec![0.0, 0.5, -0.5]);
+        assert_eq!(data.frame_size, Some(2048));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse(b"RIFF0000AVI ").is_err());
+        assert!(parse(&wav(FORMAT_PCM, 1, 12, &[0, 0], None)).is_err());
+        assert!(parse(&wav(FORMAT_PCM, 1, 16, &[], None)).is_err());
+    }
+}
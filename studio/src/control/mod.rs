@@ -4,6 +4,9 @@ use std::sync::mpsc::{Sender, Receiver};
 use std::thread::{self, JoinHandle};
 
 use rosc;
+use rosc::OscPacket;
+
+use hero_synth::synth::Synth as HeroSynth;
 
 use midi;
 use engine;
@@ -65,6 +68,14 @@ impl Control {
                         let engine_event = engine::Event::new(midi_event.timestamp(), engine_message);
                         engine_events.push(engine_event);
                     },
+                    midi::Message::SysEx { data } => {
+                        if let Some(tuning) = midi::mts::decode(&data) {
+                            let key_freqs = tuning.key_freqs().iter().map(|&(key, freq)| (key as usize, freq)).collect();
+                            let engine_message = engine::Message::Tuning { key_freqs: key_freqs, realtime: tuning.is_realtime() };
+                            let engine_event = engine::Event::new(midi_event.timestamp(), engine_message);
+                            engine_events.push(engine_event);
+                        }
+                    },
                     _ => {}
                 }
             }
@@ -77,13 +88,34 @@ impl Control {
     fn osc_input(osc_input_rx: Receiver<rosc::OscPacket>,
                   engine_input_tx: Sender<engine::PortEvents>) {
 
-        const NOW_TIMESTAMP: Timestamp = 0 as Timestamp;
         let default_port: engine::Port = engine::Port::Osc("default".to_string());
 
         for osc_packet in osc_input_rx {
-            let event = engine::Event::new(NOW_TIMESTAMP, engine::Message::Control(osc_packet));
-            let src_events = engine::PortEvents::new(default_port.clone(), vec![event]);
+            let mut events = Vec::new();
+            Control::osc_events(osc_packet, &mut events);
+            let src_events = engine::PortEvents::new(default_port.clone(), events);
             engine_input_tx.send(src_events).unwrap();
         }
     }
+
+    /// The files given by the messages are read here, as it is too slow for the audio thread,
+    /// and the engine receives what has been loaded. The bundles are split to keep the order of the messages.
+    fn osc_events(osc_packet: OscPacket, events: &mut Vec<engine::Event>) {
+        const NOW_TIMESTAMP: Timestamp = 0 as Timestamp;
+
+        match osc_packet {
+            OscPacket::Message(msg) => {
+                let engine_message = match HeroSynth::load(&msg) {
+                    Some(resource) => engine::Message::Resource(resource),
+                    None => engine::Message::Control(OscPacket::Message(msg)),
+                };
+                events.push(engine::Event::new(NOW_TIMESTAMP, engine_message));
+            },
+            OscPacket::Bundle(bundle) => {
+                for bundle_packet in bundle.content {
+                    Control::osc_events(bundle_packet, events);
+                }
+            }
+        }
+    }
 }
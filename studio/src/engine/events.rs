@@ -2,13 +2,17 @@ use std::collections::btree_map::{self, BTreeMap, Entry};
 
 use rosc::OscPacket;
 
+use hero_synth::synth::Resource;
+
 use engine::types::Timestamp;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Message {
     NoteOn { key: usize, velocity: f64 },
     NoteOff { key: usize, velocity: f64 },
+    Tuning { key_freqs: Vec<(usize, f64)>, realtime: bool },
     Control(OscPacket),
+    Resource(Result<Resource, String>),
 }
 
 #[derive(Debug, Clone)]
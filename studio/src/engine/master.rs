@@ -0,0 +1,66 @@
+use rosc::{OscType, OscMessage};
+
+use hero_core::types::SampleRate;
+use hero_core::filter::eq::Equalizer;
+use hero_synth::patch::{self, EqBandPatch};
+use hero_synth::synth::{args_eq_band, MAX_EQ_BANDS};
+
+const ADDR_MASTER_EQ_BAND: &'static str = "/master/eq/band";
+const ADDR_MASTER_EQ_NUM_BANDS: &'static str = "/master/eq/num-bands";
+
+/// Processing of the mix of all the instruments before the audio output
+pub struct MasterBus {
+    eq: Equalizer,
+    eq_bands: Vec<EqBandPatch>,     // Bands of the equalizer, like the ones of a patch
+}
+
+impl MasterBus {
+    pub fn new(sample_rate: SampleRate) -> MasterBus {
+        MasterBus {
+            eq: Equalizer::new(sample_rate),
+            eq_bands: Vec::new()
+        }
+    }
+
+    /// Handles the messages for the master bus, and returns false for the rest of them
+    pub fn control(&mut self, msg: &OscMessage) -> bool {
+        match msg.addr.as_ref() {
+            ADDR_MASTER_EQ_BAND => self.control_eq_band(&msg.args),
+            ADDR_MASTER_EQ_NUM_BANDS => self.control_eq_num_bands(&msg.args),
+            _ => return false
+        }
+        true
+    }
+
+    /// Sets a band of the equalizer, or adds it when it is just after the last one
+    fn control_eq_band(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, band)) = args_eq_band(args) {
+            if index < self.eq_bands.len() {
+                self.eq_bands[index] = band;
+            }
+            else if index == self.eq_bands.len() {
+                self.eq_bands.push(band);
+            }
+            else {
+                return;
+            }
+            patch::update_equalizer(&mut self.eq, &self.eq_bands);
+        }
+    }
+
+    /// Removes the last bands, or adds flat bands
+    fn control_eq_num_bands(&mut self, args: &Option<Vec<OscType>>) {
+        if let &Some(ref args) = args {
+            if let Some(&OscType::Int(value)) = args.first() {
+                if value >= 0 && value as usize <= MAX_EQ_BANDS {
+                    self.eq_bands.resize(value as usize, EqBandPatch::new("peak", 1000.0, 1.0, 0.0));
+                    patch::update_equalizer(&mut self.eq, &self.eq_bands);
+                }
+            }
+        }
+    }
+
+    pub fn process(&mut self, left: f64, right: f64) -> (f64, f64) {
+        self.eq.process_stereo(left, right)
+    }
+}
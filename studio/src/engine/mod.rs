@@ -1,5 +1,6 @@
 pub mod events;
 pub mod types;
+pub mod master;
 
 use std::sync::mpsc::{Sender, Receiver};
 use std::sync::atomic::{Ordering, AtomicBool};
@@ -16,6 +17,7 @@ use rosc::OscPacket;
 pub use self::types::Timestamp;
 pub use self::events::{Message, Event, Port, PortEvents};
 use self::events::EventsBuffer;
+use self::master::MasterBus;
 
 
 pub struct Engine {
@@ -26,6 +28,7 @@ pub struct Engine {
     input_events: Arc<Mutex<EventsBuffer>>,
     events_sender: Option<Sender<PortEvents>>,
     hero_synth: HeroSynth,
+    master: MasterBus,
 }
 
 unsafe impl Send for Engine {}
@@ -43,7 +46,8 @@ impl Engine {
             input_events: Arc::new(Mutex::new(EventsBuffer::new())),
             events_sender: None,
 
-            hero_synth: hero_synth
+            hero_synth: hero_synth,
+            master: MasterBus::new(sample_rate)
         }
     }
 
@@ -74,6 +78,22 @@ impl Engine {
         }
     }
 
+    /// Messages for the master bus are handled by the engine and the rest are sent to the synth
+    fn control(&mut self, packet: &OscPacket) {
+        match packet {
+            &OscPacket::Message(ref msg) => {
+                if !self.master.control(msg) {
+                    self.hero_synth.control(packet);
+                }
+            },
+            &OscPacket::Bundle(ref bundle) => {
+                for bundle_packet in &bundle.content {
+                    self.control(bundle_packet);
+                }
+            }
+        }
+    }
+
     fn events_input_loop(
         running: &AtomicBool,
         events_receiver: Receiver<PortEvents>,
@@ -111,7 +131,9 @@ impl<'a, O> Processor<'a, f32, O> for Engine
                     match message {
                         &Message::NoteOn { key, velocity } => self.hero_synth.note_on(key, velocity),
                         &Message::NoteOff { key, velocity } => self.hero_synth.note_off(key, velocity),
-                        &Message::Control(ref packet) => self.hero_synth.control(packet),
+                        &Message::Tuning { ref key_freqs, realtime } => self.hero_synth.retune(key_freqs, realtime),
+                        &Message::Control(ref packet) => self.control(packet),
+                        &Message::Resource(ref resource) => self.hero_synth.set_resource(resource.clone()),
                     }
                 }
             }
@@ -127,6 +149,7 @@ impl<'a, O> Processor<'a, f32, O> for Engine
             };
 
             let (left, right) = self.hero_synth.process();
+            let (left, right) = self.master.process(left, right);
             args.audio_out_left[i] = left as f32;
             args.audio_out_right[i] = right as f32;
             process_timestamp += time_delta;
@@ -13,6 +13,9 @@ use midi::types::Timestamp;
 const MIDI_BUF_LEN: usize = 1024;
 const MIDI_LOOP_DELAY_MILLIS: u64 = 10;
 
+const SYSEX_START: u8 = 0xf0;
+const SYSEX_END: u8 = 0xf7;
+
 pub struct Midi {
     pm_ctx: portmidi::PortMidi,
     running: Arc<AtomicBool>,
@@ -62,8 +65,9 @@ impl Midi {
         finished.store(false, Ordering::Relaxed);
         let loop_delay = Duration::from_millis(MIDI_LOOP_DELAY_MILLIS);
         let mut dev_events = Vec::<PortEvents>::with_capacity(in_ports.len());
+        let mut sysex_buffers = vec![Vec::<u8>::new(); in_ports.len()];
         while running.load(Ordering::Relaxed) {
-            Self::read_events(&in_ports, &mut dev_events);
+            Self::read_events(&in_ports, &mut sysex_buffers, &mut dev_events);
             if !dev_events.is_empty() {
                 for dev_events in dev_events.iter() {
                     sender.send(dev_events.clone()).ok();
@@ -76,11 +80,14 @@ impl Midi {
         finished.store(true, Ordering::Relaxed);
     }
 
-    fn read_events(in_ports: &Vec<portmidi::InputPort>, dev_events: &mut Vec<PortEvents>) {
+    fn read_events(in_ports: &Vec<portmidi::InputPort>,
+                   sysex_buffers: &mut Vec<Vec<u8>>,
+                   dev_events: &mut Vec<PortEvents>) {
+
         dev_events.clear();
-        for port in in_ports {
+        for (port, sysex) in in_ports.iter().zip(sysex_buffers.iter_mut()) {
             if let Ok(Some(raw_events)) = port.read_n(MIDI_BUF_LEN) {
-                let events = Self::decode_events(raw_events);
+                let events = Self::decode_events(raw_events, sysex);
                 if !events.is_empty() {
                     let device = port.device();
                     let dev_name = device.name();
@@ -90,10 +97,37 @@ impl Midi {
         }
     }
 
-    fn decode_events(raw_events: Vec<portmidi::MidiEvent>) -> Vec<Event> {
+    /// SysEx messages arrive in chunks of 4 bytes, possibly split between reads and interleaved
+    /// with real time messages, so they are gathered until the end before being decoded.
+    fn decode_events(raw_events: Vec<portmidi::MidiEvent>, sysex: &mut Vec<u8>) -> Vec<Event> {
         let mut events = Vec::with_capacity(raw_events.len());
         for raw_event in raw_events {
             let raw_msg = raw_event.message;
+            let is_sysex_data = raw_msg.status & 0x80 == 0 || raw_msg.status == SYSEX_END;
+            if raw_msg.status == SYSEX_START || (!sysex.is_empty() && is_sysex_data) {
+                if raw_msg.status == SYSEX_START {
+                    sysex.clear();
+                }
+                let chunk = [raw_msg.status, raw_msg.data1, raw_msg.data2, raw_msg.data3];
+                match chunk.iter().position(|byte| *byte == SYSEX_END) {
+                    Some(end) => {
+                        sysex.extend(&chunk[..end + 1]);
+                        if let Some(message) = Decoder::new(&sysex[..]).next() {
+                            let timestamp = raw_event.timestamp as Timestamp;
+                            events.push(Event::new(timestamp, message));
+                        }
+                        sysex.clear();
+                    },
+                    None => sysex.extend(&chunk)
+                }
+                continue;
+            }
+
+            // Any status other than a real time one ends an unfinished SysEx message
+            if raw_msg.status < 0xf8 {
+                sysex.clear();
+            }
+
             let msg_buf = [raw_msg.status, raw_msg.data1, raw_msg.data2];
             let mut decoder = Decoder::new(&msg_buf);
             match decoder.next() {
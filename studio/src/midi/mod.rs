@@ -3,6 +3,7 @@ pub mod messages;
 pub mod decoder;
 pub mod events;
 pub mod io;
+pub mod mts;
 
 // pub use self::decoder::Decoder;
 pub use self::events::{Event, PortEvents};
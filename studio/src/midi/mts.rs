@@ -0,0 +1,177 @@
+//!
+//! MIDI Tuning Standard (MTS) messages carried by the System Exclusive messages
+//!
+//! The bulk tuning dumps and the single note tuning changes are decoded into the frequencies of the keys.
+//! The data starts after the SysEx start byte and ends before the SysEx end byte, as given by the decoder.
+//!
+
+use midi::types::U7;
+
+const NON_REALTIME: U7 = 0x7e;
+const REALTIME: U7 = 0x7f;
+const SUB_ID_TUNING: U7 = 0x08;
+
+const BULK_DUMP: U7 = 0x01;
+const NOTE_CHANGE: U7 = 0x02;
+const BULK_DUMP_BANK: U7 = 0x04;
+const NOTE_CHANGE_BANK: U7 = 0x07;
+
+const NAME_SIZE: usize = 16;
+const NUM_KEYS: usize = 128;
+
+/// Tuning of a key in three bytes, meaning that the key is not changed
+const NO_CHANGE: (U7, U7, U7) = (0x7f, 0x7f, 0x7f);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TuningMessage {
+    /// Tuning of all the keys of a tuning program. The bank is 0 for the dumps without bank.
+    BulkDump { bank: U7, program: U7, name: String, key_freqs: Vec<(U7, f64)> },
+
+    /// Tuning of some keys. The real time changes also apply to the notes being played.
+    NoteChange { realtime: bool, bank: U7, program: U7, key_freqs: Vec<(U7, f64)> },
+}
+
+impl TuningMessage {
+    /// Frequencies in Hz of the keys changed by the message
+    pub fn key_freqs(&self) -> &[(U7, f64)] {
+        match *self {
+            TuningMessage::BulkDump { ref key_freqs, .. } => key_freqs,
+            TuningMessage::NoteChange { ref key_freqs, .. } => key_freqs,
+        }
+    }
+
+    pub fn is_realtime(&self) -> bool {
+        match *self {
+            TuningMessage::BulkDump { .. } => false,
+            TuningMessage::NoteChange { realtime, .. } => realtime,
+        }
+    }
+}
+
+/// Frequency of a key tuned with a semitone and a fraction of semitone in 14 bits, or None for no change
+fn key_freq(semitone: U7, msb: U7, lsb: U7) -> Option<f64> {
+    if (semitone, msb, lsb) == NO_CHANGE {
+        None
+    }
+    else {
+        let fraction = ((msb as u32) << 7 | lsb as u32) as f64 / 16384.0;
+        Some(440.0 * (2_f64).powf((semitone as f64 + fraction - 69.0) / 12.0))
+    }
+}
+
+/// Decodes the data of a SysEx message, None when it is not a supported tuning message or it is malformed
+pub fn decode(data: &[U7]) -> Option<TuningMessage> {
+    if data.len() < 4 || data[2] != SUB_ID_TUNING {
+        return None;
+    }
+
+    // The device ID in data[1] is ignored, the messages for any device are accepted
+    match (data[0], data[3]) {
+        (NON_REALTIME, BULK_DUMP) => decode_bulk_dump(data, 0, &data[4..]),
+        (NON_REALTIME, BULK_DUMP_BANK) if data.len() > 4 => decode_bulk_dump(data, data[4], &data[5..]),
+        (REALTIME, NOTE_CHANGE) => decode_note_change(true, 0, &data[4..]),
+        (NON_REALTIME, NOTE_CHANGE_BANK) | (REALTIME, NOTE_CHANGE_BANK) if data.len() > 4 => {
+            decode_note_change(data[0] == REALTIME, data[4], &data[5..])
+        },
+        _ => None
+    }
+}
+
+/// Program, name, 128 keys and the checksum, which is the XOR of all the previous bytes
+fn decode_bulk_dump(data: &[U7], bank: U7, body: &[U7]) -> Option<TuningMessage> {
+    if body.len() != 1 + NAME_SIZE + 3 * NUM_KEYS + 1 {
+        return None;
+    }
+
+    let checksum = data[..data.len() - 1].iter().fold(0, |sum, byte| sum ^ byte) & 0x7f;
+    if checksum != data[data.len() - 1] {
+        return None;
+    }
+
+    let program = body[0];
+    let name = String::from_utf8_lossy(&body[1..1 + NAME_SIZE]).trim_end_matches(|c| c == ' ' || c == '\0').to_string();
+    let key_freqs = body[1 + NAME_SIZE..1 + NAME_SIZE + 3 * NUM_KEYS].chunks(3).enumerate()
+        .filter_map(|(key, bytes)| key_freq(bytes[0], bytes[1], bytes[2]).map(|freq| (key as U7, freq)))
+        .collect();
+
+    Some(TuningMessage::BulkDump { bank: bank, program: program, name: name, key_freqs: key_freqs })
+}
+
+/// Program, number of changes and the changes with the key and its tuning
+fn decode_note_change(realtime: bool, bank: U7, body: &[U7]) -> Option<TuningMessage> {
+    if body.len() < 2 || body.len() != 2 + 4 * body[1] as usize {
+        return None;
+    }
+
+    let program = body[0];
+    let key_freqs = body[2..].chunks(4)
+        .filter_map(|bytes| key_freq(bytes[1], bytes[2], bytes[3]).map(|freq| (bytes[0], freq)))
+        .collect();
+
+    Some(TuningMessage::NoteChange { realtime: realtime, bank: bank, program: program, key_freqs: key_freqs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk_dump(header: &[U7], keys: &[(U7, U7, U7)]) -> Vec<U7> {
+        let mut data = header.to_vec();
+        data.extend(b"Test tuning     ");
+        for &(semitone, msb, lsb) in keys.iter() {
+            data.extend(&[semitone, msb, lsb]);
+        }
+        let checksum = data.iter().fold(0, |sum, byte| sum ^ byte) & 0x7f;
+        data.push(checksum);
+        data
+    }
+
+    #[test]
+    fn decode_bulk_dump() {
+        // Equal temperament a quarter tone up, the last key is not changed
+        let mut keys: Vec<(U7, U7, U7)> = (0..128).map(|key| (key as U7, 0x40, 0x00)).collect();
+        keys[127] = NO_CHANGE;
+        let data = bulk_dump(&[0x7e, 0x7f, 0x08, 0x01, 5], &keys);
+
+        match decode(&data) {
+            Some(TuningMessage::BulkDump { bank, program, ref name, ref key_freqs }) => {
+                assert_eq!((bank, program), (0, 5));
+                assert_eq!(name, "Test tuning");
+                assert_eq!(key_freqs.len(), 127);
+                assert_eq!(key_freqs[69].0, 69);
+                assert!((key_freqs[69].1 - 440.0 * (2_f64).powf(0.5 / 12.0)).abs() < 1e-9);
+            },
+            message => panic!("{:?}", message)
+        }
+
+        let data = bulk_dump(&[0x7e, 0x00, 0x08, 0x04, 2, 3], &keys);
+        match decode(&data) {
+            Some(TuningMessage::BulkDump { bank, program, .. }) => assert_eq!((bank, program), (2, 3)),
+            message => panic!("{:?}", message)
+        }
+
+        // Wrong checksum or size
+        let mut data = bulk_dump(&[0x7e, 0x7f, 0x08, 0x01, 5], &keys);
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert_eq!(decode(&data), None);
+        assert_eq!(decode(&bulk_dump(&[0x7e, 0x7f, 0x08, 0x01, 5], &keys[1..])), None);
+    }
+
+    #[test]
+    fn decode_note_change() {
+        let data = vec![0x7f, 0x7f, 0x08, 0x02, 0, 2, 69, 69, 0, 0, 60, 0x7f, 0x7f, 0x7f];
+        let message = decode(&data).unwrap();
+        assert!(message.is_realtime());
+        assert_eq!(message.key_freqs(), &[(69, 440.0)]);
+
+        let data = vec![0x7e, 0x7f, 0x08, 0x07, 1, 0, 1, 60, 69, 0, 0];
+        let message = decode(&data).unwrap();
+        assert!(!message.is_realtime());
+        assert_eq!(message.key_freqs(), &[(60, 440.0)]);
+
+        // Wrong number of changes, and not a tuning message
+        assert_eq!(decode(&[0x7f, 0x7f, 0x08, 0x02, 0, 2, 69, 69, 0, 0]), None);
+        assert_eq!(decode(&[0x7f, 0x7f, 0x09, 0x01, 0x7f]), None);
+    }
+}
@@ -0,0 +1,295 @@
+//!
+//! FM algorithms: named topologies of carriers and modulators that can be applied to a patch,
+//! and the analysis of the modulation graph of a patch.
+//!
+
+use std::collections::HashSet;
+
+use patch::{Patch, OscPatch};
+use voice::MAX_OSCILLATORS;
+
+/// Send level for the modulations that are not already in the patch
+const DEFAULT_MOD_LEVEL: f64 = 0.5;
+
+/// Modulations as (modulator, carrier) pairs of 1-based operators, and the operator with feedback.
+/// In the algorithms 4 and 6 the DX7 feeds back the operator 4 (or 5) into the operator 6,
+/// which is approximated as the feedback of the operator 6.
+const DX7: [(&'static [(usize, usize)], usize); 32] = [
+    (&[(2, 1), (4, 3), (5, 4), (6, 5)], 6),
+    (&[(2, 1), (4, 3), (5, 4), (6, 5)], 2),
+    (&[(2, 1), (3, 2), (5, 4), (6, 5)], 6),
+    (&[(2, 1), (3, 2), (5, 4), (6, 5)], 6),
+    (&[(2, 1), (4, 3), (6, 5)], 6),
+    (&[(2, 1), (4, 3), (6, 5)], 6),
+    (&[(2, 1), (4, 3), (5, 3), (6, 5)], 6),
+    (&[(2, 1), (4, 3), (5, 3), (6, 5)], 4),
+    (&[(2, 1), (4, 3), (5, 3), (6, 5)], 2),
+    (&[(2, 1), (3, 2), (5, 4), (6, 4)], 3),
+    (&[(2, 1), (3, 2), (5, 4), (6, 4)], 6),
+    (&[(2, 1), (4, 3), (5, 3), (6, 3)], 2),
+    (&[(2, 1), (4, 3), (5, 3), (6, 3)], 6),
+    (&[(2, 1), (4, 3), (5, 4), (6, 4)], 6),
+    (&[(2, 1), (4, 3), (5, 4), (6, 4)], 2),
+    (&[(2, 1), (3, 1), (4, 3), (5, 1), (6, 5)], 6),
+    (&[(2, 1), (3, 1), (4, 3), (5, 1), (6, 5)], 2),
+    (&[(2, 1), (3, 1), (4, 1), (5, 4), (6, 5)], 3),
+    (&[(2, 1), (3, 2), (6, 4), (6, 5)], 6),
+    (&[(3, 1), (3, 2), (5, 4), (6, 4)], 3),
+    (&[(3, 1), (3, 2), (6, 4), (6, 5)], 3),
+    (&[(2, 1), (6, 3), (6, 4), (6, 5)], 6),
+    (&[(3, 2), (6, 4), (6, 5)], 6),
+    (&[(6, 3), (6, 4), (6, 5)], 6),
+    (&[(6, 4), (6, 5)], 6),
+    (&[(3, 2), (5, 4), (6, 4)], 6),
+    (&[(3, 2), (5, 4), (6, 4)], 3),
+    (&[(2, 1), (4, 3), (5, 4)], 5),
+    (&[(4, 3), (6, 5)], 6),
+    (&[(4, 3), (5, 4)], 5),
+    (&[(6, 5)], 6),
+    (&[], 6),
+];
+
+/// Extensions that use the 8 oscillators
+const EXT: [(&'static [(usize, usize)], usize); 8] = [
+    // A single stack
+    (&[(2, 1), (3, 2), (4, 3), (5, 4), (6, 5), (7, 6), (8, 7)], 8),
+    // Two stacks of four
+    (&[(2, 1), (3, 2), (4, 3), (6, 5), (7, 6), (8, 7)], 8),
+    // Four pairs
+    (&[(2, 1), (4, 3), (6, 5), (8, 7)], 8),
+    // Three stacks
+    (&[(2, 1), (3, 2), (5, 4), (6, 5), (8, 7)], 8),
+    // The DX7 algorithm 1 plus a pair
+    (&[(2, 1), (4, 3), (5, 4), (6, 5), (8, 7)], 6),
+    // Seven modulators in parallel into one carrier
+    (&[(2, 1), (3, 1), (4, 1), (5, 1), (6, 1), (7, 1), (8, 1)], 8),
+    // One modulator into seven carriers
+    (&[(8, 1), (8, 2), (8, 3), (8, 4), (8, 5), (8, 6), (8, 7)], 8),
+    // Additive
+    (&[], 8),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Algorithm {
+    num_operators: usize,
+    modulations: Vec<(usize, usize)>,   // (modulator, carrier) oscillator indices
+    feedback: usize,                    // Oscillator index with feedback
+}
+
+impl Algorithm {
+    /// Algorithms are named dx1 to dx32 for the DX7 ones, and ext1 to ext8 for the 8 operator ones
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        let (table, num_operators, number): (&[(&'static [(usize, usize)], usize)], usize, &str) =
+            if name.starts_with("dx") { (&DX7, 6, &name[2..]) }
+            else if name.starts_with("ext") { (&EXT, MAX_OSCILLATORS, &name[3..]) }
+            else { return None };
+
+        match number.parse::<usize>() {
+            Ok(number) if number >= 1 && number <= table.len() => {
+                let (modulations, feedback) = table[number - 1];
+                Some(Algorithm {
+                    num_operators: num_operators,
+                    modulations: modulations.iter().map(|&(m, c)| (m - 1, c - 1)).collect(),
+                    feedback: feedback - 1
+                })
+            },
+            _ => None
+        }
+    }
+
+    pub fn names() -> Vec<String> {
+        let dx7 = (1..DX7.len() + 1).map(|number| format!("dx{}", number));
+        let ext = (1..EXT.len() + 1).map(|number| format!("ext{}", number));
+        dx7.chain(ext).collect()
+    }
+
+    pub fn num_operators(&self) -> usize {
+        self.num_operators
+    }
+
+    pub fn modulations(&self) -> &[(usize, usize)] {
+        &self.modulations
+    }
+
+    pub fn feedback(&self) -> usize {
+        self.feedback
+    }
+
+    pub fn carriers(&self) -> Vec<usize> {
+        (0..self.num_operators)
+            .filter(|index| self.modulations.iter().all(|&(modulator, _)| modulator != *index))
+            .collect()
+    }
+
+    /// Sets up the oscillators of the patch as the operators of the algorithm in phase modulation mode.
+    /// The levels of the modulations already in the patch are kept.
+    pub fn apply(&self, patch: &mut Patch) {
+        while patch.oscillators.len() < self.num_operators {
+            patch.oscillators.push(OscPatch::default());
+        }
+        patch.fm_mode = "pm".to_string();
+
+        let carriers = self.carriers();
+        for (index, osc) in patch.oscillators.iter_mut().enumerate() {
+            let previous = osc.freq_mod.clone();
+            osc.freq_mod.clear();
+            for &(_, carrier) in self.modulations.iter().filter(|&&(modulator, _)| modulator == index) {
+                let level = match previous.get(&carrier) {
+                    Some(level) if *level != 0.0 => *level,
+                    _ => DEFAULT_MOD_LEVEL
+                };
+                osc.freq_mod.insert(carrier, level);
+            }
+
+            osc.level = if carriers.contains(&index) { 1.0 } else { 0.0 };
+            if index != self.feedback {
+                osc.feedback = 0.0;
+            }
+        }
+    }
+}
+
+/// The roles of the oscillators in the frequency modulation graph of a patch
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    pub carriers: Vec<usize>,       // Oscillators mixed into the output
+    pub modulators: Vec<usize>,     // Oscillators modulating other oscillators
+    pub cycles: Vec<Vec<usize>>,    // Modulation loops, including the oscillators modulating themselves
+}
+
+impl Analysis {
+    pub fn new(patch: &Patch) -> Analysis {
+        let num_osc = patch.oscillators.len();
+        let edges: Vec<Vec<usize>> = patch.oscillators.iter()
+            .map(|osc| {
+                let mut dst: Vec<usize> = osc.freq_mod.iter()
+                    .filter(|&(index, level)| *index < num_osc && *level != 0.0)
+                    .map(|(index, _)| *index)
+                    .collect();
+                dst.sort();
+                dst
+            })
+            .collect();
+
+        let carriers = (0..num_osc).filter(|i| patch.oscillators[*i].level != 0.0).collect();
+        let modulators = (0..num_osc).filter(|i| !edges[*i].is_empty()).collect();
+
+        let mut cycles = Vec::new();
+        let mut found = HashSet::new();
+        let mut path = Vec::new();
+        for start in 0..num_osc {
+            Self::find_cycles(&edges, start, &mut path, &mut cycles, &mut found);
+        }
+
+        Analysis {
+            carriers: carriers,
+            modulators: modulators,
+            cycles: cycles
+        }
+    }
+
+    /// Depth first search of the paths from the start that return to one of their oscillators.
+    /// Cycles are normalised to start at their lowest oscillator so every one is reported once.
+    fn find_cycles(edges: &[Vec<usize>], index: usize, path: &mut Vec<usize>,
+                   cycles: &mut Vec<Vec<usize>>, found: &mut HashSet<Vec<usize>>) {
+
+        if let Some(pos) = path.iter().position(|i| *i == index) {
+            let mut cycle = path[pos..].to_vec();
+            let min_pos = (0..cycle.len()).min_by_key(|i| cycle[*i]).unwrap();
+            cycle.rotate_left(min_pos);
+            if found.insert(cycle.clone()) {
+                cycles.push(cycle);
+            }
+            return;
+        }
+
+        path.push(index);
+        for dst in edges[index].iter() {
+            Self::find_cycles(edges, *dst, path, cycles, found);
+        }
+        path.pop();
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.cycles.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn algorithm_names() {
+        let names = Algorithm::names();
+        assert_eq!(names.len(), 40);
+        for name in names.iter() {
+            assert!(Algorithm::from_name(name).is_some(), "{}", name);
+        }
+        assert!(Algorithm::from_name("dx0").is_none());
+        assert!(Algorithm::from_name("dx33").is_none());
+        assert!(Algorithm::from_name("ext9").is_none());
+        assert!(Algorithm::from_name("fm").is_none());
+    }
+
+    #[test]
+    fn algorithms_are_valid() {
+        for name in Algorithm::names() {
+            let algorithm = Algorithm::from_name(&name).unwrap();
+            let mut patch = Patch::default();
+            algorithm.apply(&mut patch);
+            let analysis = Analysis::new(&patch);
+            assert!(analysis.is_valid(), "{}", name);
+            assert_eq!(analysis.carriers, algorithm.carriers(), "{}", name);
+            for index in 0..algorithm.num_operators() {
+                assert!(analysis.carriers.contains(&index) != analysis.modulators.contains(&index), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn dx7_carriers() {
+        let carriers = |name: &str| Algorithm::from_name(name).unwrap().carriers();
+        assert_eq!(carriers("dx1"), vec![0, 2]);
+        assert_eq!(carriers("dx5"), vec![0, 2, 4]);
+        assert_eq!(carriers("dx16"), vec![0]);
+        assert_eq!(carriers("dx19"), vec![0, 3, 4]);
+        assert_eq!(carriers("dx32"), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(Algorithm::from_name("dx2").unwrap().feedback(), 1);
+    }
+
+    #[test]
+    fn apply_keeps_levels() {
+        let mut patch = Patch::default();
+        patch.oscillators[1].freq_mod.insert(0, 0.8);
+        patch.oscillators[3].feedback = 0.5;
+        Algorithm::from_name("dx1").unwrap().apply(&mut patch);
+
+        assert_eq!(patch.fm_mode, "pm");
+        assert_eq!(patch.oscillators.len(), 6);
+        assert_eq!(patch.oscillators[1].freq_mod.get(&0), Some(&0.8));
+        assert_eq!(patch.oscillators[3].freq_mod.get(&2), Some(&DEFAULT_MOD_LEVEL));
+        assert_eq!(patch.oscillators[3].feedback, 0.0);
+        assert!(patch.oscillators[0].freq_mod.is_empty());
+        assert_eq!(patch.oscillators[1].level, 0.0);
+        assert_eq!(patch.oscillators[2].level, 1.0);
+    }
+
+    #[test]
+    fn analysis_finds_cycles() {
+        let mut patch = Patch::default();
+        for osc in patch.oscillators.iter_mut() {
+            osc.freq_mod.clear();
+        }
+        patch.oscillators[0].freq_mod.insert(1, 0.5);
+        patch.oscillators[1].freq_mod.insert(2, 0.5);
+        patch.oscillators[2].freq_mod.insert(0, 0.5);
+        patch.oscillators[3].freq_mod.insert(3, 0.5);
+        patch.oscillators[3].freq_mod.insert(1, 0.0);
+
+        let analysis = Analysis::new(&patch);
+        assert!(!analysis.is_valid());
+        assert_eq!(analysis.cycles, vec![vec![0, 1, 2], vec![3]]);
+        assert_eq!(analysis.modulators, vec![0, 1, 2, 3]);
+    }
+}
@@ -1,6 +1,13 @@
 extern crate rosc;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate hero_core;
 
+#[cfg(test)]
+extern crate serde_json;
+
 pub mod patch;
+pub mod algorithm;
 pub mod voice;
 pub mod synth;
@@ -1,15 +1,75 @@
 use std::collections::HashMap;
+use std::f64::consts::PI;
 
-use hero_core::wavetable::{self, Wavetable};
-use hero_core::oscillator::Oscillator;
-use hero_core::types::SampleRate;
+use hero_core::wavetable::{self, Wavetable, Interpolation, Registry};
+use hero_core::oscillator::{Oscillator, Source, SyncMode};
+use hero_core::noise;
+use hero_core::analog;
+use hero_core::envelope::Curve;
+use hero_core::envelope::adsr::Adsr;
+use hero_core::envelope::mseg::{Breakpoint, Mseg};
+use hero_core::filter::{Filter, Mode, Slope};
+use hero_core::filter::iir::IIR;
+use hero_core::filter::svf::Svf;
+use hero_core::filter::ladder::Ladder;
+use hero_core::filter::comb::Comb;
+use hero_core::filter::formant::Formant;
+use hero_core::filter::eq::{BandKind, Equalizer};
+use hero_core::lfo::{self, Lfo, NoteValue};
+use hero_core::panning;
+use hero_core::types::{SampleRate, Tempo};
 
+use voice::{MAX_FILTERS, MAX_ENVELOPES, MAX_LFOS};
 
-#[derive(Clone, Debug)]
+/// Phase modulation in radians for the maximum feedback
+const MAX_FEEDBACK: f64 = PI;
+
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EnvelopePatch {
+    pub attack: f64,               // Attack time in seconds
+    pub decay: f64,                // Decay time in seconds
+    pub sustain: f64,              // Sustain level [0, 1]
+    pub release: f64,              // Release time in seconds
+    pub curve: String,
+}
+
+impl Default for EnvelopePatch {
+    fn default() -> Self {
+        EnvelopePatch {
+            attack: 0.005,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.1,
+            curve: "exp".to_string(),
+        }
+    }
+}
+
+impl EnvelopePatch {
+    pub fn get_curve(&self) -> Curve {
+        match Curve::from_name(&self.curve) {
+            Some(curve) => curve,
+            None => Curve::Linear,
+        }
+    }
+
+    pub fn to_envelope(&self, sample_rate: SampleRate) -> Adsr {
+        Adsr::new(sample_rate, self.attack, self.decay, self.sustain, self.release, self.get_curve())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct OscPatch {
     pub is_enabled: bool,
     pub amplitude: f64,            // Oscillator signal amplitude
+    pub source: String,            // Signal source: wavetable, noise or analog
     pub wavetable: String,
+    pub noise: String,             // Noise color: white, pink or brown
+    pub analog: String,            // Analog waveform: saw, square or triangle
+    pub pulse_width: f64,          // Pulse width for the analog waveforms (0, 1)
+    pub interpolation: String,     // Interpolation mode for the wavetable
+    pub position: f64,             // Position across the frames of the wavetable [0, 1]
     pub is_free_phase: bool,
     pub initial_phase: f64,
     pub is_fixed_freq: bool,       // When it is true the baseFrequency doesn't change with noteOn
@@ -17,9 +77,17 @@ pub struct OscPatch {
     pub octaves: f64,              // Number of octaves to shift from the base_frequency
     pub semitones: f64,            // Number of semitones to shift from the base_frequency
     pub detune: f64,               // Fine shift from the base_frequency
+    pub sync_source: Option<usize>, // Oscillator to sync the phase to
+    pub sync_mode: String,         // Sync mode: off, hard or soft
+    pub feedback: f64,             // Self phase modulation [0, 1]
+
+    pub amp_env: EnvelopePatch,    // Amplitude envelope
 
     pub amp_mod: HashMap<usize, f64>,   // Send levels for amplitude modulation
+    pub ring_mod: HashMap<usize, f64>,  // Send levels for ring modulation
     pub freq_mod: HashMap<usize, f64>,   // Send levels for frequency modulation
+    pub pos_mod: HashMap<usize, f64>,   // Send levels for wavetable position modulation
+    pub pw_mod: HashMap<usize, f64>,    // Send levels for pulse width modulation
     pub filt_send: HashMap<usize, f64>, // Send levels for the filter input
 
     pub level: f64,                // Mix level
@@ -31,7 +99,13 @@ impl Default for OscPatch {
         OscPatch {
             is_enabled: true,
             amplitude: 1.0,
+            source: "wavetable".to_string(),
             wavetable: "sin".to_string(),
+            noise: "white".to_string(),
+            analog: "saw".to_string(),
+            pulse_width: 0.5,
+            interpolation: "linear".to_string(),
+            position: 0.0,
             is_free_phase: false,
             initial_phase: 0.0,
             is_fixed_freq: false,
@@ -39,9 +113,17 @@ impl Default for OscPatch {
             octaves: 0.0,
             semitones: 0.0,
             detune: 0.0,
+            sync_source: None,
+            sync_mode: "off".to_string(),
+            feedback: 0.0,
+
+            amp_env: EnvelopePatch::default(),
 
             amp_mod: HashMap::new(),
+            ring_mod: HashMap::new(),
             freq_mod: HashMap::new(),
+            pos_mod: HashMap::new(),
+            pw_mod: HashMap::new(),
             filt_send: HashMap::new(),
 
             level: 1.0,
@@ -51,17 +133,57 @@ impl Default for OscPatch {
 }
 
 impl OscPatch {
-    pub fn get_wavetable(&self) -> Wavetable {
-        let wt_stock = match wavetable::Stock::from_name(&self.wavetable) {
-            Some(stock) => stock,
-            None => wavetable::Stock::Sin,
-        };
-        Wavetable::from_stock(wt_stock)
+    pub fn get_wavetable(&self, registry: &Registry) -> Result<Wavetable, wavetable::Error> {
+        registry.get(&self.wavetable)
+    }
+
+    pub fn get_source(&self) -> Source {
+        match self.source.as_ref() {
+            "noise" => {
+                let color = match noise::Color::from_name(&self.noise) {
+                    Some(color) => color,
+                    None => noise::Color::White,
+                };
+                Source::Noise(color)
+            },
+            "analog" => {
+                let waveform = match analog::Waveform::from_name(&self.analog) {
+                    Some(waveform) => waveform,
+                    None => analog::Waveform::Saw,
+                };
+                Source::Analog(waveform)
+            },
+            _ => Source::Wavetable
+        }
+    }
+
+    pub fn get_sync_mode(&self) -> SyncMode {
+        match SyncMode::from_name(&self.sync_mode) {
+            Some(mode) => mode,
+            None => SyncMode::Off,
+        }
+    }
+
+    /// Feedback as the phase modulation in radians
+    pub fn get_feedback(&self) -> f64 {
+        self.feedback * MAX_FEEDBACK
+    }
+
+    pub fn get_interpolation(&self) -> Interpolation {
+        match Interpolation::from_name(&self.interpolation) {
+            Some(interpolation) => interpolation,
+            None => Interpolation::Linear,
+        }
     }
 
-    pub fn to_oscillator(&self, sample_rate: SampleRate) -> Oscillator {
-        let wavetable = self.get_wavetable();
+    pub fn to_oscillator(&self, sample_rate: SampleRate, registry: &Registry) -> Result<Oscillator, wavetable::Error> {
+        let wavetable = self.get_wavetable(registry)?;
         let mut o = Oscillator::new(sample_rate, wavetable, self.base_frequency);
+        o.set_source(self.get_source());
+        o.set_interpolation(self.get_interpolation());
+        o.set_position(self.position);
+        o.set_feedback(self.get_feedback());
+        o.set_pulse_width(self.pulse_width);
         o.set_enabled(self.is_enabled);
         o.set_amplitude(self.amplitude);
         o.set_free_phase(self.is_free_phase);
@@ -69,30 +191,322 @@ impl OscPatch {
         o.set_octaves(self.octaves);
         o.set_semitones(self.semitones);
         o.set_detune(self.detune);
-        o
+        Ok(o)
     }
 }
 
-#[derive(Clone, Debug)]
+/// Implementation of a filter. The comb and formant modes always use their own filters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    Iir,
+    Svf,
+    Ladder,
+    Comb,
+    Formant,
+}
+
+impl FilterKind {
+    pub fn from_name(name: &str) -> Option<FilterKind> {
+        match name {
+            "iir" => Some(FilterKind::Iir),
+            "svf" => Some(FilterKind::Svf),
+            "ladder" => Some(FilterKind::Ladder),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FilterPatch {
-    // TODO filter params
+    pub kind: String,              // Filter implementation: iir, svf or ladder
     pub mode: String,
-    pub slope: String,
-    pub freq: f64,
-    pub res: f64,
+    pub slope: String,             // Slope in dB per octave: 6, 12, 18 or 24
+    pub freq: f64,                 // Cutoff frequency
+    pub res: f64,                  // Resonance [0, 1]
+    pub drive: f64,                // Input gain of the ladder filter
+    pub vowel: f64,                // Vowel of the formant filter [0, 4]
+    pub key_tracking: f64,         // How much the cutoff follows the key, 1 to follow it exactly
+    pub env_amount: f64,           // Scale of the cutoff modulation by the envelopes
+    pub velocity: f64,             // Cutoff shift in octaves at full velocity
 
     pub amp_mod: HashMap<usize, f64>,   // Send levels for amplitude modulation
     pub freq_mod: HashMap<usize, f64>,   // Send levels for frequency modulation
-    pub filt_send: HashMap<usize, f64>, // Send levels for the filter input
+    pub filt_send: HashMap<usize, f64>, // Send levels for the input of the following filters
 
     pub panning: f64,              // Panning [-1, +1]
     pub level: f64,                // Mix level
 }
 
-#[derive(Clone, Debug)]
+impl Default for FilterPatch {
+    fn default() -> Self {
+        FilterPatch {
+            kind: "iir".to_string(),
+            mode: "bypass".to_string(),
+            slope: "12".to_string(),
+            freq: 1000.0,
+            res: 0.0,
+            drive: 1.0,
+            vowel: 0.0,
+            key_tracking: 0.0,
+            env_amount: 1.0,
+            velocity: 0.0,
+
+            amp_mod: HashMap::new(),
+            freq_mod: HashMap::new(),
+            filt_send: HashMap::new(),
+
+            panning: 0.0,
+            level: 1.0,
+        }
+    }
+}
+
+impl FilterPatch {
+    pub fn get_mode(&self) -> Mode {
+        match Mode::from_name(&self.mode) {
+            Some(mode) => mode,
+            None => Mode::ByPass,
+        }
+    }
+
+    pub fn get_slope(&self) -> Slope {
+        match Slope::from_name(&self.slope) {
+            Some(slope) => slope,
+            None => Slope::Slope12,
+        }
+    }
+
+    pub fn get_kind(&self) -> FilterKind {
+        match self.get_mode() {
+            Mode::CombFeedForward | Mode::CombFeedBack => FilterKind::Comb,
+            Mode::Formant => FilterKind::Formant,
+            _ => match FilterKind::from_name(&self.kind) {
+                Some(kind) => kind,
+                None => FilterKind::Iir,
+            }
+        }
+    }
+
+    pub fn to_filter(&self, sample_rate: SampleRate) -> Box<dyn Filter> {
+        let (mode, slope) = (self.get_mode(), self.get_slope());
+        let mut f: Box<dyn Filter> = match self.get_kind() {
+            FilterKind::Iir => Box::new(IIR::new(mode, slope, sample_rate, self.freq, self.res)),
+            FilterKind::Svf => Box::new(Svf::new(mode, slope, sample_rate, self.freq, self.res)),
+            FilterKind::Ladder => Box::new(Ladder::new(mode, slope, sample_rate, self.freq, self.res)),
+            FilterKind::Comb => Box::new(Comb::new(mode, sample_rate, self.freq, self.res)),
+            FilterKind::Formant => Box::new(Formant::new(sample_rate, self.freq, self.res, self.vowel)),
+        };
+        f.set_drive(self.drive);
+        f
+    }
+}
+
+/// Band of the equalizer
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EqBandPatch {
+    pub kind: String,              // peak, low-shelf or high-shelf
+    pub freq: f64,                 // Center or corner frequency
+    pub q: f64,
+    pub gain: f64,                 // Gain in dB
+}
+
+impl EqBandPatch {
+    pub fn new(kind: &str, freq: f64, q: f64, gain: f64) -> EqBandPatch {
+        EqBandPatch {
+            kind: kind.to_string(),
+            freq: freq,
+            q: q,
+            gain: gain
+        }
+    }
+
+    pub fn get_kind(&self) -> BandKind {
+        match BandKind::from_name(&self.kind) {
+            Some(kind) => kind,
+            None => BandKind::Peak,
+        }
+    }
+}
+
+/// Updates the bands of an equalizer to match the patch, keeping the state of the existing bands
+pub fn update_equalizer(eq: &mut Equalizer, bands: &[EqBandPatch]) {
+    eq.truncate(bands.len());
+    for (index, band) in bands.iter().enumerate() {
+        if index < eq.bands().len() {
+            eq.set_band(index, band.get_kind(), band.freq, band.q, band.gain);
+        }
+        else {
+            eq.add_band(band.get_kind(), band.freq, band.q, band.gain);
+        }
+    }
+}
+
+/// Modulation depths of a modulation source for every destination
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ModSends {
+    pub amp: HashMap<usize, f64>,       // Amplitude depth per oscillator [0, 1]
+    pub pitch: HashMap<usize, f64>,     // Pitch depth in semitones per oscillator
+    pub position: HashMap<usize, f64>,  // Wavetable position depth per oscillator
+    pub pulse_width: HashMap<usize, f64>, // Pulse width depth per oscillator
+    pub cutoff: HashMap<usize, f64>,    // Cutoff depth in octaves per filter
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BreakpointPatch {
+    pub time: f64,                 // Time in seconds from the previous breakpoint
+    pub level: f64,
+    pub curve: f64,                // Curvature [-1, 1]
+}
+
+impl BreakpointPatch {
+    pub fn new(time: f64, level: f64, curve: f64) -> BreakpointPatch {
+        BreakpointPatch {
+            time: time,
+            level: level,
+            curve: curve
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MsegPatch {
+    pub breakpoints: Vec<BreakpointPatch>,
+    pub sustain: Option<usize>,
+    pub loop_start: Option<usize>,
+    pub loop_end: Option<usize>,
+
+    pub mod_sends: ModSends,
+}
+
+impl Default for MsegPatch {
+    fn default() -> Self {
+        MsegPatch {
+            breakpoints: vec![
+                BreakpointPatch::new(0.01, 1.0, 0.0),
+                BreakpointPatch::new(0.5, 0.0, 0.0)],
+            sustain: None,
+            loop_start: None,
+            loop_end: None,
+
+            mod_sends: ModSends::default(),
+        }
+    }
+}
+
+impl MsegPatch {
+    pub fn get_breakpoints(&self) -> Vec<Breakpoint> {
+        self.breakpoints.iter()
+            .map(|bp| Breakpoint::new(bp.time, bp.level, bp.curve))
+            .collect()
+    }
+
+    pub fn get_loop(&self) -> Option<(usize, usize)> {
+        match (self.loop_start, self.loop_end) {
+            (Some(start), Some(end)) => Some((start, end)),
+            _ => None
+        }
+    }
+
+    pub fn to_envelope(&self, sample_rate: SampleRate) -> Mseg {
+        let mut env = Mseg::new(sample_rate, self.get_breakpoints());
+        env.set_sustain(self.sustain);
+        env.set_loop(self.get_loop());
+        env
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LfoPatch {
+    pub shape: String,             // sine, triangle, saw-up, saw-down, square, sample-hold or random
+    pub freq: f64,                 // Rate in Hz when it is not synced
+    pub sync: String,              // Note value of the period synced to the tempo, like 1/4, 1/8d or 1/16t, or off
+    pub phase: f64,                // Phase offset at the start of a note [0, 1)
+    pub delay: f64,                // Time in seconds before the output starts
+    pub fade_in: f64,              // Time in seconds of the fade in after the delay
+    pub is_global: bool,           // One LFO shared by all the voices instead of one per voice restarted by the notes
+
+    pub mod_sends: ModSends,
+}
+
+impl Default for LfoPatch {
+    fn default() -> Self {
+        LfoPatch {
+            shape: "sine".to_string(),
+            freq: 1.0,
+            sync: "off".to_string(),
+            phase: 0.0,
+            delay: 0.0,
+            fade_in: 0.0,
+            is_global: false,
+
+            mod_sends: ModSends::default(),
+        }
+    }
+}
+
+impl LfoPatch {
+    pub fn get_shape(&self) -> lfo::Shape {
+        match lfo::Shape::from_name(&self.shape) {
+            Some(shape) => shape,
+            None => lfo::Shape::Sine,
+        }
+    }
+
+    pub fn get_rate(&self) -> lfo::Rate {
+        match NoteValue::from_name(&self.sync) {
+            Some(note_value) => lfo::Rate::Sync(note_value),
+            None => lfo::Rate::Free(self.freq),
+        }
+    }
+
+    pub fn to_lfo(&self, sample_rate: SampleRate, tempo: Tempo) -> Lfo {
+        let mut lfo = Lfo::new(sample_rate, self.get_shape(), self.get_rate());
+        lfo.set_tempo(tempo);
+        update_lfo(&mut lfo, self);
+        lfo.reset();
+        lfo
+    }
+}
+
+/// Sets the parameters of an LFO, without restarting it
+pub fn update_lfo(lfo: &mut Lfo, patch_lfo: &LfoPatch) {
+    lfo.set_shape(patch_lfo.get_shape());
+    lfo.set_rate(patch_lfo.get_rate());
+    lfo.set_phase(patch_lfo.phase);
+    lfo.set_delay(patch_lfo.delay);
+    lfo.set_fade_in(patch_lfo.fade_in);
+}
+
+/// How the freq_mod sends modulate the oscillators
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FmMode {
+    Frequency,      // The modulator deviates the frequency by a fixed index
+    Phase,          // The modulator offsets the phase, as in the DX7
+}
+
+impl FmMode {
+    pub fn from_name(name: &str) -> Option<FmMode> {
+        match name {
+            "fm" => Some(FmMode::Frequency),
+            "pm" => Some(FmMode::Phase),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Patch {
+    pub fm_mode: String,                // Modulation mode for the freq_mod sends: fm or pm
     pub oscillators: Vec<OscPatch>,
     pub filters: Vec<FilterPatch>,
+    pub envelopes: Vec<MsegPatch>,      // Multi-stage envelopes used as modulation sources
+    pub lfos: Vec<LfoPatch>,            // Low frequency oscillators used as modulation sources
+    pub eq: Vec<EqBandPatch>,           // Tone shaping bands applied to the output of every voice
+    pub oversampling: usize,            // Factor of the sample rate the voices run at: 1, 2, 4 or 8
+    pub smoothing: f64,                 // Ramp time in seconds of the continuous parameters changed while playing
+    pub pan_law: String,                // Panning law of the oscillators and filters: linear, -3db, -4.5db or -6db
+    pub width: f64,                     // Stereo width of the output, 0 for mono
+    pub balance: f64,                   // Balance of the output [-1, 1]
 }
 
 impl Default for Patch {
@@ -134,8 +548,33 @@ impl Default for Patch {
         o3.freq_mod.insert(O1, 0.70);
 
         Patch {
+            fm_mode: "fm".to_string(),
             oscillators: vec![o1, o2, o3, o4],
-            filters: Vec::new()
+            filters: vec![FilterPatch::default(); MAX_FILTERS],
+            envelopes: vec![MsegPatch::default(); MAX_ENVELOPES],
+            lfos: vec![LfoPatch::default(); MAX_LFOS],
+            eq: Vec::new(),
+            oversampling: 1,
+            smoothing: 0.02,
+            pan_law: "-3db".to_string(),
+            width: 1.0,
+            balance: 0.0,
+        }
+    }
+}
+
+impl Patch {
+    pub fn get_pan_law(&self) -> panning::Law {
+        match panning::Law::from_name(&self.pan_law) {
+            Some(law) => law,
+            None => panning::Law::default(),
+        }
+    }
+
+    pub fn get_fm_mode(&self) -> FmMode {
+        match FmMode::from_name(&self.fm_mode) {
+            Some(mode) => mode,
+            None => FmMode::Frequency,
         }
     }
 }
@@ -148,3 +587,74 @@ pub fn load(path: &Path) -> io::Result<Patch> {
     //try!(Json::from_str(&data))
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn patch_serialization() {
+        let mut patch = Patch::default();
+        patch.envelopes[1].sustain = Some(1);
+        patch.envelopes[1].mod_sends.pitch.insert(2, 12.0);
+
+        let json = serde_json::to_string(&patch).unwrap();
+        let decoded: Patch = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.oscillators.len(), patch.oscillators.len());
+        assert_eq!(decoded.envelopes.len(), patch.envelopes.len());
+        assert_eq!(decoded.envelopes[1].sustain, Some(1));
+        assert_eq!(decoded.envelopes[1].mod_sends.pitch.get(&2), Some(&12.0));
+        assert_eq!(decoded.envelopes[0].breakpoints.len(), 2);
+    }
+
+    #[test]
+    fn osc_patch_unknown_wavetable() {
+        let registry = Registry::default();
+        let mut osc_patch = OscPatch::default();
+        osc_patch.wavetable = "saw".to_string();
+        assert!(osc_patch.to_oscillator(44100.0, &registry).is_ok());
+
+        osc_patch.wavetable = "missing".to_string();
+        match osc_patch.to_oscillator(44100.0, &registry) {
+            Err(wavetable::Error::UnknownName(name)) => assert_eq!(name, "missing"),
+            _ => panic!("expected an unknown wavetable error")
+        }
+    }
+
+    #[test]
+    fn filter_patch_kind() {
+        let mut filter_patch = FilterPatch::default();
+        assert_eq!(filter_patch.get_kind(), FilterKind::Iir);
+
+        filter_patch.kind = "ladder".to_string();
+        filter_patch.mode = "lowpass".to_string();
+        filter_patch.slope = "24".to_string();
+        assert_eq!(filter_patch.get_kind(), FilterKind::Ladder);
+        assert_eq!(filter_patch.get_slope(), Slope::Slope24);
+
+        // The comb and formant modes have their own filters
+        filter_patch.mode = "comb-fb".to_string();
+        assert_eq!(filter_patch.get_kind(), FilterKind::Comb);
+        filter_patch.mode = "formant".to_string();
+        assert_eq!(filter_patch.get_kind(), FilterKind::Formant);
+
+        filter_patch.mode = "unknown".to_string();
+        filter_patch.slope = "unknown".to_string();
+        assert_eq!(filter_patch.get_mode(), Mode::ByPass);
+        assert_eq!(filter_patch.get_slope(), Slope::Slope12);
+    }
+
+    #[test]
+    fn lfo_patch_rate() {
+        let mut lfo_patch = LfoPatch::default();
+        lfo_patch.freq = 3.0;
+        assert_eq!(lfo_patch.get_rate(), lfo::Rate::Free(3.0));
+
+        lfo_patch.sync = "1/8t".to_string();
+        lfo_patch.shape = "square".to_string();
+        let lfo = lfo_patch.to_lfo(44100.0, 120.0);
+        assert_eq!(lfo.get_shape(), lfo::Shape::Square);
+        assert!((lfo.get_frequency() - 6.0).abs() < 1e-9);
+    }
+}
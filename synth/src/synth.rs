@@ -1,19 +1,39 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::collections::HashSet;
+use std::path::Path;
 use std::sync::mpsc::Sender;
 
 use rosc::{OscType, OscMessage, OscBundle, OscPacket};
 
-use hero_core::types::{SampleRate, DEFAULT_SAMPLE_RATE};
+use hero_core::types::{SampleRate, Tempo, DEFAULT_SAMPLE_RATE, DEFAULT_TEMPO};
+use hero_core::wavetable::{self, registry, Interpolation, Registry, Wavetable};
+use hero_core::noise;
+use hero_core::analog;
+use hero_core::oscillator::SyncMode;
+use hero_core::filter::{self, Mode, Slope};
+use hero_core::filter::eq::BandKind;
+use hero_core::oversampling::{self, Downsampler};
+use hero_core::lfo::{self, Lfo, NoteValue};
+use hero_core::panning::{self, StereoImage};
+use hero_core::freq::tuning::{self, Tuning};
 
-use patch::Patch;
-use voice::{Voice, MAX_OSCILLATORS};
+use patch::{self, Patch, BreakpointPatch, FmMode, FilterKind, EqBandPatch};
+use algorithm::{Algorithm, Analysis};
+use voice::{Voice, MAX_OSCILLATORS, MAX_FILTERS, MAX_ENVELOPES, MAX_LFOS};
 
 const MAX_KEYS: usize = 128;
+const MAX_OVERSAMPLING: usize = 8;
 
 const ADDR_SYNC: &'static str = "/sync";
+const ADDR_ERROR: &'static str = "/error";
 const ADDR_NOTE: &'static str = "/note";
+const ADDR_FM_MODE: &'static str = "/fm/mode";
+const ADDR_FM_ALGORITHM: &'static str = "/fm/algorithm";
+const ADDR_FM_ANALYSIS: &'static str = "/fm/analysis";
+const ADDR_FM_CARRIERS: &'static str = "/fm/carriers";
+const ADDR_FM_MODULATORS: &'static str = "/fm/modulators";
+const ADDR_FM_CYCLE: &'static str = "/fm/cycle";
 const ADDR_OSC_ENABLED: &'static str = "/osc/enabled";
 const ADDR_OSC_FREE_PHASE: &'static str = "/osc/free-phase";
 const ADDR_OSC_INITIAL_PHASE: &'static str = "/osc/phase";
@@ -23,18 +43,101 @@ const ADDR_OSC_FREQ: &'static str = "/osc/freq";
 const ADDR_OSC_OCTAVES: &'static str = "/osc/octaves";
 const ADDR_OSC_SEMITONES: &'static str = "/osc/semitones";
 const ADDR_OSC_DETUNE: &'static str = "/osc/detune";
+const ADDR_OSC_SYNC: &'static str = "/osc/sync";
+const ADDR_OSC_FEEDBACK: &'static str = "/osc/feedback";
 const ADDR_OSC_AMP_MOD: &'static str = "/osc/am";
+const ADDR_OSC_RING_MOD: &'static str = "/osc/ring";
 const ADDR_OSC_FREQ_MOD: &'static str = "/osc/fm";
+const ADDR_OSC_POSITION_MOD: &'static str = "/osc/position-mod";
+const ADDR_OSC_PULSE_WIDTH_MOD: &'static str = "/osc/pw-mod";
 const ADDR_OSC_PAN: &'static str = "/osc/pan";
 const ADDR_OSC_LEVEL: &'static str = "/osc/level";
+const ADDR_OSC_SOURCE: &'static str = "/osc/source";
+const ADDR_OSC_WAVETABLE: &'static str = "/osc/wavetable";
+const ADDR_OSC_NOISE: &'static str = "/osc/noise";
+const ADDR_OSC_ANALOG: &'static str = "/osc/analog";
+const ADDR_OSC_PULSE_WIDTH: &'static str = "/osc/pulse-width";
+const ADDR_OSC_INTERPOLATION: &'static str = "/osc/interpolation";
+const ADDR_OSC_POSITION: &'static str = "/osc/position";
+const ADDR_OSC_ATTACK: &'static str = "/osc/attack";
+const ADDR_OSC_DECAY: &'static str = "/osc/decay";
+const ADDR_OSC_SUSTAIN: &'static str = "/osc/sustain";
+const ADDR_OSC_RELEASE: &'static str = "/osc/release";
+const ADDR_FILTER_KIND: &'static str = "/filter/kind";
+const ADDR_FILTER_MODE: &'static str = "/filter/mode";
+const ADDR_FILTER_SLOPE: &'static str = "/filter/slope";
+const ADDR_FILTER_FREQ: &'static str = "/filter/freq";
+const ADDR_FILTER_RES: &'static str = "/filter/res";
+const ADDR_FILTER_DRIVE: &'static str = "/filter/drive";
+const ADDR_FILTER_VOWEL: &'static str = "/filter/vowel";
+const ADDR_FILTER_KEY_TRACKING: &'static str = "/filter/key-tracking";
+const ADDR_FILTER_ENV_AMOUNT: &'static str = "/filter/env-amount";
+const ADDR_FILTER_VELOCITY: &'static str = "/filter/velocity";
+const ADDR_FILTER_SEND: &'static str = "/filter/send";
+const ADDR_FILTER_CHAIN: &'static str = "/filter/chain";
+const ADDR_FILTER_PAN: &'static str = "/filter/pan";
+const ADDR_FILTER_LEVEL: &'static str = "/filter/level";
+const ADDR_EQ_BAND: &'static str = "/eq/band";
+const ADDR_EQ_NUM_BANDS: &'static str = "/eq/num-bands";
+const ADDR_ENV_POINT: &'static str = "/env/point";
+const ADDR_ENV_NUM_POINTS: &'static str = "/env/num-points";
+const ADDR_ENV_SUSTAIN: &'static str = "/env/sustain";
+const ADDR_ENV_LOOP: &'static str = "/env/loop";
+const ADDR_ENV_AMP: &'static str = "/env/amp";
+const ADDR_ENV_PITCH: &'static str = "/env/pitch";
+const ADDR_ENV_POSITION: &'static str = "/env/position";
+const ADDR_ENV_PULSE_WIDTH: &'static str = "/env/pulse-width";
+const ADDR_ENV_CUTOFF: &'static str = "/env/cutoff";
+const ADDR_WAVETABLE_LOAD: &'static str = "/wavetable/load";
+const ADDR_TEMPO: &'static str = "/tempo";
+const ADDR_LFO_SHAPE: &'static str = "/lfo/shape";
+const ADDR_LFO_FREQ: &'static str = "/lfo/freq";
+const ADDR_LFO_SYNC: &'static str = "/lfo/sync";
+const ADDR_LFO_PHASE: &'static str = "/lfo/phase";
+const ADDR_LFO_DELAY: &'static str = "/lfo/delay";
+const ADDR_LFO_FADE_IN: &'static str = "/lfo/fade-in";
+const ADDR_LFO_GLOBAL: &'static str = "/lfo/global";
+const ADDR_LFO_AMP: &'static str = "/lfo/amp";
+const ADDR_LFO_PITCH: &'static str = "/lfo/pitch";
+const ADDR_LFO_POSITION: &'static str = "/lfo/position";
+const ADDR_LFO_PULSE_WIDTH: &'static str = "/lfo/pulse-width";
+const ADDR_LFO_CUTOFF: &'static str = "/lfo/cutoff";
+const ADDR_OVERSAMPLING: &'static str = "/oversampling";
+const ADDR_LATENCY: &'static str = "/latency";
+const ADDR_SMOOTHING: &'static str = "/smoothing";
+const ADDR_PAN_LAW: &'static str = "/pan-law";
+const ADDR_WIDTH: &'static str = "/width";
+const ADDR_BALANCE: &'static str = "/balance";
+const ADDR_TUNING_LOAD: &'static str = "/tuning/load";
+const ADDR_TUNING_REFERENCE: &'static str = "/tuning/reference";
+
+const MAX_ENV_POINTS: usize = 32;
+pub const MAX_EQ_BANDS: usize = 8;
+
+/// Data read from the files given by a message. Reading it is too slow for the audio thread,
+/// so it is loaded out of it with `Synth::load` and then given to `Synth::set_resource`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resource {
+    Wavetable { name: String, wavetable: Wavetable },
+    Tuning { tuning: Tuning, keep_reference: bool },       // Keeping the reference frequency of the current tuning
+}
 
 pub struct Synth {
     sample_rate: SampleRate,
     patch: Rc<RefCell<Patch>>,
     patch_version: usize,
+    registry: Registry,
     voices: Vec<Voice>,
     active_voices: HashSet<usize>,
     output_packets: Vec<OscPacket>,
+    tempo: Tempo,
+    global_lfos: Vec<Lfo>,              // LFOs shared by all the voices, used when they are global in the patch
+    global_lfos_version: usize,
+    oversampling: usize,
+    downsamplers: Vec<[Downsampler; 2]>, // Left and right for every factor, so changing it does not allocate
+    buffers: [[f64; MAX_OVERSAMPLING]; 2], // Output of the voices at the oversampled rate
+    stereo_image: StereoImage,
+    tuning: Tuning,
 }
 
 impl Default for Synth {
@@ -43,9 +146,20 @@ impl Default for Synth {
             sample_rate: DEFAULT_SAMPLE_RATE,
             patch: Rc::new(RefCell::new(Patch::default())),
             patch_version: 0,
+            registry: Registry::default(),
             voices: Vec::new(),
             active_voices: HashSet::new(),
             output_packets: Vec::new(),
+            tempo: DEFAULT_TEMPO,
+            global_lfos: Vec::new(),
+            global_lfos_version: 0,
+            oversampling: 1,
+            downsamplers: oversampling::FACTORS.iter()
+                .map(|&factor| [Downsampler::new(factor), Downsampler::new(factor)])
+                .collect(),
+            buffers: [[0.0; MAX_OVERSAMPLING]; 2],
+            stereo_image: StereoImage::default(),
+            tuning: Tuning::default(),
         }
     }
 }
@@ -53,31 +167,174 @@ impl Default for Synth {
 impl Synth {
     pub fn new(sample_rate: SampleRate) -> Synth {
         let patch = Rc::new(RefCell::new(Patch::default()));
+        let registry = Registry::default();
         let mut voices = Vec::<Voice>::with_capacity(MAX_KEYS);
         for _key in 0..MAX_KEYS {
-            let voice = Voice::new(sample_rate, patch.clone());
+            let voice = Voice::new(sample_rate, patch.clone(), &registry);
             voices.push(voice);
         }
 
-        Synth {
+        let global_lfos = patch.borrow().lfos.iter()
+            .take(MAX_LFOS)
+            .map(|patch_lfo| patch_lfo.to_lfo(sample_rate, DEFAULT_TEMPO))
+            .collect();
+
+        let factor = patch.borrow().oversampling;
+        let mut synth = Synth {
             sample_rate: sample_rate,
+            global_lfos: global_lfos,
             patch: patch,
+            registry: registry,
             voices: voices,
 
             ..Synth::default()
-        }
+        };
+        synth.set_oversampling(factor);
+        synth
     }
 
     pub fn get_sample_rate(&self) -> SampleRate {
         self.sample_rate
     }
 
+    pub fn get_tempo(&self) -> Tempo {
+        self.tempo
+    }
+
+    /// Sets the tempo in beats per minute of the synced LFOs
+    pub fn set_tempo(&mut self, tempo: Tempo) {
+        assert!(tempo > 0.0);
+        self.tempo = tempo;
+        for lfo in self.global_lfos.iter_mut() {
+            lfo.set_tempo(tempo);
+        }
+        for voice in self.voices.iter_mut() {
+            voice.set_tempo(tempo);
+        }
+    }
+
+    pub fn get_tuning(&self) -> &Tuning {
+        &self.tuning
+    }
+
+    /// Sets the frequencies of the keys, used from the next notes
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+    }
+
+    /// Retunes some keys with their frequencies, like with the MIDI Tuning Standard.
+    /// The notes being played follow the new frequencies when the change is in real time.
+    pub fn retune(&mut self, key_freqs: &[(usize, f64)], realtime: bool) {
+        for &(key, freq) in key_freqs.iter() {
+            // The keys that are not MIDI keys are ignored, as they are by the tuning
+            self.tuning.set_key_freq(key, freq);
+            if realtime && freq > 0.0 && key < MAX_KEYS && self.active_voices.contains(&key) {
+                self.voices[key].set_key_freq(freq);
+            }
+        }
+    }
+
+    /// Loads a Scala scale, with a keyboard mapping or a linear mapping from the middle C.
+    /// The reference frequency of the current tuning is kept without keyboard mapping.
+    pub fn load_tuning(&mut self, scale_path: &Path, mapping_path: Option<&Path>) -> Result<(), tuning::Error> {
+        let tuning = Tuning::load(scale_path, mapping_path)?;
+        self.set_loaded_tuning(tuning, mapping_path.is_none());
+        Ok(())
+    }
+
+    fn set_loaded_tuning(&mut self, mut tuning: Tuning, keep_reference: bool) {
+        if keep_reference {
+            tuning.set_reference_freq(self.tuning.get_reference_freq());
+        }
+        self.tuning = tuning;
+    }
+
+    /// Delay of the output caused by the oversampling, in samples
+    pub fn get_latency(&self) -> f64 {
+        self.downsamplers[factor_index(self.oversampling)][0].latency()
+    }
+
+    /// Runs the voices at a multiple of the sample rate. The sample rate of the voices is changed
+    /// in place, so the notes being played go on and nothing is allocated.
+    fn set_oversampling(&mut self, factor: usize) {
+        assert!(oversampling::is_valid_factor(factor));
+        if self.oversampling != factor {
+            let sample_rate = self.sample_rate * factor as f64;
+            for voice in self.voices.iter_mut() {
+                voice.set_sample_rate(sample_rate);
+            }
+            for lfo in self.global_lfos.iter_mut() {
+                lfo.set_sample_rate(sample_rate);
+            }
+            for downsampler in self.downsamplers[factor_index(factor)].iter_mut() {
+                downsampler.reset();
+            }
+            self.oversampling = factor;
+        }
+    }
+
+    pub fn get_registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Loads a wavetable from a WAV file, replacing any previous wavetable with the same name.
+    /// When the frame size is not given, it is taken from the `clm ` chunk or guessed from the length of the file.
+    pub fn load_wavetable(&mut self, name: &str, path: &Path, frame_size: Option<usize>) -> Result<(), wavetable::Error> {
+        let wavetable = registry::read_wav(path, frame_size, self.registry.frame_size())?;
+        self.set_wavetable(name, wavetable);
+        Ok(())
+    }
+
+    /// Registers a wavetable, replacing any previous wavetable with the same name in the notes being played
+    pub fn set_wavetable(&mut self, name: &str, wavetable: Wavetable) {
+        self.registry.register(name, wavetable);
+        for voice in self.voices.iter_mut() {
+            voice.reload_wavetable(name, &self.registry);
+        }
+    }
+
+    /// Reads the files of a `/wavetable/load` or `/tuning/load` message, and returns None for the rest of the messages.
+    /// It does not need the synth, so it can be called from any thread but the audio one.
+    pub fn load(msg: &OscMessage) -> Option<Result<Resource, String>> {
+        match msg.addr.as_ref() {
+            ADDR_WAVETABLE_LOAD => args_wavetable_load(&msg.args).map(|(name, path, frame_size)| {
+                registry::read_wav(Path::new(&path), frame_size, registry::DEFAULT_FRAME_SIZE)
+                    .map(|wavetable| Resource::Wavetable { name: name.clone(), wavetable: wavetable })
+                    .map_err(|err| format!("Failed to load the wavetable {} from {}: {}", name, path, err))
+            }),
+            ADDR_TUNING_LOAD => args_tuning_load(&msg.args).map(|(scale_path, mapping_path)| {
+                Tuning::load(Path::new(&scale_path), mapping_path.as_ref().map(Path::new))
+                    .map(|tuning| Resource::Tuning { tuning: tuning, keep_reference: mapping_path.is_none() })
+                    .map_err(|err| format!("Failed to load the tuning from {}: {}", scale_path, err))
+            }),
+            _ => None
+        }
+    }
+
+    /// Uses the data loaded by `Synth::load`, or sends the error of loading it
+    pub fn set_resource(&mut self, resource: Result<Resource, String>) {
+        match resource {
+            Ok(Resource::Wavetable { name, wavetable }) => self.set_wavetable(&name, wavetable),
+            Ok(Resource::Tuning { tuning, keep_reference }) => self.set_loaded_tuning(tuning, keep_reference),
+            Err(description) => self.output_error(description),
+        }
+    }
+
     pub fn note_on(&mut self, key: usize, vel: f64) {
+        // The keys not mapped by the tuning are silent
+        let freq = self.tuning.freq(key);
+        if freq == 0.0 {
+            return;
+        }
+
         let voice_index = key & 0x7f;
         let ref mut voice = self.voices[voice_index];
-        voice.reset();
-        voice.update_patch(&self.patch.borrow(), self.patch_version);
-        voice.note_on(key, vel);
+        voice.update_patch(&self.patch.borrow(), &self.registry, self.patch_version);
+        // A new note starts without the ramps of the parameters changed since the last one
+        if !voice.is_active() {
+            voice.reset();
+        }
+        voice.note_on(key, freq, vel);
         self.active_voices.insert(voice_index);
     }
 
@@ -85,15 +342,25 @@ impl Synth {
         let voice_index = key & 0x7f;
         let ref mut voice = self.voices[voice_index];
         voice.note_off(key, vel);
-        self.active_voices.remove(&voice_index);
     }
 
+    /// Handles the messages in the audio thread, except the ones reading files that are handled by `Synth::load`
     pub fn control(&mut self, packet: &OscPacket) {
         match packet {
             &OscPacket::Message(ref msg) => {
                 match msg.addr.as_ref() {
                     ADDR_SYNC => self.control_sync(&msg.args),
                     ADDR_NOTE => self.control_note(&msg.args),
+                    ADDR_FM_MODE => self.control_fm_mode(&msg.args),
+                    ADDR_FM_ALGORITHM => self.control_fm_algorithm(&msg.args),
+                    ADDR_FM_ANALYSIS => self.control_fm_analysis(&msg.args),
+                    ADDR_OVERSAMPLING => self.control_oversampling(&msg.args),
+                    ADDR_TEMPO => self.control_tempo(&msg.args),
+                    ADDR_SMOOTHING => self.control_smoothing(&msg.args),
+                    ADDR_PAN_LAW => self.control_pan_law(&msg.args),
+                    ADDR_WIDTH => self.control_width(&msg.args),
+                    ADDR_BALANCE => self.control_balance(&msg.args),
+                    ADDR_TUNING_REFERENCE => self.control_tuning_reference(&msg.args),
                     ADDR_OSC_AMP => self.control_osc_amplitude(&msg.args),
                     ADDR_OSC_FREQ => self.control_osc_frequency(&msg.args),
                     ADDR_OSC_OCTAVES => self.control_osc_octaves(&msg.args),
@@ -101,9 +368,62 @@ impl Synth {
                     ADDR_OSC_LEVEL => self.control_osc_level(&msg.args),
                     ADDR_OSC_PAN => self.control_osc_panning(&msg.args),
                     ADDR_OSC_FREQ_MOD => self.control_osc_fm_mod(&msg.args),
+                    ADDR_OSC_RING_MOD => self.control_osc_ring_mod(&msg.args),
+                    ADDR_OSC_SYNC => self.control_osc_sync(&msg.args),
+                    ADDR_OSC_FEEDBACK => self.control_osc_feedback(&msg.args),
+                    ADDR_OSC_POSITION_MOD => self.control_osc_position_mod(&msg.args),
+                    ADDR_OSC_PULSE_WIDTH_MOD => self.control_osc_pulse_width_mod(&msg.args),
                     ADDR_OSC_ENABLED => self.control_osc_enabled(&msg.args),
                     ADDR_OSC_FIXED_FREQ => self.control_osc_fixed_freq(&msg.args),
                     ADDR_OSC_FREE_PHASE => self.control_osc_free_phase(&msg.args),
+                    ADDR_OSC_SOURCE => self.control_osc_source(&msg.args),
+                    ADDR_OSC_WAVETABLE => self.control_osc_wavetable(&msg.args),
+                    ADDR_OSC_NOISE => self.control_osc_noise(&msg.args),
+                    ADDR_OSC_ANALOG => self.control_osc_analog(&msg.args),
+                    ADDR_OSC_PULSE_WIDTH => self.control_osc_pulse_width(&msg.args),
+                    ADDR_OSC_INTERPOLATION => self.control_osc_interpolation(&msg.args),
+                    ADDR_OSC_POSITION => self.control_osc_position(&msg.args),
+                    ADDR_OSC_ATTACK => self.control_osc_attack(&msg.args),
+                    ADDR_OSC_DECAY => self.control_osc_decay(&msg.args),
+                    ADDR_OSC_SUSTAIN => self.control_osc_sustain(&msg.args),
+                    ADDR_OSC_RELEASE => self.control_osc_release(&msg.args),
+                    ADDR_FILTER_KIND => self.control_filter_kind(&msg.args),
+                    ADDR_FILTER_MODE => self.control_filter_mode(&msg.args),
+                    ADDR_FILTER_SLOPE => self.control_filter_slope(&msg.args),
+                    ADDR_FILTER_FREQ => self.control_filter_freq(&msg.args),
+                    ADDR_FILTER_RES => self.control_filter_res(&msg.args),
+                    ADDR_FILTER_DRIVE => self.control_filter_drive(&msg.args),
+                    ADDR_FILTER_VOWEL => self.control_filter_vowel(&msg.args),
+                    ADDR_FILTER_KEY_TRACKING => self.control_filter_key_tracking(&msg.args),
+                    ADDR_FILTER_ENV_AMOUNT => self.control_filter_env_amount(&msg.args),
+                    ADDR_FILTER_VELOCITY => self.control_filter_velocity(&msg.args),
+                    ADDR_FILTER_SEND => self.control_filter_send(&msg.args),
+                    ADDR_FILTER_CHAIN => self.control_filter_chain(&msg.args),
+                    ADDR_FILTER_PAN => self.control_filter_panning(&msg.args),
+                    ADDR_FILTER_LEVEL => self.control_filter_level(&msg.args),
+                    ADDR_EQ_BAND => self.control_eq_band(&msg.args),
+                    ADDR_EQ_NUM_BANDS => self.control_eq_num_bands(&msg.args),
+                    ADDR_ENV_POINT => self.control_env_point(&msg.args),
+                    ADDR_ENV_NUM_POINTS => self.control_env_num_points(&msg.args),
+                    ADDR_ENV_SUSTAIN => self.control_env_sustain(&msg.args),
+                    ADDR_ENV_LOOP => self.control_env_loop(&msg.args),
+                    ADDR_ENV_AMP => self.control_env_amp(&msg.args),
+                    ADDR_ENV_PITCH => self.control_env_pitch(&msg.args),
+                    ADDR_ENV_POSITION => self.control_env_position(&msg.args),
+                    ADDR_ENV_PULSE_WIDTH => self.control_env_pulse_width(&msg.args),
+                    ADDR_ENV_CUTOFF => self.control_env_cutoff(&msg.args),
+                    ADDR_LFO_SHAPE => self.control_lfo_shape(&msg.args),
+                    ADDR_LFO_FREQ => self.control_lfo_freq(&msg.args),
+                    ADDR_LFO_SYNC => self.control_lfo_sync(&msg.args),
+                    ADDR_LFO_PHASE => self.control_lfo_phase(&msg.args),
+                    ADDR_LFO_DELAY => self.control_lfo_delay(&msg.args),
+                    ADDR_LFO_FADE_IN => self.control_lfo_fade_in(&msg.args),
+                    ADDR_LFO_GLOBAL => self.control_lfo_global(&msg.args),
+                    ADDR_LFO_AMP => self.control_lfo_amp(&msg.args),
+                    ADDR_LFO_PITCH => self.control_lfo_pitch(&msg.args),
+                    ADDR_LFO_POSITION => self.control_lfo_position(&msg.args),
+                    ADDR_LFO_PULSE_WIDTH => self.control_lfo_pulse_width(&msg.args),
+                    ADDR_LFO_CUTOFF => self.control_lfo_cutoff(&msg.args),
                     _ => {}
                 }
             },
@@ -125,20 +445,41 @@ impl Synth {
         let mut packets = Vec::with_capacity(8 * (11 + 8 * 2));
         use rosc::OscType::{Int, Float, Time};
         let patch = self.patch.borrow();
+        packets.push(Self::osc_message(ADDR_FM_MODE, vec![OscType::String(patch.fm_mode.clone())]));
+        packets.push(Self::osc_message(ADDR_OVERSAMPLING, vec![Int(patch.oversampling as i32)]));
+        packets.push(Self::osc_message(ADDR_LATENCY, vec![Float(self.get_latency() as f32)]));
+        packets.push(Self::osc_message(ADDR_SMOOTHING, vec![Float(patch.smoothing as f32)]));
+        packets.push(Self::osc_message(ADDR_PAN_LAW, vec![OscType::String(patch.pan_law.clone())]));
+        packets.push(Self::osc_message(ADDR_WIDTH, vec![Float(patch.width as f32)]));
+        packets.push(Self::osc_message(ADDR_BALANCE, vec![Float(patch.balance as f32)]));
         for i in 0..patch.oscillators.len() {
             let index = i as i32;
             let patch_osc = &patch.oscillators[i];
             packets.push(Self::osc_message(ADDR_OSC_ENABLED, vec![Int(index), Int(patch_osc.is_enabled as i32)]));
             packets.push(Self::osc_message(ADDR_OSC_AMP, vec![Int(index), Float(patch_osc.amplitude as f32)]));
             packets.push(Self::osc_message(ADDR_OSC_FREE_PHASE, vec![Int(index), Int(patch_osc.is_free_phase as i32)]));
+            packets.push(Self::osc_message(ADDR_OSC_SOURCE, vec![Int(index), OscType::String(patch_osc.source.clone())]));
+            packets.push(Self::osc_message(ADDR_OSC_WAVETABLE, vec![Int(index), OscType::String(patch_osc.wavetable.clone())]));
+            packets.push(Self::osc_message(ADDR_OSC_NOISE, vec![Int(index), OscType::String(patch_osc.noise.clone())]));
+            packets.push(Self::osc_message(ADDR_OSC_ANALOG, vec![Int(index), OscType::String(patch_osc.analog.clone())]));
+            packets.push(Self::osc_message(ADDR_OSC_PULSE_WIDTH, vec![Int(index), Float(patch_osc.pulse_width as f32)]));
+            packets.push(Self::osc_message(ADDR_OSC_INTERPOLATION, vec![Int(index), OscType::String(patch_osc.interpolation.clone())]));
+            packets.push(Self::osc_message(ADDR_OSC_POSITION, vec![Int(index), Float(patch_osc.position as f32)]));
             packets.push(Self::osc_message(ADDR_OSC_INITIAL_PHASE, vec![Int(index), Float(patch_osc.initial_phase as f32)]));
             packets.push(Self::osc_message(ADDR_OSC_FIXED_FREQ, vec![Int(index), Int(patch_osc.is_fixed_freq as i32)]));
             packets.push(Self::osc_message(ADDR_OSC_FREQ, vec![Int(index), Float(patch_osc.base_frequency as f32)]));
             packets.push(Self::osc_message(ADDR_OSC_OCTAVES, vec![Int(index), Float(patch_osc.octaves as f32)]));
             packets.push(Self::osc_message(ADDR_OSC_SEMITONES, vec![Int(index), Float(patch_osc.semitones as f32)]));
             packets.push(Self::osc_message(ADDR_OSC_DETUNE, vec![Int(index), Float(patch_osc.detune as f32)]));
+            packets.push(Self::osc_message(ADDR_OSC_FEEDBACK, vec![Int(index), Float(patch_osc.feedback as f32)]));
+            let sync_source = patch_osc.sync_source.map_or(0, |src_index| src_index + 1) as i32;
+            packets.push(Self::osc_message(ADDR_OSC_SYNC, vec![Int(index), Int(sync_source), OscType::String(patch_osc.sync_mode.clone())]));
             packets.push(Self::osc_message(ADDR_OSC_LEVEL, vec![Int(index), Float(patch_osc.level as f32)]));
             packets.push(Self::osc_message(ADDR_OSC_PAN, vec![Int(index), Float(patch_osc.panning as f32)]));
+            packets.push(Self::osc_message(ADDR_OSC_ATTACK, vec![Int(index), Float(patch_osc.amp_env.attack as f32)]));
+            packets.push(Self::osc_message(ADDR_OSC_DECAY, vec![Int(index), Float(patch_osc.amp_env.decay as f32)]));
+            packets.push(Self::osc_message(ADDR_OSC_SUSTAIN, vec![Int(index), Float(patch_osc.amp_env.sustain as f32)]));
+            packets.push(Self::osc_message(ADDR_OSC_RELEASE, vec![Int(index), Float(patch_osc.amp_env.release as f32)]));
             for j in 0..MAX_OSCILLATORS {
                 let level = match patch_osc.amp_mod.get(&j) {
                     Some(level) => level.clone() as f32,
@@ -146,11 +487,108 @@ impl Synth {
                 };
                 packets.push(Self::osc_message(ADDR_OSC_AMP_MOD, vec![Int(j as i32), Int(index), Float(level)]));
 
+                let level = match patch_osc.ring_mod.get(&j) {
+                    Some(level) => level.clone() as f32,
+                    None => 0.0f32
+                };
+                packets.push(Self::osc_message(ADDR_OSC_RING_MOD, vec![Int(j as i32), Int(index), Float(level)]));
+
                 let level = match patch_osc.freq_mod.get(&j) {
                     Some(level) => level.clone() as f32,
                     None => 0.0f32
                 };
                 packets.push(Self::osc_message(ADDR_OSC_FREQ_MOD, vec![Int(j as i32), Int(index), Float(level)]));
+
+                let level = match patch_osc.pos_mod.get(&j) {
+                    Some(level) => level.clone() as f32,
+                    None => 0.0f32
+                };
+                packets.push(Self::osc_message(ADDR_OSC_POSITION_MOD, vec![Int(j as i32), Int(index), Float(level)]));
+
+                let level = match patch_osc.pw_mod.get(&j) {
+                    Some(level) => level.clone() as f32,
+                    None => 0.0f32
+                };
+                packets.push(Self::osc_message(ADDR_OSC_PULSE_WIDTH_MOD, vec![Int(j as i32), Int(index), Float(level)]));
+            }
+        }
+        for i in 0..patch.filters.len() {
+            let index = (i + 1) as i32;
+            let patch_filter = &patch.filters[i];
+            packets.push(Self::osc_message(ADDR_FILTER_KIND, vec![Int(index), OscType::String(patch_filter.kind.clone())]));
+            packets.push(Self::osc_message(ADDR_FILTER_MODE, vec![Int(index), OscType::String(patch_filter.mode.clone())]));
+            packets.push(Self::osc_message(ADDR_FILTER_SLOPE, vec![Int(index), OscType::String(patch_filter.slope.clone())]));
+            packets.push(Self::osc_message(ADDR_FILTER_FREQ, vec![Int(index), Float(patch_filter.freq as f32)]));
+            packets.push(Self::osc_message(ADDR_FILTER_RES, vec![Int(index), Float(patch_filter.res as f32)]));
+            packets.push(Self::osc_message(ADDR_FILTER_DRIVE, vec![Int(index), Float(patch_filter.drive as f32)]));
+            packets.push(Self::osc_message(ADDR_FILTER_VOWEL, vec![Int(index), Float(patch_filter.vowel as f32)]));
+            packets.push(Self::osc_message(ADDR_FILTER_KEY_TRACKING, vec![Int(index), Float(patch_filter.key_tracking as f32)]));
+            packets.push(Self::osc_message(ADDR_FILTER_ENV_AMOUNT, vec![Int(index), Float(patch_filter.env_amount as f32)]));
+            packets.push(Self::osc_message(ADDR_FILTER_VELOCITY, vec![Int(index), Float(patch_filter.velocity as f32)]));
+            packets.push(Self::osc_message(ADDR_FILTER_PAN, vec![Int(index), Float(patch_filter.panning as f32)]));
+            packets.push(Self::osc_message(ADDR_FILTER_LEVEL, vec![Int(index), Float(patch_filter.level as f32)]));
+            for (dst_index, level) in patch_filter.filt_send.iter() {
+                packets.push(Self::osc_message(ADDR_FILTER_CHAIN, vec![Int((dst_index + 1) as i32), Int(index), Float(*level as f32)]));
+            }
+            for (j, patch_osc) in patch.oscillators.iter().enumerate() {
+                if let Some(level) = patch_osc.filt_send.get(&i) {
+                    packets.push(Self::osc_message(ADDR_FILTER_SEND, vec![Int(index), Int((j + 1) as i32), Float(*level as f32)]));
+                }
+            }
+        }
+        packets.push(Self::osc_message(ADDR_EQ_NUM_BANDS, vec![Int(patch.eq.len() as i32)]));
+        for (i, band) in patch.eq.iter().enumerate() {
+            packets.push(Self::osc_message(ADDR_EQ_BAND, vec![Int((i + 1) as i32), OscType::String(band.kind.clone()),
+                Float(band.freq as f32), Float(band.q as f32), Float(band.gain as f32)]));
+        }
+        for i in 0..patch.envelopes.len() {
+            let index = (i + 1) as i32;
+            let patch_env = &patch.envelopes[i];
+            packets.push(Self::osc_message(ADDR_ENV_NUM_POINTS, vec![Int(index), Int(patch_env.breakpoints.len() as i32)]));
+            for (j, bp) in patch_env.breakpoints.iter().enumerate() {
+                packets.push(Self::osc_message(ADDR_ENV_POINT, vec![Int(index), Int((j + 1) as i32),
+                    Float(bp.time as f32), Float(bp.level as f32), Float(bp.curve as f32)]));
+            }
+            let sustain = patch_env.sustain.map_or(0, |point| point + 1) as i32;
+            packets.push(Self::osc_message(ADDR_ENV_SUSTAIN, vec![Int(index), Int(sustain)]));
+            let (loop_start, loop_end) = patch_env.get_loop().map_or((0, 0), |(start, end)| (start + 1, end + 1));
+            packets.push(Self::osc_message(ADDR_ENV_LOOP, vec![Int(index), Int(loop_start as i32), Int(loop_end as i32)]));
+            let sends = &patch_env.mod_sends;
+            for (dst_index, depth) in sends.amp.iter() {
+                packets.push(Self::osc_message(ADDR_ENV_AMP, vec![Int(index), Int((dst_index + 1) as i32), Float(*depth as f32)]));
+            }
+            for (dst_index, depth) in sends.pitch.iter() {
+                packets.push(Self::osc_message(ADDR_ENV_PITCH, vec![Int(index), Int((dst_index + 1) as i32), Float(*depth as f32)]));
+            }
+            for (dst_index, depth) in sends.position.iter() {
+                packets.push(Self::osc_message(ADDR_ENV_POSITION, vec![Int(index), Int((dst_index + 1) as i32), Float(*depth as f32)]));
+            }
+            for (dst_index, depth) in sends.pulse_width.iter() {
+                packets.push(Self::osc_message(ADDR_ENV_PULSE_WIDTH, vec![Int(index), Int((dst_index + 1) as i32), Float(*depth as f32)]));
+            }
+            for (dst_index, depth) in sends.cutoff.iter() {
+                packets.push(Self::osc_message(ADDR_ENV_CUTOFF, vec![Int(index), Int((dst_index + 1) as i32), Float(*depth as f32)]));
+            }
+        }
+        packets.push(Self::osc_message(ADDR_TEMPO, vec![Float(self.tempo as f32)]));
+        packets.push(Self::osc_message(ADDR_TUNING_REFERENCE, vec![Float(self.tuning.get_reference_freq() as f32)]));
+        for i in 0..patch.lfos.len() {
+            let index = (i + 1) as i32;
+            let patch_lfo = &patch.lfos[i];
+            packets.push(Self::osc_message(ADDR_LFO_SHAPE, vec![Int(index), OscType::String(patch_lfo.shape.clone())]));
+            packets.push(Self::osc_message(ADDR_LFO_FREQ, vec![Int(index), Float(patch_lfo.freq as f32)]));
+            packets.push(Self::osc_message(ADDR_LFO_SYNC, vec![Int(index), OscType::String(patch_lfo.sync.clone())]));
+            packets.push(Self::osc_message(ADDR_LFO_PHASE, vec![Int(index), Float(patch_lfo.phase as f32)]));
+            packets.push(Self::osc_message(ADDR_LFO_DELAY, vec![Int(index), Float(patch_lfo.delay as f32)]));
+            packets.push(Self::osc_message(ADDR_LFO_FADE_IN, vec![Int(index), Float(patch_lfo.fade_in as f32)]));
+            packets.push(Self::osc_message(ADDR_LFO_GLOBAL, vec![Int(index), Int(patch_lfo.is_global as i32)]));
+            let sends = &patch_lfo.mod_sends;
+            let all_sends = [(ADDR_LFO_AMP, &sends.amp), (ADDR_LFO_PITCH, &sends.pitch), (ADDR_LFO_POSITION, &sends.position),
+                (ADDR_LFO_PULSE_WIDTH, &sends.pulse_width), (ADDR_LFO_CUTOFF, &sends.cutoff)];
+            for &(addr, depths) in all_sends.iter() {
+                for (dst_index, depth) in depths.iter() {
+                    packets.push(Self::osc_message(addr, vec![Int(index), Int((dst_index + 1) as i32), Float(*depth as f32)]));
+                }
             }
         }
         let packet = OscPacket::Bundle(OscBundle {
@@ -177,6 +615,59 @@ impl Synth {
         }
     }
 
+    fn control_fm_mode(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some(name) = args_name(args) {
+            if FmMode::from_name(&name).is_some() {
+                self.patch.borrow_mut().fm_mode = name;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    /// Oversampling factor of the voices: 1, 2, 4 or 8
+    fn control_oversampling(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some(factor) = args_int(args) {
+            if oversampling::is_valid_factor(factor) {
+                self.patch.borrow_mut().oversampling = factor;
+                self.patch_version += 1;
+                self.set_oversampling(factor);
+            }
+            else {
+                self.output_error(format!("Invalid oversampling factor {}", factor));
+            }
+        }
+    }
+
+    fn control_fm_algorithm(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some(name) = args_name(args) {
+            match Algorithm::from_name(&name) {
+                Some(algorithm) => {
+                    algorithm.apply(&mut self.patch.borrow_mut());
+                    self.patch_version += 1;
+                    self.control_sync(&None);
+                },
+                None => self.output_error(format!("Unknown algorithm: {}", name))
+            }
+        }
+    }
+
+    /// Reports the carriers, the modulators and the modulation cycles, with 1-based indices
+    fn control_fm_analysis(&mut self, _args: &Option<Vec<OscType>>) {
+        let analysis = Analysis::new(&self.patch.borrow());
+        let to_args = |indices: &[usize]| indices.iter().map(|index| OscType::Int((index + 1) as i32)).collect();
+        let mut packets = Vec::with_capacity(2 + analysis.cycles.len());
+        packets.push(Self::osc_message(ADDR_FM_CARRIERS, to_args(&analysis.carriers)));
+        packets.push(Self::osc_message(ADDR_FM_MODULATORS, to_args(&analysis.modulators)));
+        for cycle in analysis.cycles.iter() {
+            packets.push(Self::osc_message(ADDR_FM_CYCLE, to_args(cycle)));
+        }
+        let packet = OscPacket::Bundle(OscBundle {
+            timetag: OscType::Time(0, 0),
+            content: packets
+        });
+        self.output_packets.push(packet);
+    }
+
     fn control_osc_amplitude(&mut self, args: &Option<Vec<OscType>>) {
         if let Some((index, value)) = args_osc_val(args, -100.0, 100.0) {
             self.patch.borrow_mut().oscillators[index].amplitude = value;
@@ -229,6 +720,54 @@ impl Synth {
         }
     }
 
+    fn control_osc_feedback(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_osc_val(args, 0.0, 1.0) {
+            if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                patch_osc.feedback = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_osc_ring_mod(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((src_index, dst_index, value)) = args_osc_mod_val(args) {
+            if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(src_index) {
+                patch_osc.ring_mod.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_osc_sync(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, src_index, mode)) = args_osc_sync(args) {
+            if SyncMode::from_name(&mode).is_some() {
+                if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                    patch_osc.sync_source = src_index;
+                    patch_osc.sync_mode = mode;
+                    self.patch_version += 1;
+                }
+            }
+        }
+    }
+
+    fn control_osc_position_mod(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((src_index, dst_index, value)) = args_osc_mod_val(args) {
+            if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(src_index) {
+                patch_osc.pos_mod.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_osc_pulse_width_mod(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((src_index, dst_index, value)) = args_osc_mod_val(args) {
+            if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(src_index) {
+                patch_osc.pw_mod.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
     fn control_osc_enabled(&mut self, args: &Option<Vec<OscType>>) {
         if let Some((index, value)) = args_osc_toggle(args) {
             self.patch.borrow_mut().oscillators[index].is_enabled = value;
@@ -250,109 +789,992 @@ impl Synth {
         }
     }
 
-    // fn foreach_active_voice<F>(&mut self, mut f: F) where F: FnMut(&mut Voice) {
-    //     for voice_index in self.active_voices.iter() {
-    //         f(&mut self.voices[*voice_index])
-    //     }
-    // }
+    fn control_osc_source(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, name)) = args_osc_name(args) {
+            if name == "wavetable" || name == "noise" || name == "analog" {
+                if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                    patch_osc.source = name;
+                    self.patch_version += 1;
+                }
+            }
+        }
+    }
 
-    pub fn process(&mut self) -> (f64, f64) {
-        let mut left = 0f64;
-        let mut right = 0f64;
-        for voice_index in self.active_voices.iter() {
-            let ref mut voice = self.voices[*voice_index];
-            if voice.patch_version() != self.patch_version {
-                voice.update_patch(&self.patch.borrow(), self.patch_version);
+    fn control_osc_noise(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, name)) = args_osc_name(args) {
+            if noise::Color::from_name(&name).is_some() {
+                if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                    patch_osc.noise = name;
+                    self.patch_version += 1;
+                }
             }
-            let (voice_left, voice_right) = voice.process();
-            left += voice_left;
-            right += voice_right;
         }
+    }
 
-        (left, right)
+    fn control_osc_analog(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, name)) = args_osc_name(args) {
+            if analog::Waveform::from_name(&name).is_some() {
+                if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                    patch_osc.analog = name;
+                    self.patch_version += 1;
+                }
+            }
+        }
     }
-}
 
-fn args_note(args: &Option<Vec<OscType>>) -> Option<(usize, f64)> {
-    match args {
-        &Some(ref args) if args.len() == 2 => {
-            match (&args[0], &args[1]) {
-                (&OscType::Float(ref key), &OscType::Float(ref value)) => {
-                    let key = key.clone() as usize;
-                    let velocity = value.clone() as f64;
-                    if key < MAX_KEYS && velocity >= 0.0 && velocity <= 1.0 {
-                        Some((key, velocity))
-                    }
-                    else { None }
-                },
-                _ => None
+    fn control_osc_pulse_width(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_osc_val(args, 0.0, 1.0) {
+            if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                patch_osc.pulse_width = value;
+                self.patch_version += 1;
             }
-        },
-        &Some(ref args) if args.len() == 4 => {
-            match (&args[1], &args[2]) {
-                (&OscType::Int(ref key), &OscType::Float(ref value)) => {
-                    let key = key.clone() as usize;
-                    let velocity = value.clone() as f64;
-                    if key < MAX_KEYS && velocity >= 0.0 && velocity <= 1.0 {
-                        Some((key, velocity))
-                    }
-                    else { None }
-                },
-                _ => None
+        }
+    }
+
+    fn control_osc_wavetable(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, name)) = args_osc_name(args) {
+            if self.registry.contains(&name) {
+                if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                    patch_osc.wavetable = name;
+                    self.patch_version += 1;
+                }
             }
-        },
-        _ => None
+            else {
+                let err = wavetable::Error::UnknownName(name);
+                self.output_error(err.to_string());
+            }
+        }
     }
-}
 
-fn args_osc_val(args: &Option<Vec<OscType>>, min: f64, max: f64) -> Option<(usize, f64)> {
-    match args {
-        &Some(ref args) if args.len() == 2 => {
-            match (&args[0], &args[1]) {
-                (&OscType::Int(ref index), &OscType::Float(ref value)) => {
-                    let index = (index - 1) as usize;
-                    let value = value.clone() as f64;
-                    if index < MAX_OSCILLATORS && value >= min && value <= max {
-                        Some((index, value))
-                    }
-                    else { None }
-                },
-                _ => None
+    fn control_osc_interpolation(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, name)) = args_osc_name(args) {
+            if Interpolation::from_name(&name).is_some() {
+                if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                    patch_osc.interpolation = name;
+                    self.patch_version += 1;
+                }
             }
-        },
-        _ => None
+        }
     }
-}
 
-fn args_osc_toggle(args: &Option<Vec<OscType>>) -> Option<(usize, bool)> {
-    match args {
-        &Some(ref args) if args.len() == 2 => {
-            match (&args[0], &args[1]) {
-                (&OscType::Int(ref index), &OscType::Int(ref value)) => {
-                    let index = (index - 1) as usize;
-                    let value = value.clone() as usize;
-                    if index < MAX_OSCILLATORS && (value == 1 || value == 0) {
-                        Some((index, value == 1))
-                    }
-                    else { None }
-                },
-                _ => None
+    fn control_osc_position(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_osc_val(args, 0.0, 1.0) {
+            if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                patch_osc.position = value;
+                self.patch_version += 1;
             }
-        },
-        _ => None
+        }
     }
-}
 
-fn args_osc_mod_val(args: &Option<Vec<OscType>>) -> Option<(usize, usize, f64)> {
-    match args {
-        &Some(ref args) if args.len() == 3 => {
-            match (&args[0], &args[1], &args[2]) {
-                (&OscType::Int(ref dst_index), &OscType::Int(ref src_index), &OscType::Float(ref value)) => {
-                    let src_index = (src_index - 1) as usize;
-                    let dst_index = (dst_index - 1) as usize;
-                    let value = value.clone() as f64;
-                    if src_index < MAX_OSCILLATORS && dst_index < MAX_OSCILLATORS && value >= -1.0 && value <= 1.0 {
-                        Some((src_index, dst_index, value))
+    fn control_osc_attack(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_osc_val(args, 0.0, 10.0) {
+            if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                patch_osc.amp_env.attack = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_osc_decay(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_osc_val(args, 0.0, 10.0) {
+            if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                patch_osc.amp_env.decay = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_osc_sustain(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_osc_val(args, 0.0, 1.0) {
+            if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                patch_osc.amp_env.sustain = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_osc_release(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_osc_val(args, 0.0, 10.0) {
+            if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(index) {
+                patch_osc.amp_env.release = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_filter_kind(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, name)) = args_index_name(args, MAX_FILTERS) {
+            if FilterKind::from_name(&name).is_some() {
+                if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                    patch_filter.kind = name;
+                    self.patch_version += 1;
+                }
+            }
+        }
+    }
+
+    fn control_filter_mode(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, name)) = args_index_name(args, MAX_FILTERS) {
+            if Mode::from_name(&name).is_some() {
+                if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                    patch_filter.mode = name;
+                    self.patch_version += 1;
+                }
+            }
+        }
+    }
+
+    fn control_filter_slope(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, name)) = args_index_name(args, MAX_FILTERS) {
+            if Slope::from_name(&name).is_some() {
+                if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                    patch_filter.slope = name;
+                    self.patch_version += 1;
+                }
+            }
+        }
+    }
+
+    fn control_filter_freq(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_FILTERS, filter::CUTOFF_MIN, 22000.0) {
+            if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                patch_filter.freq = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_filter_res(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_FILTERS, 0.0, 1.0) {
+            if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                patch_filter.res = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_filter_drive(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_FILTERS, 0.1, 10.0) {
+            if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                patch_filter.drive = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_filter_vowel(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_FILTERS, 0.0, 4.0) {
+            if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                patch_filter.vowel = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    /// Amount of key tracking of the cutoff, relative to the middle C
+    fn control_filter_key_tracking(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_FILTERS, -1.0, 2.0) {
+            if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                patch_filter.key_tracking = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    /// Scale of the cutoff modulation sent by the envelopes, negative to invert them
+    fn control_filter_env_amount(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_FILTERS, -1.0, 1.0) {
+            if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                patch_filter.env_amount = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    /// Cutoff shift in octaves at full velocity
+    fn control_filter_velocity(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_FILTERS, -8.0, 8.0) {
+            if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                patch_filter.velocity = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    /// Send level from an oscillator to the input of a filter
+    fn control_filter_send(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((dst_index, src_index, value)) = args_send(args, MAX_FILTERS, MAX_OSCILLATORS) {
+            if let Some(patch_osc) = self.patch.borrow_mut().oscillators.get_mut(src_index) {
+                patch_osc.filt_send.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    /// Send level from a filter to the input of a following filter
+    fn control_filter_chain(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((dst_index, src_index, value)) = args_send(args, MAX_FILTERS, MAX_FILTERS) {
+            if src_index < dst_index {
+                if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(src_index) {
+                    patch_filter.filt_send.insert(dst_index, value);
+                    self.patch_version += 1;
+                }
+            }
+        }
+    }
+
+    fn control_filter_panning(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_FILTERS, -1.0, 1.0) {
+            if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                patch_filter.panning = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_filter_level(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_FILTERS, 0.0, 1.0) {
+            if let Some(patch_filter) = self.patch.borrow_mut().filters.get_mut(index) {
+                patch_filter.level = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    /// Sets a band of the equalizer, or adds it when it is just after the last one
+    fn control_eq_band(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, band)) = args_eq_band(args) {
+            let mut patch = self.patch.borrow_mut();
+            if index < patch.eq.len() {
+                patch.eq[index] = band;
+            }
+            else if index == patch.eq.len() {
+                patch.eq.push(band);
+            }
+            else {
+                return;
+            }
+            self.patch_version += 1;
+        }
+    }
+
+    fn control_eq_num_bands(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some(value) = args_int(args) {
+            if value <= MAX_EQ_BANDS {
+                self.patch.borrow_mut().eq.resize(value, EqBandPatch::new("peak", 1000.0, 1.0, 0.0));
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_env_point(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, point, time, level, curve)) = args_env_point(args) {
+            if let Some(patch_env) = self.patch.borrow_mut().envelopes.get_mut(index) {
+                let breakpoints = &mut patch_env.breakpoints;
+                let bp = BreakpointPatch::new(time, level, curve);
+                if point < breakpoints.len() {
+                    breakpoints[point] = bp;
+                }
+                else if point == breakpoints.len() {
+                    breakpoints.push(bp);
+                }
+                else {
+                    return;
+                }
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_env_num_points(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_int(args, MAX_ENVELOPES) {
+            if value <= MAX_ENV_POINTS {
+                if let Some(patch_env) = self.patch.borrow_mut().envelopes.get_mut(index) {
+                    let breakpoints = &mut patch_env.breakpoints;
+                    let last = breakpoints.last().map_or(0.0, |bp| bp.level);
+                    breakpoints.resize(value, BreakpointPatch::new(0.0, last, 0.0));
+                    self.patch_version += 1;
+                }
+            }
+        }
+    }
+
+    fn control_env_sustain(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_int(args, MAX_ENVELOPES) {
+            if let Some(patch_env) = self.patch.borrow_mut().envelopes.get_mut(index) {
+                patch_env.sustain = value.checked_sub(1);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_env_loop(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, start, end)) = args_env_loop(args) {
+            if let Some(patch_env) = self.patch.borrow_mut().envelopes.get_mut(index) {
+                patch_env.loop_start = start.checked_sub(1);
+                patch_env.loop_end = end.checked_sub(1);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_env_amp(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, dst_index, value)) = args_env_send(args, MAX_OSCILLATORS, 0.0, 1.0) {
+            if let Some(patch_env) = self.patch.borrow_mut().envelopes.get_mut(index) {
+                patch_env.mod_sends.amp.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_env_pitch(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, dst_index, value)) = args_env_send(args, MAX_OSCILLATORS, -48.0, 48.0) {
+            if let Some(patch_env) = self.patch.borrow_mut().envelopes.get_mut(index) {
+                patch_env.mod_sends.pitch.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_env_position(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, dst_index, value)) = args_env_send(args, MAX_OSCILLATORS, -1.0, 1.0) {
+            if let Some(patch_env) = self.patch.borrow_mut().envelopes.get_mut(index) {
+                patch_env.mod_sends.position.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_env_pulse_width(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, dst_index, value)) = args_env_send(args, MAX_OSCILLATORS, -1.0, 1.0) {
+            if let Some(patch_env) = self.patch.borrow_mut().envelopes.get_mut(index) {
+                patch_env.mod_sends.pulse_width.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_env_cutoff(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, dst_index, value)) = args_env_send(args, MAX_FILTERS, -8.0, 8.0) {
+            if let Some(patch_env) = self.patch.borrow_mut().envelopes.get_mut(index) {
+                patch_env.mod_sends.cutoff.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_lfo_shape(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, name)) = args_index_name(args, MAX_LFOS) {
+            if lfo::Shape::from_name(&name).is_some() {
+                if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                    patch_lfo.shape = name;
+                    self.patch_version += 1;
+                }
+            }
+            else {
+                self.output_error(format!("Unknown LFO shape {}", name));
+            }
+        }
+    }
+
+    /// Rate in Hz, used when the LFO is not synced to the tempo
+    fn control_lfo_freq(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_LFOS, 0.01, 50.0) {
+            if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                patch_lfo.freq = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    /// Note value of the period like 1/4, 1/8d or 1/16t, or off for the free rate
+    fn control_lfo_sync(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, name)) = args_index_name(args, MAX_LFOS) {
+            if name == "off" || NoteValue::from_name(&name).is_some() {
+                if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                    patch_lfo.sync = name;
+                    self.patch_version += 1;
+                }
+            }
+            else {
+                self.output_error(format!("Invalid LFO note value {}", name));
+            }
+        }
+    }
+
+    fn control_lfo_phase(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_LFOS, 0.0, 1.0) {
+            if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                patch_lfo.phase = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_lfo_delay(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_LFOS, 0.0, 10.0) {
+            if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                patch_lfo.delay = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_lfo_fade_in(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_val(args, MAX_LFOS, 0.0, 10.0) {
+            if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                patch_lfo.fade_in = value;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    /// Shares one LFO between all the voices, instead of restarting one per voice with every note
+    fn control_lfo_global(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, value)) = args_index_int(args, MAX_LFOS) {
+            if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                patch_lfo.is_global = value != 0;
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_lfo_amp(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, dst_index, value)) = args_mod_send(args, MAX_LFOS, MAX_OSCILLATORS, 0.0, 1.0) {
+            if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                patch_lfo.mod_sends.amp.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_lfo_pitch(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, dst_index, value)) = args_mod_send(args, MAX_LFOS, MAX_OSCILLATORS, -48.0, 48.0) {
+            if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                patch_lfo.mod_sends.pitch.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_lfo_position(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, dst_index, value)) = args_mod_send(args, MAX_LFOS, MAX_OSCILLATORS, -1.0, 1.0) {
+            if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                patch_lfo.mod_sends.position.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_lfo_pulse_width(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, dst_index, value)) = args_mod_send(args, MAX_LFOS, MAX_OSCILLATORS, -1.0, 1.0) {
+            if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                patch_lfo.mod_sends.pulse_width.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    fn control_lfo_cutoff(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some((index, dst_index, value)) = args_mod_send(args, MAX_LFOS, MAX_FILTERS, -8.0, 8.0) {
+            if let Some(patch_lfo) = self.patch.borrow_mut().lfos.get_mut(index) {
+                patch_lfo.mod_sends.cutoff.insert(dst_index, value);
+                self.patch_version += 1;
+            }
+        }
+    }
+
+    /// Ramp time in seconds of the amplitude, level, panning, detune and cutoff changes
+    fn control_smoothing(&mut self, args: &Option<Vec<OscType>>) {
+        if let &Some(ref args) = args {
+            if let Some(&OscType::Float(value)) = args.first() {
+                if value >= 0.0 && value <= 1.0 {
+                    self.patch.borrow_mut().smoothing = value as f64;
+                    self.patch_version += 1;
+                }
+            }
+        }
+    }
+
+    fn control_pan_law(&mut self, args: &Option<Vec<OscType>>) {
+        if let Some(name) = args_name(args) {
+            if panning::Law::from_name(&name).is_some() {
+                self.patch.borrow_mut().pan_law = name;
+                self.patch_version += 1;
+            }
+            else {
+                self.output_error(format!("Unknown panning law {}", name));
+            }
+        }
+    }
+
+    /// Stereo width of the output, from 0 for mono to 2
+    fn control_width(&mut self, args: &Option<Vec<OscType>>) {
+        if let &Some(ref args) = args {
+            if let Some(&OscType::Float(value)) = args.first() {
+                if value >= 0.0 && value <= 2.0 {
+                    self.patch.borrow_mut().width = value as f64;
+                    self.stereo_image.set_width(value as f64);
+                    self.patch_version += 1;
+                }
+            }
+        }
+    }
+
+    fn control_balance(&mut self, args: &Option<Vec<OscType>>) {
+        if let &Some(ref args) = args {
+            if let Some(&OscType::Float(value)) = args.first() {
+                if value >= -1.0 && value <= 1.0 {
+                    self.patch.borrow_mut().balance = value as f64;
+                    self.stereo_image.set_balance(value as f64);
+                    self.patch_version += 1;
+                }
+            }
+        }
+    }
+
+    /// Tempo in beats per minute
+    fn control_tempo(&mut self, args: &Option<Vec<OscType>>) {
+        if let &Some(ref args) = args {
+            if let Some(&OscType::Float(value)) = args.first() {
+                if value >= 20.0 && value <= 300.0 {
+                    self.set_tempo(value as f64);
+                }
+            }
+        }
+    }
+
+    /// Frequency of the reference key from 400 to 480 Hz, the A above the middle C without keyboard mapping
+    fn control_tuning_reference(&mut self, args: &Option<Vec<OscType>>) {
+        if let &Some(ref args) = args {
+            if let Some(&OscType::Float(value)) = args.first() {
+                if value >= 400.0 && value <= 480.0 {
+                    self.tuning.set_reference_freq(value as f64);
+                }
+                else {
+                    self.output_error(format!("Invalid reference frequency {}, it has to be from 400 to 480 Hz", value));
+                }
+            }
+        }
+    }
+
+    fn output_error(&mut self, description: String) {
+        let packet = Self::osc_message(ADDR_ERROR, vec![OscType::String(description)]);
+        self.output_packets.push(packet);
+    }
+
+    // fn foreach_active_voice<F>(&mut self, mut f: F) where F: FnMut(&mut Voice) {
+    //     for voice_index in self.active_voices.iter() {
+    //         f(&mut self.voices[*voice_index])
+    //     }
+    // }
+
+    pub fn process(&mut self) -> (f64, f64) {
+        for voice_index in self.active_voices.iter() {
+            let ref mut voice = self.voices[*voice_index];
+            if voice.patch_version() != self.patch_version {
+                voice.update_patch(&self.patch.borrow(), &self.registry, self.patch_version);
+            }
+        }
+
+        if self.global_lfos_version != self.patch_version {
+            for (lfo, patch_lfo) in self.global_lfos.iter_mut().zip(self.patch.borrow().lfos.iter()) {
+                patch::update_lfo(lfo, patch_lfo);
+            }
+            self.global_lfos_version = self.patch_version;
+        }
+
+        let mut global_lfos = [0.0f64; MAX_LFOS];
+        for i in 0..self.oversampling {
+            for (value, lfo) in global_lfos.iter_mut().zip(self.global_lfos.iter_mut()) {
+                *value = lfo.process();
+            }
+
+            let mut left = 0f64;
+            let mut right = 0f64;
+            for voice_index in self.active_voices.iter() {
+                let (voice_left, voice_right) = self.voices[*voice_index].process(&global_lfos);
+                left += voice_left;
+                right += voice_right;
+            }
+            self.buffers[0][i] = left;
+            self.buffers[1][i] = right;
+        }
+
+        // Voices stay active until their envelopes finish the release
+        let voices = &self.voices;
+        self.active_voices.retain(|voice_index| voices[*voice_index].is_active());
+
+        let factor = self.oversampling;
+        let downsamplers = &mut self.downsamplers[factor_index(factor)];
+        let left = downsamplers[0].process(&self.buffers[0][..factor]);
+        let right = downsamplers[1].process(&self.buffers[1][..factor]);
+        self.stereo_image.process(left, right)
+    }
+}
+
+/// Index of an oversampling factor in `oversampling::FACTORS`
+fn factor_index(factor: usize) -> usize {
+    factor.trailing_zeros() as usize
+}
+
+fn args_note(args: &Option<Vec<OscType>>) -> Option<(usize, f64)> {
+    match args {
+        &Some(ref args) if args.len() == 2 => {
+            match (&args[0], &args[1]) {
+                (&OscType::Float(ref key), &OscType::Float(ref value)) => {
+                    let key = key.clone() as usize;
+                    let velocity = value.clone() as f64;
+                    if key < MAX_KEYS && velocity >= 0.0 && velocity <= 1.0 {
+                        Some((key, velocity))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        &Some(ref args) if args.len() == 4 => {
+            match (&args[1], &args[2]) {
+                (&OscType::Int(ref key), &OscType::Float(ref value)) => {
+                    let key = key.clone() as usize;
+                    let velocity = value.clone() as f64;
+                    if key < MAX_KEYS && velocity >= 0.0 && velocity <= 1.0 {
+                        Some((key, velocity))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_osc_val(args: &Option<Vec<OscType>>, min: f64, max: f64) -> Option<(usize, f64)> {
+    match args {
+        &Some(ref args) if args.len() == 2 => {
+            match (&args[0], &args[1]) {
+                (&OscType::Int(ref index), &OscType::Float(ref value)) => {
+                    let index = (index - 1) as usize;
+                    let value = value.clone() as f64;
+                    if index < MAX_OSCILLATORS && value >= min && value <= max {
+                        Some((index, value))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_osc_toggle(args: &Option<Vec<OscType>>) -> Option<(usize, bool)> {
+    match args {
+        &Some(ref args) if args.len() == 2 => {
+            match (&args[0], &args[1]) {
+                (&OscType::Int(ref index), &OscType::Int(ref value)) => {
+                    let index = (index - 1) as usize;
+                    let value = value.clone() as usize;
+                    if index < MAX_OSCILLATORS && (value == 1 || value == 0) {
+                        Some((index, value == 1))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_name(args: &Option<Vec<OscType>>) -> Option<String> {
+    match args {
+        &Some(ref args) if args.len() == 1 => {
+            match &args[0] {
+                &OscType::String(ref name) => Some(name.clone()),
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_int(args: &Option<Vec<OscType>>) -> Option<usize> {
+    match args {
+        &Some(ref args) if args.len() == 1 => {
+            match &args[0] {
+                &OscType::Int(value) if value >= 0 => Some(value as usize),
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_osc_name(args: &Option<Vec<OscType>>) -> Option<(usize, String)> {
+    match args {
+        &Some(ref args) if args.len() == 2 => {
+            match (&args[0], &args[1]) {
+                (&OscType::Int(ref index), &OscType::String(ref name)) => {
+                    let index = (index - 1) as usize;
+                    if index < MAX_OSCILLATORS {
+                        Some((index, name.clone()))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_index_val(args: &Option<Vec<OscType>>, max_index: usize, min: f64, max: f64) -> Option<(usize, f64)> {
+    match args {
+        &Some(ref args) if args.len() == 2 => {
+            match (&args[0], &args[1]) {
+                (&OscType::Int(ref index), &OscType::Float(ref value)) => {
+                    let index = (index - 1) as usize;
+                    let value = *value as f64;
+                    if index < max_index && value >= min && value <= max {
+                        Some((index, value))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_index_name(args: &Option<Vec<OscType>>, max_index: usize) -> Option<(usize, String)> {
+    match args {
+        &Some(ref args) if args.len() == 2 => {
+            match (&args[0], &args[1]) {
+                (&OscType::Int(ref index), &OscType::String(ref name)) => {
+                    let index = (index - 1) as usize;
+                    if index < max_index {
+                        Some((index, name.clone()))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+/// Arguments of a send: destination index, source index and level
+fn args_send(args: &Option<Vec<OscType>>, max_dst: usize, max_src: usize) -> Option<(usize, usize, f64)> {
+    match args {
+        &Some(ref args) if args.len() == 3 => {
+            match (&args[0], &args[1], &args[2]) {
+                (&OscType::Int(ref dst_index), &OscType::Int(ref src_index), &OscType::Float(ref value)) => {
+                    let dst_index = (dst_index - 1) as usize;
+                    let src_index = (src_index - 1) as usize;
+                    let value = *value as f64;
+                    if dst_index < max_dst && src_index < max_src && value >= -1.0 && value <= 1.0 {
+                        Some((dst_index, src_index, value))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+/// Arguments of a band of the equalizer: index, kind, frequency, Q and gain in dB.
+/// It is shared with the equalizer of the master bus, so both are controlled the same way.
+pub fn args_eq_band(args: &Option<Vec<OscType>>) -> Option<(usize, EqBandPatch)> {
+    match args {
+        &Some(ref args) if args.len() == 5 => {
+            match (&args[0], &args[1], &args[2], &args[3], &args[4]) {
+                (&OscType::Int(ref index), &OscType::String(ref kind),
+                 &OscType::Float(ref freq), &OscType::Float(ref q), &OscType::Float(ref gain)) => {
+                    let index = (index - 1) as usize;
+                    let (freq, q, gain) = (*freq as f64, *q as f64, *gain as f64);
+                    if index < MAX_EQ_BANDS && BandKind::from_name(kind).is_some()
+                        && freq >= filter::CUTOFF_MIN && freq <= 22000.0
+                        && q >= 0.1 && q <= 20.0
+                        && gain >= -24.0 && gain <= 24.0 {
+                        Some((index, EqBandPatch::new(kind, freq, q, gain)))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_wavetable_load(args: &Option<Vec<OscType>>) -> Option<(String, String, Option<usize>)> {
+    match args {
+        &Some(ref args) if args.len() == 2 => {
+            match (&args[0], &args[1]) {
+                (&OscType::String(ref name), &OscType::String(ref path)) => {
+                    Some((name.clone(), path.clone(), None))
+                },
+                _ => None
+            }
+        },
+        &Some(ref args) if args.len() == 3 => {
+            match (&args[0], &args[1], &args[2]) {
+                (&OscType::String(ref name), &OscType::String(ref path), &OscType::Int(ref frame_size)) => {
+                    if *frame_size > 0 {
+                        Some((name.clone(), path.clone(), Some(*frame_size as usize)))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+/// Path of a scale file and optionally of a keyboard mapping file
+fn args_tuning_load(args: &Option<Vec<OscType>>) -> Option<(String, Option<String>)> {
+    match args {
+        &Some(ref args) if args.len() == 1 => {
+            match &args[0] {
+                &OscType::String(ref scale_path) => Some((scale_path.clone(), None)),
+                _ => None
+            }
+        },
+        &Some(ref args) if args.len() == 2 => {
+            match (&args[0], &args[1]) {
+                (&OscType::String(ref scale_path), &OscType::String(ref mapping_path)) => {
+                    Some((scale_path.clone(), Some(mapping_path.clone())))
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+/// The source index is 0 to disable the sync
+fn args_osc_sync(args: &Option<Vec<OscType>>) -> Option<(usize, Option<usize>, String)> {
+    match args {
+        &Some(ref args) if args.len() == 3 => {
+            match (&args[0], &args[1], &args[2]) {
+                (&OscType::Int(ref index), &OscType::Int(ref src_index), &OscType::String(ref mode)) => {
+                    let index = (index - 1) as usize;
+                    let src_index = *src_index as usize;
+                    if index < MAX_OSCILLATORS && src_index <= MAX_OSCILLATORS && src_index != index + 1 {
+                        let src_index = if src_index > 0 { Some(src_index - 1) } else { None };
+                        Some((index, src_index, mode.clone()))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_osc_mod_val(args: &Option<Vec<OscType>>) -> Option<(usize, usize, f64)> {
+    match args {
+        &Some(ref args) if args.len() == 3 => {
+            match (&args[0], &args[1], &args[2]) {
+                (&OscType::Int(ref dst_index), &OscType::Int(ref src_index), &OscType::Float(ref value)) => {
+                    let src_index = (src_index - 1) as usize;
+                    let dst_index = (dst_index - 1) as usize;
+                    let value = value.clone() as f64;
+                    if src_index < MAX_OSCILLATORS && dst_index < MAX_OSCILLATORS && value >= -1.0 && value <= 1.0 {
+                        Some((src_index, dst_index, value))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_index_int(args: &Option<Vec<OscType>>, max_index: usize) -> Option<(usize, usize)> {
+    match args {
+        &Some(ref args) if args.len() == 2 => {
+            match (&args[0], &args[1]) {
+                (&OscType::Int(ref index), &OscType::Int(ref value)) => {
+                    let index = (index - 1) as usize;
+                    if index < max_index && *value >= 0 {
+                        Some((index, *value as usize))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_env_point(args: &Option<Vec<OscType>>) -> Option<(usize, usize, f64, f64, f64)> {
+    match args {
+        &Some(ref args) if args.len() == 5 => {
+            match (&args[0], &args[1], &args[2], &args[3], &args[4]) {
+                (&OscType::Int(ref index), &OscType::Int(ref point),
+                 &OscType::Float(ref time), &OscType::Float(ref level), &OscType::Float(ref curve)) => {
+                    let index = (index - 1) as usize;
+                    let point = (point - 1) as usize;
+                    let (time, level, curve) = (*time as f64, *level as f64, *curve as f64);
+                    if index < MAX_ENVELOPES && point < MAX_ENV_POINTS
+                        && time >= 0.0 && time <= 60.0
+                        && level >= -1.0 && level <= 1.0
+                        && curve >= -1.0 && curve <= 1.0 {
+                        Some((index, point, time, level, curve))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_env_loop(args: &Option<Vec<OscType>>) -> Option<(usize, usize, usize)> {
+    match args {
+        &Some(ref args) if args.len() == 3 => {
+            match (&args[0], &args[1], &args[2]) {
+                (&OscType::Int(ref index), &OscType::Int(ref start), &OscType::Int(ref end)) => {
+                    let index = (index - 1) as usize;
+                    if index < MAX_ENVELOPES && *start >= 0 && *end >= 0 {
+                        Some((index, *start as usize, *end as usize))
+                    }
+                    else { None }
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
+}
+
+fn args_env_send(args: &Option<Vec<OscType>>, max_dst: usize, min: f64, max: f64) -> Option<(usize, usize, f64)> {
+    args_mod_send(args, MAX_ENVELOPES, max_dst, min, max)
+}
+
+/// Index of a modulation source, index of its destination and depth
+fn args_mod_send(args: &Option<Vec<OscType>>, max_index: usize, max_dst: usize, min: f64, max: f64) -> Option<(usize, usize, f64)> {
+    match args {
+        &Some(ref args) if args.len() == 3 => {
+            match (&args[0], &args[1], &args[2]) {
+                (&OscType::Int(ref index), &OscType::Int(ref dst_index), &OscType::Float(ref value)) => {
+                    let index = (index - 1) as usize;
+                    let dst_index = (dst_index - 1) as usize;
+                    let value = *value as f64;
+                    if index < max_index && dst_index < max_dst && value >= min && value <= max {
+                        Some((index, dst_index, value))
                     }
                     else { None }
                 },
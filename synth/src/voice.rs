@@ -1,18 +1,24 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 
-use hero_core::types::SampleRate;
-use hero_core::freq::KEY_FREQ;
-use hero_core::wavetable::{self, Wavetable};
+use hero_core::types::{SampleRate, Tempo, DEFAULT_TEMPO};
+use hero_core::wavetable::{self, Wavetable, Registry};
 use hero_core::oscillator::Oscillator;
+use hero_core::envelope::Envelope;
+use hero_core::envelope::adsr::Adsr;
+use hero_core::envelope::mseg::Mseg;
+use hero_core::lfo::Lfo;
+use hero_core::smoothing::Smoother;
 use hero_core::panning::Panning;
-use hero_core::filter::Filter;
-use hero_core::filter::iir::IIR;
+use hero_core::filter::{self, Filter, Mode, Slope};
+use hero_core::filter::eq::Equalizer;
 
-use patch::Patch;
+use patch::{self, Patch, OscPatch, FilterPatch, FilterKind, FmMode};
 
 pub const MAX_OSCILLATORS: usize = 8;
 pub const MAX_FILTERS: usize = 2;
+pub const MAX_ENVELOPES: usize = 4;
+pub const MAX_LFOS: usize = 2;
 
 /// Modulation index for Frequency Modulation
 const MOD_INDEX: f64 = 6.0;
@@ -20,95 +26,312 @@ const MOD_INDEX: f64 = 6.0;
 #[derive(Debug)]
 struct VoiceOsc {
     oscillator: Oscillator,
+    wavetable: String,          // Name of the wavetable loaded in the oscillator
+    envelope: Adsr,
     panning: Panning,
+    amplitude: Smoother,
+    level: Smoother,
+    pan: Smoother,
+    detune: Smoother,
+}
+
+impl VoiceOsc {
+    fn new(sample_rate: SampleRate, oscillator: Oscillator, wavetable: String, envelope: Adsr, patch_osc: &OscPatch, smoothing: f64) -> VoiceOsc {
+        VoiceOsc {
+            oscillator: oscillator,
+            wavetable: wavetable,
+            envelope: envelope,
+            panning: Panning::new(patch_osc.panning),
+            amplitude: Smoother::linear(sample_rate, smoothing, patch_osc.amplitude),
+            level: Smoother::linear(sample_rate, smoothing, patch_osc.level),
+            pan: Smoother::linear(sample_rate, smoothing, patch_osc.panning),
+            detune: Smoother::linear(sample_rate, smoothing, patch_osc.detune),
+        }
+    }
+
+    fn set_smoothing(&mut self, time: f64) {
+        self.amplitude.set_time(time);
+        self.level.set_time(time);
+        self.pan.set_time(time);
+        self.detune.set_time(time);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.oscillator.set_sample_rate(sample_rate);
+        self.envelope.set_sample_rate(sample_rate);
+        self.amplitude.set_sample_rate(sample_rate);
+        self.level.set_sample_rate(sample_rate);
+        self.pan.set_sample_rate(sample_rate);
+        self.detune.set_sample_rate(sample_rate);
+    }
+
+    fn skip_smoothing(&mut self) {
+        self.amplitude.skip();
+        self.level.skip();
+        self.pan.skip();
+        self.detune.skip();
+    }
 }
 
 #[derive(Debug)]
 struct VoiceFilter {
-    iir: IIR,
-    panning: Panning
+    filter: Box<dyn Filter>,
+    kind: FilterKind,           // Implementation of the filter, it is created again when it changes
+    mode: Mode,
+    slope: Slope,
+    panning: Panning,
+    cutoff: Smoother,
+    level: Smoother,
+    pan: Smoother,
+}
+
+impl VoiceFilter {
+    fn new(sample_rate: SampleRate, patch_filter: &FilterPatch, smoothing: f64) -> VoiceFilter {
+        VoiceFilter {
+            filter: patch_filter.to_filter(sample_rate),
+            kind: patch_filter.get_kind(),
+            mode: patch_filter.get_mode(),
+            slope: patch_filter.get_slope(),
+            panning: Panning::new(patch_filter.panning),
+            cutoff: Smoother::linear(sample_rate, smoothing, patch_filter.freq),
+            level: Smoother::linear(sample_rate, smoothing, patch_filter.level),
+            pan: Smoother::linear(sample_rate, smoothing, patch_filter.panning),
+        }
+    }
+
+    fn set_smoothing(&mut self, time: f64) {
+        self.cutoff.set_time(time);
+        self.level.set_time(time);
+        self.pan.set_time(time);
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.filter.set_sample_rate(sample_rate);
+        self.cutoff.set_sample_rate(sample_rate);
+        self.level.set_sample_rate(sample_rate);
+        self.pan.set_sample_rate(sample_rate);
+    }
+
+    fn skip_smoothing(&mut self) {
+        self.cutoff.skip();
+        self.level.skip();
+        self.pan.skip();
+    }
 }
 
 #[derive(Debug)]
 pub struct Voice {
+    sample_rate: SampleRate,
     patch: Rc<RefCell<Patch>>,
     patch_version: usize,
     oscillators: Vec<VoiceOsc>,
     filters: Vec<VoiceFilter>,
-    key: usize,
+    envelopes: Vec<Mseg>,
+    lfos: Vec<Lfo>,             // Only the LFOs of the patch that are not global are used
+    tempo: Tempo,
+    eq: Equalizer,
+    key_freq: f64,              // Frequency of the key in the tuning of the synth
     velocity: f64,
 }
 
 /// This synth has one voice per allowed key, so every voice has a fixed freq.
 impl Voice {
-    pub fn new(sample_rate: SampleRate, patch: Rc<RefCell<Patch>>) -> Voice {
+    pub fn new(sample_rate: SampleRate, patch: Rc<RefCell<Patch>>, registry: &Registry) -> Voice {
+        let smoothing = patch.borrow().smoothing;
         let mut oscillators = Vec::<VoiceOsc>::with_capacity(MAX_OSCILLATORS);
         for patch_osc in patch.borrow().oscillators.iter().take(MAX_OSCILLATORS) {
-            let osc = patch_osc.to_oscillator(sample_rate);
-            let voice_osc = VoiceOsc {
-                oscillator: osc,
-                panning: Panning::new(patch_osc.panning),
+            let (osc, wt_name) = match patch_osc.to_oscillator(sample_rate, registry) {
+                Ok(osc) => (osc, patch_osc.wavetable.clone()),
+                Err(_) => (Oscillator::from_sample_rate(sample_rate), "sin".to_string()),
             };
+            let envelope = patch_osc.amp_env.to_envelope(sample_rate);
+            let voice_osc = VoiceOsc::new(sample_rate, osc, wt_name, envelope, patch_osc, smoothing);
             oscillators.push(voice_osc);
         }
         while oscillators.len() < MAX_OSCILLATORS {
             let wt = Wavetable::from_stock(wavetable::Stock::Sin);
             let osc = Oscillator::new(sample_rate, wt, 0.0);
-            let voice_osc = VoiceOsc {
-                oscillator: osc,
-                panning: Panning::new(0.0),
-            };
+            let envelope = Adsr::from_sample_rate(sample_rate);
+            let voice_osc = VoiceOsc::new(sample_rate, osc, "sin".to_string(), envelope, &OscPatch::default(), smoothing);
             oscillators.push(voice_osc);
         }
 
-        let mut filters = Vec::<VoiceFilter>::with_capacity(MAX_FILTERS);
-        for filt_patch in patch.borrow().filters.iter().take(MAX_FILTERS) {
-            // TODO filter from patch
-            let iir = IIR::bypass(sample_rate);
-            let pan = Panning::new(filt_patch.panning);
-            let voice_filter = VoiceFilter {
-                iir: iir,
-                panning: pan
-            };
-            filters.push(voice_filter);
-        }
+        let filters = Self::create_filters(sample_rate, &patch.borrow());
+        let envelopes = Self::create_envelopes(sample_rate, &patch.borrow());
+        let lfos = Self::create_lfos(sample_rate, DEFAULT_TEMPO, &patch.borrow());
+        let mut eq = Equalizer::new(sample_rate);
+        patch::update_equalizer(&mut eq, &patch.borrow().eq);
 
         Voice {
+            sample_rate: sample_rate,
             patch: patch,
             patch_version: 0,
             oscillators: oscillators,
             filters: filters,
-            key: 0,
+            envelopes: envelopes,
+            lfos: lfos,
+            tempo: DEFAULT_TEMPO,
+            eq: eq,
+            key_freq: 0.0,
             velocity: 0.0
         }
     }
 
+    fn create_filters(sample_rate: SampleRate, patch: &Patch) -> Vec<VoiceFilter> {
+        patch.filters.iter()
+            .take(MAX_FILTERS)
+            .map(|patch_filter| VoiceFilter::new(sample_rate, patch_filter, patch.smoothing))
+            .collect()
+    }
+
+    fn create_envelopes(sample_rate: SampleRate, patch: &Patch) -> Vec<Mseg> {
+        patch.envelopes.iter()
+            .take(MAX_ENVELOPES)
+            .map(|patch_env| patch_env.to_envelope(sample_rate))
+            .collect()
+    }
+
+    fn create_lfos(sample_rate: SampleRate, tempo: Tempo, patch: &Patch) -> Vec<Lfo> {
+        patch.lfos.iter()
+            .take(MAX_LFOS)
+            .map(|patch_lfo| patch_lfo.to_lfo(sample_rate, tempo))
+            .collect()
+    }
+
+    /// Changes the sample rate of all the parts of the voice, the note being played goes on
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        for voice_osc in self.oscillators.iter_mut() {
+            voice_osc.set_sample_rate(sample_rate);
+        }
+        for voice_filter in self.filters.iter_mut() {
+            voice_filter.set_sample_rate(sample_rate);
+        }
+        for env in self.envelopes.iter_mut() {
+            env.set_sample_rate(sample_rate);
+        }
+        for lfo in self.lfos.iter_mut() {
+            lfo.set_sample_rate(sample_rate);
+        }
+        self.eq.set_sample_rate(sample_rate);
+    }
+
+    pub fn set_tempo(&mut self, tempo: Tempo) {
+        self.tempo = tempo;
+        for lfo in self.lfos.iter_mut() {
+            lfo.set_tempo(tempo);
+        }
+    }
+
     pub fn patch_version(&self) -> usize {
         self.patch_version
     }
 
-    pub fn update_patch(&mut self, patch: &Patch, patch_version: usize) {
+    pub fn update_patch(&mut self, patch: &Patch, registry: &Registry, patch_version: usize) {
         self.patch_version = patch_version;
         for index in 0..patch.oscillators.len() {
             let patch_osc = &patch.oscillators[index];
 
+            // The continuous parameters are smoothed while playing
             let voice_osc = &mut self.oscillators[index];
-            voice_osc.panning.set_value(patch_osc.panning);
+            voice_osc.set_smoothing(patch.smoothing);
+            voice_osc.panning.set_law(patch.get_pan_law());
+            voice_osc.amplitude.set_target(patch_osc.amplitude);
+            voice_osc.level.set_target(patch_osc.level);
+            voice_osc.pan.set_target(patch_osc.panning);
+            voice_osc.detune.set_target(patch_osc.detune);
+
+            let env = &mut voice_osc.envelope;
+            env.set_attack(patch_osc.amp_env.attack);
+            env.set_decay(patch_osc.amp_env.decay);
+            env.set_sustain(patch_osc.amp_env.sustain);
+            env.set_release(patch_osc.amp_env.release);
+            env.set_curve(patch_osc.amp_env.get_curve());
+
+            // Unknown wavetables keep the previous one
+            if voice_osc.wavetable != patch_osc.wavetable {
+                if let Ok(wavetable) = patch_osc.get_wavetable(registry) {
+                    voice_osc.oscillator.set_wavetable(wavetable);
+                    voice_osc.wavetable = patch_osc.wavetable.clone();
+                }
+            }
 
             let osc = &mut voice_osc.oscillator;
             osc.set_enabled(patch_osc.is_enabled);
-            osc.set_amplitude(patch_osc.amplitude);
-            // TODO wavetable
+            osc.set_source(patch_osc.get_source());
+            osc.set_interpolation(patch_osc.get_interpolation());
+            osc.set_position(patch_osc.position);
+            osc.set_feedback(patch_osc.get_feedback());
+            osc.set_pulse_width(patch_osc.pulse_width);
             osc.set_free_phase(patch_osc.is_free_phase);
             osc.set_initial_phase(patch_osc.initial_phase);
             osc.set_octaves(patch_osc.octaves);
             osc.set_semitones(patch_osc.semitones);
-            osc.set_detune(patch_osc.detune);
         }
+        // The oscillators removed from the patch stop their envelopes, as they will not get the note off
         let remaining_osc = patch.oscillators.len() .. MAX_OSCILLATORS;
         for voice_osc in self.oscillators[remaining_osc].iter_mut() {
             voice_osc.oscillator.set_enabled(false);
             voice_osc.oscillator.set_amplitude(0.0);
+            voice_osc.amplitude.set_value(0.0);
+            voice_osc.envelope.reset();
+        }
+
+        if self.filters.len() != patch.filters.len().min(MAX_FILTERS) {
+            self.filters = Self::create_filters(self.sample_rate, patch);
+        }
+        for (voice_filter, patch_filter) in self.filters.iter_mut().zip(patch.filters.iter()) {
+            if voice_filter.kind != patch_filter.get_kind() {
+                *voice_filter = VoiceFilter::new(self.sample_rate, patch_filter, patch.smoothing);
+                continue;
+            }
+
+            // Changing the mode or the slope can reset the state of the filter
+            let (mode, slope) = (patch_filter.get_mode(), patch_filter.get_slope());
+            if voice_filter.mode != mode {
+                voice_filter.filter.set_mode(mode);
+                voice_filter.mode = mode;
+            }
+            if voice_filter.slope != slope {
+                voice_filter.filter.set_slope(slope);
+                voice_filter.slope = slope;
+            }
+            let filter = &mut voice_filter.filter;
+            filter.set_resonance(patch_filter.res);
+            filter.set_drive(patch_filter.drive);
+            filter.set_vowel(patch_filter.vowel);
+            voice_filter.set_smoothing(patch.smoothing);
+            voice_filter.panning.set_law(patch.get_pan_law());
+            voice_filter.cutoff.set_target(patch_filter.freq);
+            voice_filter.level.set_target(patch_filter.level);
+            voice_filter.pan.set_target(patch_filter.panning);
+        }
+
+        if self.envelopes.len() != patch.envelopes.len().min(MAX_ENVELOPES) {
+            self.envelopes = Self::create_envelopes(self.sample_rate, patch);
+        }
+        for (env, patch_env) in self.envelopes.iter_mut().zip(patch.envelopes.iter()) {
+            env.set_breakpoints(patch_env.get_breakpoints());
+            env.set_sustain(patch_env.sustain);
+            env.set_loop(patch_env.get_loop());
+        }
+
+        if self.lfos.len() != patch.lfos.len().min(MAX_LFOS) {
+            self.lfos = Self::create_lfos(self.sample_rate, self.tempo, patch);
+        }
+        for (lfo, patch_lfo) in self.lfos.iter_mut().zip(patch.lfos.iter()) {
+            patch::update_lfo(lfo, patch_lfo);
+        }
+
+        patch::update_equalizer(&mut self.eq, &patch.eq);
+    }
+
+    /// Replaces the wavetable of the oscillators using it, after it has been loaded again into the registry
+    pub fn reload_wavetable(&mut self, name: &str, registry: &Registry) {
+        if let Ok(wavetable) = registry.get(name) {
+            for voice_osc in self.oscillators.iter_mut().filter(|voice_osc| voice_osc.wavetable == name) {
+                voice_osc.oscillator.set_wavetable(wavetable.clone());
+            }
         }
     }
 
@@ -116,59 +339,225 @@ impl Voice {
         // Oscillators
         for voice_osc in self.oscillators.iter_mut() {
             voice_osc.oscillator.reset();
+            voice_osc.envelope.reset();
+            voice_osc.skip_smoothing();
         }
 
         // Filters
         for voice_filter in self.filters.iter_mut() {
-            voice_filter.iir.reset();
+            voice_filter.filter.reset();
+            voice_filter.skip_smoothing();
+        }
+
+        // Envelopes
+        for env in self.envelopes.iter_mut() {
+            env.reset();
+        }
+
+        for lfo in self.lfos.iter_mut() {
+            lfo.reset();
         }
+
+        self.eq.reset();
     }
 
-    pub fn note_on(&mut self, key: usize, vel: f64) {
-        if self.key != key {
-            let freq = KEY_FREQ[key & 0x7f];
-            let patch = self.patch.borrow();
-            for (index, patch_osc) in patch.oscillators.iter().enumerate() {
-                if !patch_osc.is_fixed_freq {
-                    let mut voice_osc = &mut self.oscillators[index];
-                    voice_osc.oscillator.set_base_frequency(freq);
-                }
+    /// Starts a note with the frequency of its key in the tuning of the synth
+    pub fn note_on(&mut self, _key: usize, freq: f64, vel: f64) {
+        let patch = self.patch.borrow();
+        for (index, patch_osc) in patch.oscillators.iter().enumerate() {
+            let voice_osc = &mut self.oscillators[index];
+            if self.key_freq != freq && !patch_osc.is_fixed_freq {
+                voice_osc.oscillator.set_base_frequency(freq);
             }
+            voice_osc.envelope.note_on();
+        }
+        for env in self.envelopes.iter_mut() {
+            env.note_on();
+        }
+        for lfo in self.lfos.iter_mut() {
+            lfo.reset();
         }
+        self.key_freq = freq;
         self.velocity = vel;
     }
 
+    /// Changes the frequency of the note being played, when the tuning changes in real time
+    pub fn set_key_freq(&mut self, freq: f64) {
+        let patch = self.patch.borrow();
+        for (index, patch_osc) in patch.oscillators.iter().enumerate() {
+            if !patch_osc.is_fixed_freq {
+                self.oscillators[index].oscillator.set_base_frequency(freq);
+            }
+        }
+        self.key_freq = freq;
+    }
+
     pub fn note_off(&mut self, _key: usize, _vel: f64) {
-        self.velocity = 0.0;
-        // TODO envelopes !!!
+        let num_osc = self.patch.borrow().oscillators.len();
+        for voice_osc in self.oscillators.iter_mut().take(num_osc) {
+            voice_osc.envelope.note_off();
+        }
+        for env in self.envelopes.iter_mut() {
+            env.note_off();
+        }
+    }
+
+    /// A voice is active until all the envelopes of its oscillators have finished the release
+    pub fn is_active(&self) -> bool {
+        self.oscillators.iter().any(|voice_osc| voice_osc.envelope.is_active())
     }
 
-    pub fn process(&mut self) -> (f64, f64) {
+    /// Generates a sample, with the values of the global LFOs of the synth
+    pub fn process(&mut self, global_lfos: &[f64]) -> (f64, f64) {
         let mut osc_signals = [0.0f64; MAX_OSCILLATORS];
         let mut osc_amp_mod = [1.0f64; MAX_OSCILLATORS];
+        let mut osc_ring_mod = [1.0f64; MAX_OSCILLATORS];
         let mut osc_freq_mod = [0.0f64; MAX_OSCILLATORS];
+        let mut osc_pos_mod = [0.0f64; MAX_OSCILLATORS];
+        let mut osc_pw_mod = [0.0f64; MAX_OSCILLATORS];
 
         let num_osc = self.oscillators.len();
 
         let patch = &self.patch.borrow();
+        let fm_mode = patch.get_fm_mode();
+
+        // Calculate envelopes' modulation
+
+        let mut osc_env_amp = [1.0f64; MAX_OSCILLATORS];
+        let mut osc_pitch_mod = [0.0f64; MAX_OSCILLATORS];
+        let mut filt_cutoff_mod = [0.0f64; MAX_FILTERS];
+        let mut filt_lfo_mod = [0.0f64; MAX_FILTERS];
+
+        for (env, patch_env) in self.envelopes.iter_mut().zip(patch.envelopes.iter()) {
+            let value = env.process();
+            let sends = &patch_env.mod_sends;
+            for (index, depth) in sends.amp.iter() {
+                if let Some(dst) = osc_env_amp.get_mut(*index) {
+                    *dst *= 1.0 - depth + depth * value;
+                }
+            }
+            for (index, depth) in sends.pitch.iter() {
+                if let Some(dst) = osc_pitch_mod.get_mut(*index) {
+                    *dst += depth * value;
+                }
+            }
+            for (index, depth) in sends.position.iter() {
+                if let Some(dst) = osc_pos_mod.get_mut(*index) {
+                    *dst += depth * value;
+                }
+            }
+            for (index, depth) in sends.pulse_width.iter() {
+                if let Some(dst) = osc_pw_mod.get_mut(*index) {
+                    *dst += depth * value;
+                }
+            }
+            for (index, depth) in sends.cutoff.iter() {
+                if let Some(dst) = filt_cutoff_mod.get_mut(*index) {
+                    *dst += depth * value;
+                }
+            }
+        }
+
+        // Calculate LFOs' modulation, bipolar except for the amplitude
+
+        for (i, (lfo, patch_lfo)) in self.lfos.iter_mut().zip(patch.lfos.iter()).enumerate() {
+            let value = if patch_lfo.is_global { global_lfos[i] } else { lfo.process() };
+            let sends = &patch_lfo.mod_sends;
+            for (index, depth) in sends.amp.iter() {
+                if let Some(dst) = osc_env_amp.get_mut(*index) {
+                    *dst *= 1.0 - depth * (1.0 - value) / 2.0;
+                }
+            }
+            for (index, depth) in sends.pitch.iter() {
+                if let Some(dst) = osc_pitch_mod.get_mut(*index) {
+                    *dst += depth * value;
+                }
+            }
+            for (index, depth) in sends.position.iter() {
+                if let Some(dst) = osc_pos_mod.get_mut(*index) {
+                    *dst += depth * value;
+                }
+            }
+            for (index, depth) in sends.pulse_width.iter() {
+                if let Some(dst) = osc_pw_mod.get_mut(*index) {
+                    *dst += depth * value;
+                }
+            }
+            for (index, depth) in sends.cutoff.iter() {
+                if let Some(dst) = filt_lfo_mod.get_mut(*index) {
+                    *dst += depth * value;
+                }
+            }
+        }
+
+        for (i, (voice_filter, patch_filter)) in self.filters.iter_mut().zip(patch.filters.iter()).enumerate() {
+            let octaves = patch_filter.env_amount * filt_cutoff_mod[i] + filt_lfo_mod[i]
+                + patch_filter.velocity * self.velocity;
+            let cutoff = filter::key_track(voice_filter.cutoff.process(), self.key_freq, patch_filter.key_tracking);
+            voice_filter.filter.set_cutoff(cutoff * (2_f64).powf(octaves));
+        }
 
         // Calculate oscillators' signals and send AM and FM modulation
 
         for i in 0..patch.oscillators.len() {
+            // Oscillators before this one have already wrapped in this sample, the rest in the previous one
+            let sync_mode = patch.oscillators[i].get_sync_mode();
+            let sync = match patch.oscillators[i].sync_source {
+                Some(src_index) if src_index != i && src_index < num_osc => {
+                    self.oscillators[src_index].oscillator.has_wrapped()
+                },
+                _ => false
+            };
+
             let voice_osc = &mut self.oscillators[i];
             let ref mut osc = voice_osc.oscillator;
-            let sig = osc.process();
+            if sync {
+                osc.sync(sync_mode);
+            }
+            osc.set_amplitude(voice_osc.amplitude.process());
+            let detune = voice_osc.detune.process();
+            if osc.get_detune() != detune {
+                osc.set_detune(detune);
+            }
+            osc.set_pitch_modulation(osc_pitch_mod[i]);
+            let sig = osc.process() * voice_osc.envelope.process() * osc_env_amp[i];
             osc_signals[i] = sig;
 
             if i < patch.oscillators.len() {
                 let patch_osc = &patch.oscillators[i];
                 for (index, level) in patch_osc.amp_mod.iter() {
-                    osc_amp_mod[index.clone()] += sig * level;
+                    if let Some(dst) = osc_amp_mod.get_mut(*index) {
+                        *dst += sig * level;
+                    }
                 }
 
-                let fm = sig * MOD_INDEX * osc.get_base_frequency();
+                // Unlike AM, the carrier is removed when the level is 1
+                for (index, level) in patch_osc.ring_mod.iter() {
+                    if let Some(dst) = osc_ring_mod.get_mut(*index) {
+                        *dst *= 1.0 - level + sig * level;
+                    }
+                }
+
+                let fm = match fm_mode {
+                    FmMode::Frequency => sig * MOD_INDEX * osc.get_base_frequency(),
+                    FmMode::Phase => sig * MOD_INDEX,
+                };
                 for (index, level) in patch_osc.freq_mod.iter() {
-                    osc_freq_mod[index.clone()] += fm * level;
+                    if let Some(dst) = osc_freq_mod.get_mut(*index) {
+                        *dst += fm * level;
+                    }
+                }
+
+                for (index, level) in patch_osc.pos_mod.iter() {
+                    if let Some(dst) = osc_pos_mod.get_mut(*index) {
+                        *dst += sig * level;
+                    }
+                }
+
+                for (index, level) in patch_osc.pw_mod.iter() {
+                    if let Some(dst) = osc_pw_mod.get_mut(*index) {
+                        *dst += sig * level;
+                    }
                 }
             }
         }
@@ -181,17 +570,61 @@ impl Voice {
         for i in 0..num_osc {
             let voice_osc = &mut self.oscillators[i];
             let ref mut osc = voice_osc.oscillator;
-            osc.set_amplitude_modulation(osc_amp_mod[i]);
-            osc.set_freq_modulation(osc_freq_mod[i]);
+            osc.set_amplitude_modulation(osc_amp_mod[i] * osc_ring_mod[i]);
+            match fm_mode {
+                FmMode::Frequency => {
+                    osc.set_freq_modulation(osc_freq_mod[i]);
+                    osc.set_phase_modulation(0.0);
+                },
+                FmMode::Phase => {
+                    osc.set_freq_modulation(0.0);
+                    osc.set_phase_modulation(osc_freq_mod[i]);
+                },
+            }
+            osc.set_position_modulation(osc_pos_mod[i]);
+            osc.set_pulse_width_modulation(osc_pw_mod[i]);
 
             if i < patch.oscillators.len() {
-                let patch_osc = &patch.oscillators[i];
-                if patch_osc.level > 0.0 {
+                let level = voice_osc.level.process();
+                voice_osc.panning.set_value(voice_osc.pan.process());
+                if level > 0.0 {
                     let (osc_left, osc_right) = voice_osc.panning.process(osc_signals[i]);
-                    left += osc_left * patch_osc.level;
-                    right += osc_right * patch_osc.level;
+                    left += osc_left * level;
+                    right += osc_right * level;
+                }
+            }
+        }
+
+        // Filter the signals sent by the oscillators and by the previous filters, and mix them
+
+        let mut filt_inputs = [0.0f64; MAX_FILTERS];
+        for (i, patch_osc) in patch.oscillators.iter().enumerate().take(num_osc) {
+            for (index, level) in patch_osc.filt_send.iter() {
+                if let Some(dst) = filt_inputs.get_mut(*index) {
+                    *dst += osc_signals[i] * level;
+                }
+            }
+        }
+
+        for (i, (voice_filter, patch_filter)) in self.filters.iter_mut().zip(patch.filters.iter()).enumerate() {
+            let sig = voice_filter.filter.process(filt_inputs[i]);
+
+            // Sends to the same or a previous filter would need a delay, so they are ignored
+            for (index, level) in patch_filter.filt_send.iter() {
+                if *index > i {
+                    if let Some(dst) = filt_inputs.get_mut(*index) {
+                        *dst += sig * level;
+                    }
                 }
             }
+
+            let level = voice_filter.level.process();
+            voice_filter.panning.set_value(voice_filter.pan.process());
+            if level > 0.0 {
+                let (filt_left, filt_right) = voice_filter.panning.process(sig);
+                left += filt_left * level;
+                right += filt_right * level;
+            }
         }
 
         // Normalize output
@@ -199,6 +632,6 @@ impl Voice {
         let inv_count = 1.0 / num_osc as f64;
         let left = left * inv_count * self.velocity;
         let right = right * inv_count * self.velocity;
-        (left, right)
+        self.eq.process_stereo(left, right)
     }
 }
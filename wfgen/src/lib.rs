@@ -1,5 +1,8 @@
 pub mod sin;
 pub mod saw;
+pub mod square;
+pub mod triangle;
+pub mod pulse;
 
 use std::fs::File;
 use std::path::PathBuf;
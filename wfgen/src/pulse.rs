@@ -0,0 +1,37 @@
+pub struct PulseGen {
+    count: usize,
+    phase: f64,
+    step: f64,
+    width: f64,
+}
+
+impl PulseGen {
+    /// Pulse with a width in (0, 1) of the cycle
+    pub fn new(size: usize, width: f64) -> PulseGen {
+        PulseGen {
+            count: size,
+            phase: 0.0,
+            step: 1.0 / (size as f64),
+            width: width,
+        }
+    }
+}
+
+impl Iterator for PulseGen {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        }
+        else {
+            // The edges take the middle value
+            let value = if self.phase == 0.0 || self.phase == self.width { 0.0 }
+                        else if self.phase < self.width { 1.0 }
+                        else { -1.0 };
+            self.phase += self.step;
+            self.count -= 1;
+            Some(value)
+        }
+    }
+}
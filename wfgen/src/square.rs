@@ -0,0 +1,34 @@
+pub struct SquareGen {
+    count: usize,
+    phase: f64,
+    step: f64,
+}
+
+impl SquareGen {
+    pub fn new(size: usize) -> SquareGen {
+        SquareGen {
+            count: size,
+            phase: 0.0,
+            step: 1.0 / (size as f64),
+        }
+    }
+}
+
+impl Iterator for SquareGen {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        }
+        else {
+            // The edges take the middle value
+            let value = if self.phase == 0.0 || self.phase == 0.5 { 0.0 }
+                        else if self.phase < 0.5 { 1.0 }
+                        else { -1.0 };
+            self.phase += self.step;
+            self.count -= 1;
+            Some(value)
+        }
+    }
+}
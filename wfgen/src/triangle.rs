@@ -0,0 +1,33 @@
+pub struct TriangleGen {
+    count: usize,
+    phase: f64,
+    step: f64,
+}
+
+impl TriangleGen {
+    pub fn new(size: usize) -> TriangleGen {
+        TriangleGen {
+            count: size,
+            phase: 0.0,
+            step: 1.0 / (size as f64),
+        }
+    }
+}
+
+impl Iterator for TriangleGen {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        }
+        else {
+            let value = if self.phase < 0.25 { 4.0 * self.phase }
+                        else if self.phase < 0.75 { 2.0 - 4.0 * self.phase }
+                        else { 4.0 * self.phase - 4.0 };
+            self.phase += self.step;
+            self.count -= 1;
+            Some(value)
+        }
+    }
+}